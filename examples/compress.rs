@@ -17,10 +17,10 @@ fn main() {
 
     //'decode' wav stream bit-by-bit
     //Note:in this case we are just reading PCM info
-    while let Some(samples_read) = wav_reader.decode(&mut samples) {
+    while let Some(samples_read) = wav_reader.decode(&mut samples).unwrap() {
         //encode wav data bit-by-bit
         //memory is allocated as needed
-        adhoc.encode(&samples[0..samples_read]);
+        adhoc.encode(&samples[0..samples_read]).unwrap();
     }
 
     //write compressed audio back to disk
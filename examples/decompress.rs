@@ -16,10 +16,10 @@ fn main() {
     let mut wav_writer = WavCodec::new(adhoc.info());
 
     //decode adhoc stream a chunk of samples at a time
-    while let Some(samples_read) = adhoc.decode(&mut samples) {
+    while let Some(samples_read) = adhoc.decode(&mut samples).unwrap() {
         //encode wav data bit-by-bit
         //memory is allocated as needed
-        wav_writer.encode(&samples[0..samples_read]);
+        wav_writer.encode(&samples[0..samples_read]).unwrap();
     }
 
     //write compressed audio back to disk
@@ -0,0 +1,29 @@
+#![no_main]
+
+use adhoc_audio::{AdhocCodec, AdhocReader, SeekFrom, Streamable};
+use libfuzzer_sys::fuzz_target;
+use std::io::Cursor;
+
+/// keeps the fuzzer from spending its time on huge allocations
+const LOAD_LIMIT: u64 = 1 << 24;
+
+fuzz_target!(|data: &[u8]| {
+    if let Ok(reader) = AdhocReader::new(Cursor::new(data)) {
+        let mut reader = reader.with_buffer_limit(LOAD_LIMIT as usize);
+        let mut pcm = [0.0; 1024];
+        while let Ok(Some(_)) = reader.decode(&mut pcm[..]) {}
+    }
+
+    let mut codec = match AdhocCodec::load_with_limit(Cursor::new(data), LOAD_LIMIT) {
+        Ok(codec) => codec,
+        Err(_) => return,
+    };
+
+    let mut pcm = [0.0; 1024];
+    while let Ok(Some(_)) = codec.decode(&mut pcm[..]) {}
+
+    let mut wide = [0; 1024];
+    if codec.seek(SeekFrom::Start(10)).is_ok() {
+        while let Ok(Some(_)) = codec.decode_i32(&mut wide[..]) {}
+    }
+});
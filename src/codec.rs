@@ -1,9 +1,11 @@
 use super::{
-    collections::{BitStream, CircularStack, NibbleList},
+    collections::{BitReader, BitStream, CircularStack, NibbleList},
+    error::Error,
     math::FixedParabola,
 };
 use serde::{Deserialize, Serialize};
 pub use std::io::{Seek, SeekFrom};
+use std::time::Duration;
 
 /// The first codec in this crate that actually compresses things
 pub mod adhoc;
@@ -22,11 +24,49 @@ where
     i32::from(samp) as f32 * NORMALIZE_FACTOR
 }
 
-fn truncate_sample(samp: f32) -> i16 {
-    (samp.clamp(-1.0, 1.0) * ((i16::MAX) as f32)) as i16
+/// largest sample a `bits_per_sample` stream can have
+fn max_sample(bits_per_sample: u32) -> i32 {
+    ((1i64 << (bits_per_sample - 1)) - 1) as i32
 }
 
-#[derive(Copy, Clone, Default, Serialize, Deserialize)]
+/// maps `samp` from `[-1,1]` onto a `bits_per_sample` integer, samples outside the range get clamped
+fn quantize_sample(samp: f32, bits_per_sample: u32) -> i32 {
+    (samp.clamp(-1.0, 1.0) * (max_sample(bits_per_sample) as f32)) as i32
+}
+
+/// `normalize_sample(..)` for any bit depth, for 16-bit samples the two are identical
+fn dequantize_sample(samp: i32, bits_per_sample: u32) -> f32 {
+    samp as f32 * (1.0 / max_sample(bits_per_sample) as f32)
+}
+
+/// moves an integer sample from one bit depth to another (MSB-aligned)
+fn rescale_sample(samp: i32, from_bits: u32, to_bits: u32) -> i32 {
+    if to_bits >= from_bits {
+        ((samp as i64) << (to_bits - from_bits)) as i32
+    } else {
+        samp >> (from_bits - to_bits)
+    }
+}
+
+/// # Description
+/// resolves `dt` (in milliseconds) to a position in samples per channel
+/// ## Parameters
+/// - `position` where the stream currently is, in samples per channel
+/// - `len` length of the stream, in samples per channel
+/// ## Comments
+/// - the result is clamped to `0..=len`, seeking to `len` leaves nothing to decode
+/// - every codec in this crate seeks through this so they all behave the same
+fn resolve_seek(dt: SeekFrom, position: u64, len: u64, sample_rate: u32) -> u64 {
+    let millis_to_samples = |millis: i128| millis * sample_rate as i128 / 1000;
+    let target = match dt {
+        SeekFrom::Start(millis) => millis_to_samples(millis as i128),
+        SeekFrom::Current(millis) => position as i128 + millis_to_samples(millis as i128),
+        SeekFrom::End(millis) => len as i128 + millis_to_samples(millis as i128),
+    };
+    target.clamp(0, len as i128) as u64
+}
+
+#[derive(Copy, Clone, Serialize, Deserialize)]
 /// A POD that contains basic information about an audio signal
 /// ## Comments
 /// If you instantiate a codec make sure this is set before you encode(..)/decode(..)
@@ -35,15 +75,64 @@ pub struct StreamInfo {
     sample_rate: u32,
     /// number of channels in your stream
     channels: u32,
+    /// bits per (integer) sample, `16` unless stated otherwise
+    bits_per_sample: u32,
+    /// speaker layout, see `with_channel_mask(..)`
+    #[serde(skip)]
+    channel_mask: u32,
 }
+
+impl Default for StreamInfo {
+    fn default() -> Self {
+        Self::new(0, 0)
+    }
+}
+
 impl StreamInfo {
+    /// smallest bit depth codecs in this crate can handle
+    pub const MIN_BITS_PER_SAMPLE: u32 = 8;
+    /// largest bit depth codecs in this crate can handle
+    pub const MAX_BITS_PER_SAMPLE: u32 = 32;
+
     pub fn new(sample_rate: u32, channels: u32) -> Self {
         Self {
             sample_rate,
             channels,
+            bits_per_sample: 16,
+            channel_mask: 0,
         }
     }
 
+    /// # Description
+    /// sets the bit depth of the stream, typically `16`, `24` or `32`
+    /// ## Comments
+    /// - `bits_per_sample` is clamped to `MIN_BITS_PER_SAMPLE..=MAX_BITS_PER_SAMPLE`
+    pub fn with_bits_per_sample(mut self, bits_per_sample: u32) -> Self {
+        self.bits_per_sample =
+            bits_per_sample.clamp(Self::MIN_BITS_PER_SAMPLE, Self::MAX_BITS_PER_SAMPLE);
+        self
+    }
+
+    pub fn bits_per_sample(&self) -> u32 {
+        self.bits_per_sample
+    }
+
+    /// # Description
+    /// sets which speaker each channel is meant for
+    /// ## Comments
+    /// - uses the `dwChannelMask` bits of `WAVE_FORMAT_EXTENSIBLE`: bit 0 is front left, bit 1 front right,
+    ///   bit 2 front center, bit 3 LFE and so on, channels are assigned to set bits in increasing order
+    /// - `0` (the default) means the layout is unknown
+    /// - `WavCodec` reads and writes it, `.adhoc` files don't store it
+    pub fn with_channel_mask(mut self, channel_mask: u32) -> Self {
+        self.channel_mask = channel_mask;
+        self
+    }
+
+    pub fn channel_mask(&self) -> u32 {
+        self.channel_mask
+    }
+
     pub fn channels(&self) -> usize {
         self.channels as usize
     }
@@ -75,28 +164,60 @@ pub trait Streamable {
 
     /// # Description
     /// encodes `samples` and returns number of samples encoded
-    fn encode(&mut self, samples: &[f32]) -> Option<usize>;
+    fn encode(&mut self, samples: &[f32]) -> Result<usize, Error>;
 
     /// # Description
     /// Decodes part of the stream and writes it out into the `samples` buffer
     /// ## Returns
-    /// Number of samples decoded
-    fn decode(&mut self, samples: &mut [f32]) -> Option<usize>;
+    /// Number of samples decoded, `None` once the end of the stream has been reached
+    fn decode(&mut self, samples: &mut [f32]) -> Result<Option<usize>, Error>;
 
     /// # Description
     /// Seeks to a certain spot in the stream
     /// ## Parameters
     /// - `dt` is change in time in milliseconds
     /// ## Comments
+    /// - `SeekFrom::Start`, `SeekFrom::Current` and `SeekFrom::End` behave the same for every codec,
+    ///   `Current` is relative to the decode position and the result is clamped to the bounds of the stream
     /// - Notes about `AdhocCodec`:
-    ///     - Currently only `SeekFrom::Start` is implemented
     ///     - Intented to be used **ONLY AFTER** you've completely finished encoding \
-    ///     audio, or you have just loaded the codec for the first time
+    ///       audio, or you have just loaded the codec for the first time
     ///     - with `AdhocCodec` you can't just seek to a random spot and start encoding
     ///     - If you want to reuse the memory allocated call `AdhocCodec::init()` or `dt=SeekFrom::Start(0)` \
-    ///     before encoding. This will reuse the stream memory allocated.
+    ///       before encoding. This will reuse the stream memory allocated.
     /// - Notes about `WavCodec`:
-    ///     - `SeekFrom::Start`, `SeekFrom::Current` and `SeekFrom::End` is implemented
     ///     - there no restrictions on how one should call this after encode/decode
-    fn seek(&mut self, dt: SeekFrom);
+    /// - milliseconds don't always land on a sample, use `seek_to_sample(..)` when that matters
+    fn seek(&mut self, dt: SeekFrom) -> Result<(), Error>;
+
+    /// # Description
+    /// Seeks to an exact sample
+    /// ## Parameters
+    /// - `sample` is counted per channel, so in a stereo stream a left/right pair is a single sample
+    /// ## Comments
+    /// - `sample` is clamped to `duration_in_samples()`
+    /// - the same notes as `seek(..)` apply
+    fn seek_to_sample(&mut self, sample: u64) -> Result<(), Error>;
+
+    /// # Description
+    /// returns the sample (per channel) the next `decode(..)` starts at
+    fn position_in_samples(&self) -> u64;
+
+    /// # Description
+    /// returns the length of the stream in samples per channel
+    fn duration_in_samples(&self) -> u64;
+
+    /// # Description
+    /// returns the length of the stream
+    /// ## Comments
+    /// - computed from `duration_in_samples()` and the sample rate, so there's no rounding drift
+    fn duration(&self) -> Duration {
+        let sample_rate = self.info().sample_rate as u64;
+        if sample_rate == 0 {
+            return Duration::ZERO;
+        }
+        let samples = self.duration_in_samples();
+        let nanos = (samples % sample_rate) * 1_000_000_000 / sample_rate;
+        Duration::from_secs(samples / sample_rate) + Duration::from_nanos(nanos)
+    }
 }
@@ -1,14 +1,19 @@
-use crate::math::PseudoRandom;
+use crate::{
+    error::Error,
+    math::{self, PseudoRandom},
+};
 
 use super::*;
 use std::{
+    borrow::Cow,
     collections::VecDeque,
-    io::{Read, Write},
-    ops::{Deref, DerefMut},
+    io::{Cursor, Read, Write},
     mem,
+    ops::{Deref, DerefMut, Range},
+    sync::Arc,
 };
 
-use bincode;
+use bincode::{self, Options};
 
 mod audio_stream;
 pub use audio_stream::*;
@@ -16,8 +21,43 @@ pub use audio_stream::*;
 mod frame;
 pub use frame::*;
 
+mod stereo;
+pub use stereo::*;
+
+mod file_header;
+pub use file_header::*;
+
+mod legacy;
+
+mod reader;
+pub use reader::*;
+
+mod writer;
+pub use writer::*;
+
+mod asset;
+pub use asset::*;
+
+mod rate;
+pub use rate::*;
+
 pub const MAX_DECODE_ATTEMPTS: usize = 10;
 
+/// default number of samples (per channel) in each segment `encode_parallel(..)` hands to a thread
+pub const DEFAULT_SEGMENT_SIZE: usize = 1 << 16;
+
+/// default number of samples (per channel) in a frame, see `AdhocCodec::with_frame_size(..)`
+pub const DEFAULT_FRAME_SIZE: usize = 1024;
+
+/// largest depth `AdhocCodec::with_partition_depth(..)` accepts
+pub const MAX_PARTITION_DEPTH: u32 = 6;
+
+/// frames are never split into sub-blocks smaller than this (samples per channel)
+const MIN_PARTITION_SIZE: usize = 32;
+
+/// default number of bytes `AdhocCodec::load(..)` is allowed to deserialize
+pub const DEFAULT_LOAD_LIMIT: u64 = 1 << 30;
+
 #[derive(Copy, Clone)]
 pub enum CodecState {
     Init,
@@ -40,8 +80,11 @@ pub struct AdhocCodec {
     /// contains 'frames' of audio samples in rice-encoded format
     stream: AudioStream,
 
-    /// a temporary buffer used in encoding step for seperating interleaved stream
-    deinterleaved_channel: Vec<f32>,
+    /// temporary buffers used in encoding step for seperating and quantizing the interleaved stream
+    quantized_channel_list: Vec<Vec<i32>>,
+
+    /// temporary buffers used in encoding step to hold decorrelated channels
+    coded_channel_list: Vec<Vec<i32>>,
 
     /// internally this value is from 0-7
     compression_level: u32,
@@ -54,6 +97,33 @@ pub struct AdhocCodec {
 
     /// random number sequence
     seq: PseudoRandom,
+
+    /// number of samples (per channel) decoded so far, where `SeekFrom::Current(..)` is relative to
+    position: u64,
+
+    /// number of samples (per channel) in the stream, kept up to date so it never has to be recounted
+    sample_count: u64,
+
+    /// number of samples (per channel) in each independently encoded segment, see `encode_parallel(..)`
+    segment_size: usize,
+
+    /// number of samples (per channel) in a frame, input is buffered until a frame is complete
+    frame_size: usize,
+
+    /// how many times a frame may be halved when looking for a cheaper partition, `0` turns the search off
+    partition_depth: u32,
+
+    /// picks the quantization shift of every frame and keeps track of the bits spent
+    rate_control: RateControl,
+
+    /// has the final say on the quantization shift of every frame, see `with_quantization_callback(..)`
+    quantization_callback: Option<Arc<QuantizationCallback>>,
+}
+
+impl Default for AdhocCodec {
+    fn default() -> Self {
+        Self::new()
+    }
 }
 
 impl AdhocCodec {
@@ -61,12 +131,20 @@ impl AdhocCodec {
         Self {
             stream: AudioStream::new(),
             frame_header_list: FrameHeaders::new(),
-            deinterleaved_channel: Vec::new(),
+            quantized_channel_list: Vec::new(),
+            coded_channel_list: Vec::new(),
             channel_state_list: Vec::new(),
             compression_level: 0,
             scale: 1.0,
             inv_scale: 1.0,
             seq: PseudoRandom::new(314),
+            position: 0,
+            sample_count: 0,
+            segment_size: DEFAULT_SEGMENT_SIZE,
+            frame_size: DEFAULT_FRAME_SIZE,
+            partition_depth: 0,
+            rate_control: RateControl::new(),
+            quantization_callback: None,
         }
     }
 
@@ -78,8 +156,6 @@ impl AdhocCodec {
         self
     }
 
-
-
     pub fn set_info(&mut self, info: StreamInfo) {
         let num_channels = info.channels as usize;
         self.channel_state_list
@@ -87,43 +163,44 @@ impl AdhocCodec {
         self.stream.set_info(Some(info));
     }
 
+    /// # Description
+    /// returns the info set with `with_info(..)`/`set_info(..)`
+    /// ## Comments
+    /// - `StreamInfo::default()` until it's been set, see `try_info()`
     pub fn info(&self) -> StreamInfo {
-        self.stream
-            .info()
-            .expect("info not initalized, call set_info/with_info before decoding")
+        self.stream.info().unwrap_or_default()
     }
 
+    /// # Description
+    /// same as `info()` but fails if info hasn't been set
+    /// ## Comments
+    /// - fails with `Error::UnsupportedFormat` if the stream has no channels, there's nothing to code then
+    pub fn try_info(&self) -> Result<StreamInfo, Error> {
+        self.compressed().try_info()
+    }
 
-    /// # Description 
-    /// returns a tight upper-bound estimate on the number of bits needed to write this file 
-    fn filesize_upperbound(&self)->u64{
-        /*
-            the final filesize is roughly equal to:
-                FrameHeaderList_bits+
-                AudioStream_bits + 
-                compression_level_bits 
-        */
-        let audio_stream_header =         
-            //number of bits AudioStream need to store info 
-            mem::size_of::<StreamInfo>() as u64 * 8; 
-
-        let bitstream = self.stream.capacity_upperbound() as u64+ 
-          //streams internal cursor + capacity 
-          128*2 ;
-
-
-        let audio_stream_bits = audio_stream_header + bitstream;
-        let compression_level_bits = 32;
-        let frame_header_bits =  self.frame_header_list.calculate_weight_upperbound();
+    /// # Description
+    /// the parts of the codec decoding reads but never changes
+    fn compressed(&self) -> CompressedStream<'_> {
+        CompressedStream {
+            stream: &self.stream,
+            frame_header_list: &self.frame_header_list,
+            compression_level: self.compression_level,
+            scale: self.scale,
+            sample_count: self.sample_count,
+        }
+    }
 
-        //estimated upperbound is..
-        audio_stream_bits + 
-        compression_level_bits + 
-        frame_header_bits
+    /// # Description
+    /// returns a tight upper-bound estimate on the number of bits needed to write this file
+    fn filesize_upperbound(&self) -> u64 {
+        self.compressed().filesize_upperbound()
     }
 
     /// # Description
     /// use this to specify compression `level` where level is 0-10, where 0 is little to no loss in quality while 10 is very,very lossy
+    /// ## Comments
+    /// - level 0 is only bit-exact through `encode_i16(..)`/`decode_i16(..)`, the `f32` path truncates samples
     pub fn with_compression_level(mut self, mut level: u32) -> Self {
         level = level.clamp(0, 10);
         let scale = (1 << level) as f32;
@@ -133,37 +210,185 @@ impl AdhocCodec {
         self
     }
 
+    /// # Description
+    /// sets the number of samples (per channel) in a frame, `DEFAULT_FRAME_SIZE` unless stated otherwise
+    /// ## Comments
+    /// - `encode(..)` buffers its input and cuts it into frames of exactly this size, so it doesn't
+    ///   matter how the input is chunked, call `flush()` to encode whatever is left over
+    /// - `samples` is clamped to `1..=u16::MAX`, frames store their size in 16 bits
+    /// - bigger frames compress a bit better, smaller frames make seeking cheaper
+    pub fn with_frame_size(mut self, samples: usize) -> Self {
+        self.frame_size = samples.clamp(1, u16::MAX as usize);
+        self
+    }
+
+    /// # Description
+    /// lets the encoder cut frames into smaller sub-blocks when that looks cheaper, off (`0`) unless stated otherwise
+    /// ## Comments
+    /// - every frame is recursively halved up to `depth` times, each half is kept if its estimated size
+    ///   (header included) beats encoding the whole thing, so transients and silence get their own
+    ///   rice exponent and predictor
+    /// - `depth` is clamped to `MAX_PARTITION_DEPTH`, every level costs roughly one more encode of the
+    ///   frame, so higher depths trade encode time for size
+    /// - decoding isn't affected at all, sub-blocks are regular frames
+    pub fn with_partition_depth(mut self, depth: u32) -> Self {
+        self.partition_depth = depth.min(MAX_PARTITION_DEPTH);
+        self
+    }
+
+    /// # Description
+    /// makes the encoder aim for an average bitrate of `kbps` kilobits per second
+    /// ## Comments
+    /// - every frame gets its own quantization shift (stored in its header) on top of the compression level,
+    ///   the rate control picks the smallest shift whose estimated size fits the frame's share of the budget
+    /// - bits that were over/under spent get paid back over the next few frames, see `encode_report()`
+    ///   for what was actually achieved
+    /// - residuals cost at least three bits a sample, targets below that (roughly 132kbps per channel at 44.1kHz)
+    ///   just quantize as much as they can
+    pub fn with_target_bitrate(mut self, kbps: u32) -> Self {
+        self.rate_control = self.rate_control.with_target_kbps(Some(kbps as f64));
+        self
+    }
+
+    /// # Description
+    /// makes the encoder quantize every frame as much as it can while keeping its signal to noise ratio
+    /// at or above `db`
+    /// ## Comments
+    /// - noise is measured against the samples handed to `encode(..)` (after the compression level's
+    ///   quantization) with `math::compute_mse`, see `encode_report()` for the shifts picked and the quality achieved
+    /// - every frame meets the target on its own, so the stream as a whole does too
+    /// - can be combined with `with_target_bitrate(..)`, frames then get the bits the quality target needs
+    ///   even if that blows the budget
+    pub fn with_target_snr(mut self, db: f32) -> Self {
+        self.rate_control = self.rate_control.with_target_snr_db(Some(db as f64));
+        self
+    }
+
+    /// # Description
+    /// lets `callback` pick the quantization shift of every frame, so quality can be varied over time
+    /// ## Comments
+    /// - the shift is stored in the frame's header and undone by the decoder frame by frame, `0` keeps
+    ///   the frame as it is (after the compression level's quantization)
+    /// - the callback sees the frame and the shift the targets set with `with_target_bitrate(..)` and
+    ///   `with_target_snr(..)` would have picked, whatever it returns wins
+    /// - `encode_parallel(..)` calls it from rayon's threads, hence `Send + Sync`
+    /// ## Example
+    /// ```
+    /// use adhoc_audio::{AdhocCodec, StreamInfo, Streamable};
+    ///
+    /// // quantize quiet passages harder than loud ones
+    /// let mut codec = AdhocCodec::new()
+    ///     .with_quantization_callback(|frame| if frame.peak() < 0.1 { 6 } else { 2 })
+    ///     .with_info(StreamInfo::new(44100, 1));
+    /// codec.encode(&[0.05; 4096]).unwrap();
+    /// codec.flush();
+    /// assert_eq!(codec.encode_report().max_quant_shift, 6);
+    /// ```
+    pub fn with_quantization_callback<Callback>(mut self, callback: Callback) -> Self
+    where
+        Callback: Fn(&FrameContext) -> u32 + Send + Sync + 'static,
+    {
+        self.quantization_callback = Some(Arc::new(callback));
+        self
+    }
+
+    /// # Description
+    /// `true` if frames may end up with a quantization shift of their own
+    fn quantizes_frames(&self) -> bool {
+        self.rate_control.is_active() || self.quantization_callback.is_some()
+    }
+
+    /// # Description
+    /// sets the number of samples (per channel) in each segment `encode_parallel(..)` encodes on its own
+    /// ## Comments
+    /// - every segment starts with an init frame, so smaller segments compress a little worse
+    pub fn with_segment_size(mut self, samples: usize) -> Self {
+        self.segment_size = samples.max(1);
+        self
+    }
+
+    /// # Description
+    /// encodes the samples `encode(..)` has buffered, even if they don't make up a whole frame
+    /// ## Comments
+    /// - called by `init()`, `seek(..)` and `save_to(..)`, so you only need this to make buffered
+    ///   samples visible to `decode_range(..)` and friends
+    pub fn flush(&mut self) {
+        // can't fail, samples are never buffered while decoding: `encode(..)` refuses them
+        // and decoding flushes first
+        let _ = self.encode_complete_frames(true);
+    }
+
     /// # Description
     /// re-initalizes state, ususally for switching to decoding
+    /// ## Comments
+    /// - buffered samples are flushed first
     pub fn init(&mut self) {
+        self.flush();
         self.stream.seek_start();
         self.frame_header_list.reset();
-        self.channel_state_list.iter_mut().for_each(|cs| cs.init())
+        self.channel_state_list.iter_mut().for_each(|cs| cs.init());
+        self.position = 0;
     }
 
     /// # Description
-    /// computes number of samples per channel in O(n)
+    /// returns number of samples per channel
     /// ## Comments
-    /// - Computes the count by looking at frame header data
-    /// - if you are only reading data, you should probably cache the results of this function
-    pub fn calculate_sample_count_per_channel(&self)->u64{
-        let frame_header_list = &self.frame_header_list;
-        let num_channels = self.info().channels as u64;
-        let frame_count = self.frame_header_list.len();
-        
-        //this will count all samples in all channels
-        let total_samples_interleaved = (0..frame_count).filter_map(|idx| frame_header_list.get(idx)).map(|frame| frame.size as u64).sum::<u64>();
-        
-        //this returns the number of samples in a single channel
-        let total_samples_in_a_single_channel = total_samples_interleaved/num_channels;
-        total_samples_in_a_single_channel
+    /// - the count is cached, this is the same as `Streamable::duration_in_samples(..)`
+    /// - samples buffered by `encode(..)` are included
+    pub fn calculate_sample_count_per_channel(&self) -> u64 {
+        self.sample_count + self.buffered_len() as u64
+    }
+
+    /// # Description
+    /// how many bits the encoder has spent so far and the bitrate that works out to
+    /// ## Comments
+    /// - counts frames encoded since the codec was created (or since encoding started over at the
+    ///   beginning of the stream), samples still waiting in the buffer aren't included
+    /// - bits are counted as frames are written, header sizes are estimates
+    /// - a codec without stream info reports nothing encoded
+    pub fn encode_report(&self) -> EncodeReport {
+        self.rate_control
+            .report(self.stream.info().unwrap_or_default())
+    }
+
+    /// # Description
+    /// number of samples (per channel) `encode(..)` is holding on to until a frame is complete
+    fn buffered_len(&self) -> usize {
+        self.quantized_channel_list.first().map_or(0, Vec::len)
     }
 
+    /// # Description
+    /// features of the format this stream makes use of, see `FileHeader`
+    fn feature_flags(&self) -> u16 {
+        let mut flags = 0;
+        if self.info().bits_per_sample() != 16 {
+            flags |= FileHeader::FLAG_WIDE_SAMPLES;
+        }
+        let frame_header_list = &self.frame_header_list;
+        for header in (0..frame_header_list.len()).filter_map(|idx| frame_header_list.get(idx)) {
+            if header.predictor.order > 0 {
+                flags |= FileHeader::FLAG_LPC;
+            }
+            if header.channel_mode != ChannelMode::Independent {
+                flags |= FileHeader::FLAG_STEREO_DECORRELATION;
+            }
+            if header.quant_shift > 0 {
+                flags |= FileHeader::FLAG_FRAME_QUANTIZATION;
+            }
+        }
+        flags
+    }
 
-    pub fn save_to<Resource>(&self, res: Resource) -> Option<()>
+    /// # Description
+    /// writes a `FileHeader` followed by the stream
+    /// ## Comments
+    /// - buffered samples are flushed first
+    pub fn save_to<Resource>(&mut self, mut res: Resource) -> Result<(), Error>
     where
         Resource: Write,
     {
+        self.flush();
+
         #[derive(Serialize)]
         struct SlimAdhocCodecRef<'a> {
             compression_level: u32,
@@ -177,268 +402,1495 @@ impl AdhocCodec {
             frame_header_list: &self.frame_header_list,
         };
 
-        bincode::serialize_into(res, &slim).ok()
+        self.try_info()?;
+        FileHeader::new(self.feature_flags()).write_to(&mut res)?;
+        bincode::serialize_into(res, &slim)?;
+        Ok(())
     }
 
-    pub fn load<Resource>(res: Resource) -> Option<Self>
+    /// # Description
+    /// loads a stream written by `save_to(..)`
+    /// ## Comments
+    /// - files written before `FileHeader` existed (version `0`) are migrated on the fly
+    /// - files from newer versions of the format are rejected with `Error::UnsupportedFormat`
+    /// - at most `DEFAULT_LOAD_LIMIT` bytes get deserialized, see `load_with_limit(..)`
+    pub fn load<Resource>(res: Resource) -> Result<Self, Error>
     where
         Resource: Read,
     {
-        #[derive(Deserialize)]
-        struct SlimAdhocCodec {
-            compression_level: u32,
-            frame_header_list: FrameHeaders,
-            stream: AudioStream,
-        }
-
-        bincode::deserialize_from::<_, SlimAdhocCodec>(res)
-            .ok()
-            .and_then(|slim| {
-                let scale = (1 << slim.compression_level) as f32;
-                let info = slim.stream.info()?;
-                let mut adhoc_codec = Self {
-                    compression_level: slim.compression_level,
-                    channel_state_list: (0..info.channels)
-                        .map(|_| FrameCodec::new())
-                        .collect::<Vec<_>>(),
-                    stream: slim.stream,
-                    frame_header_list: slim.frame_header_list,
-                    deinterleaved_channel: Vec::new(),
-                    scale,
-                    inv_scale: 1.0 / scale,
-                    seq: PseudoRandom::new(314),
-                };
-                adhoc_codec.init();
-                Some(adhoc_codec)
-            })
+        Self::load_with_limit(res, DEFAULT_LOAD_LIMIT)
     }
 
-    fn encode(&mut self, interleaved_pcm: &[f32]) {
-        let num_channels = self.stream.info().expect("info not set").channels as usize;
-        let num_chunks = interleaved_pcm.len() / num_channels;
-        let inv_scale = self.inv_scale;
-
-        //controls the 'strength'/'influence' of dithering
-        const DITHER_AMPLITUDE: f32 = 0.001;
-
-        //split borrows
-        let channel_list = &mut self.channel_state_list;
-        let stream = &mut self.stream;
-        let block_info = &mut self.frame_header_list;
-        let deinterleaved_channel = &mut self.deinterleaved_channel;
-        let seq = &mut self.seq;
-
-        deinterleaved_channel.resize(num_chunks, 0.0);
-
-        //de-interleave pcm by channel
-        for channel_idx in 0..num_channels {
-            //fill buffer with channel number 'channel_idx'
-            for chunk_idx in 0..num_chunks {
-                deinterleaved_channel[chunk_idx] =
-                    interleaved_pcm[(chunk_idx * num_channels) + channel_idx];
+    /// # Description
+    /// same as `load(..)` but deserializes at most `limit` bytes
+    /// ## Comments
+    /// - use this when `res` comes from an untrusted source, the data is validated before anything is decoded
+    /// - going over the limit fails with `Error::LimitExceeded` instead of allocating
+    pub fn load_with_limit<Resource>(mut res: Resource, limit: u64) -> Result<Self, Error>
+    where
+        Resource: Read,
+    {
+        let header = match Prelude::read_from(&mut res)? {
+            Prelude::Versioned(header) => header,
+            Prelude::Legacy(magic) => {
+                // version 0 files start straight away with bincode data,
+                // if that doesn't work out either this isn't an adhoc file
+                let legacy_res = Cursor::new(magic).chain(res);
+                let (compression_level, frame_header_list, stream) = bincode_options(limit)
+                    .deserialize_from::<_, legacy::LegacyAdhocCodec>(legacy_res)
+                    .map_err(|err| match *err {
+                        bincode::ErrorKind::SizeLimit => Error::LimitExceeded,
+                        _ => Error::BadMagic,
+                    })?
+                    .migrate()?;
+                return Self::from_parts(compression_level, frame_header_list, stream);
             }
+        };
 
-            deinterleaved_channel
-                .iter_mut()
-                .zip(seq.triangle())
-                .for_each(|(channel_samples, noise)| {
-                    //dither signal s, before quantization
-                    *channel_samples += noise * DITHER_AMPLITUDE * (1.0 - inv_scale);
-                    //scale sound down
-                    *channel_samples *= inv_scale;
-                });
-
-            // println!("{:?}", deinterleaved_channel);
-            channel_list[channel_idx].encode_frame(stream, block_info, &deinterleaved_channel);
+        if header.is_streamed() {
+            return Self::load_packets(res, limit, header.version);
         }
-    }
-
-    fn decode(&mut self, pcm_out: &mut [f32]) -> usize {
-        let num_channels = self.stream.info().expect("info not set").channels as usize;
-        let channel_list = &mut self.channel_state_list;
-        let stream = &mut self.stream;
-        let block_info = &mut self.frame_header_list;
 
-        let is_buffers_empty = |channel_list: &mut Vec<FrameCodec>| {
-            channel_list
-                .iter()
-                .all(|cs| cs.buffered_channel().is_empty())
-        };
+        // same layout `save_to(..)` writes, read a field at a time since the headers depend on the version
+        let compression_level = bincode_options(limit).deserialize_from::<_, u32>(&mut res)?;
+        let frame_header_list = read_frame_headers(&mut res, limit, header.version)?;
+        let stream = bincode_options(limit).deserialize_from::<_, AudioStream>(&mut res)?;
+        Self::from_parts(compression_level, frame_header_list, stream)
+    }
 
-        let mut buffer_audio = |channel_list: &mut Vec<FrameCodec>| {
-            let mut attempts = 0;
-            //decode and load buffers if empty
-            while attempts < MAX_DECODE_ATTEMPTS && is_buffers_empty(channel_list) {
-                //decode more data
-                for channel_idx in 0..num_channels {
-                    channel_list[channel_idx].decode_frame(stream, block_info);
+    /// # Description
+    /// loads the `StreamPacket`s `AdhocWriter` writes (everything after the `FileHeader`)
+    /// ## Comments
+    /// - packets are stitched back together, the result is the same as if the whole stream was encoded in memory
+    fn load_packets<Resource>(res: Resource, limit: u64, version: u16) -> Result<Self, Error>
+    where
+        Resource: Read,
+    {
+        let mut res = res.take(limit);
+        let mut load = || -> Result<Self, Error> {
+            let (compression_level, info) =
+                bincode_options(limit).deserialize_from::<_, (u32, StreamInfo)>(&mut res)?;
+
+            let mut frame_header_list = FrameHeaders::new();
+            let mut chunk_list: Vec<u128> = Vec::new();
+            while let Some(packet) = StreamPacket::read_from(&mut res, limit, version)? {
+                packet.frame_header_list.validate(info)?;
+                if packet.first_chunk > chunk_list.len() as u64 {
+                    return Err(Error::CorruptHeader(
+                        "packet doesn't continue the stream".to_string(),
+                    ));
                 }
-                attempts += 1;
-            }
-            attempts
-        };
-
-        // number of samples that can be written has to number a multiple of `num_channels`
-        let legal_output_len = (pcm_out.len() / num_channels) * num_channels;
-        let mut pcm_out_cursor = 0;
 
-        while pcm_out_cursor < legal_output_len {
-            if buffer_audio(channel_list) >= MAX_DECODE_ATTEMPTS {
-                break;
-            }
+                // the first chunk of a packet completes the last chunk of the previous packet
+                chunk_list.truncate(packet.first_chunk as usize);
+                chunk_list.extend_from_slice(&packet.chunk_list);
 
-            let mut channel_idx = 0;
-            while (pcm_out_cursor < legal_output_len) && (channel_idx < num_channels) {
-                let decoded_sample = channel_list[channel_idx]
-                    .buffered_channel_mut()
-                    .pop_front()
-                    .unwrap_or_default();
-                pcm_out[pcm_out_cursor] = decoded_sample;
-                pcm_out_cursor += 1;
-                channel_idx += 1;
+                let packet_headers = &packet.frame_header_list;
+                (0..packet_headers.len())
+                    .filter_map(|idx| packet_headers.get(idx))
+                    .for_each(|header| frame_header_list.push(header));
             }
-        }
+            frame_header_list.reset();
+
+            let mut bitstream = BitStream::new();
+            chunk_list
+                .into_iter()
+                .for_each(|chunk| bitstream.push_chunk(chunk));
+            let stream = AudioStream::from_bitstream(Some(info), bitstream);
+            Self::from_parts(compression_level, frame_header_list, stream)
+        };
 
-        //scale up signal
-        for s in pcm_out {
-            *s *= self.scale
+        match load() {
+            // running out of data right at the limit means the limit was hit
+            Err(Error::Io(_)) if res.limit() == 0 => Err(Error::LimitExceeded),
+            result => result,
         }
-
-        pcm_out_cursor
-    }
-}
-
-impl Streamable for AdhocCodec {
-    
-    fn info(&self) -> StreamInfo {
-        self.info()
-    }
-    
-    fn filesize_upperbound(&self) ->u64 {
-        self.filesize_upperbound()
     }
 
-    fn encode(&mut self, samples: &[f32]) -> Option<usize> {
-        self.encode(samples);
+    fn from_parts(
+        compression_level: u32,
+        frame_header_list: FrameHeaders,
+        stream: AudioStream,
+    ) -> Result<Self, Error> {
+        let info = stream
+            .info()
+            .ok_or_else(|| Error::CorruptHeader("stream info missing".to_string()))?;
 
-        Some(samples.len())
+        let bits_range = StreamInfo::MIN_BITS_PER_SAMPLE..=StreamInfo::MAX_BITS_PER_SAMPLE;
+        if info.channels == 0 || info.channels > u16::MAX as u32 {
+            return Err(Error::CorruptHeader(format!("{} channels", info.channels)));
+        }
+        if !bits_range.contains(&info.bits_per_sample()) {
+            return Err(Error::UnsupportedFormat(format!(
+                "{} bits per sample",
+                info.bits_per_sample()
+            )));
+        }
+        if compression_level > 10 {
+            return Err(Error::CorruptHeader(format!(
+                "compression level {}",
+                compression_level
+            )));
+        }
+        frame_header_list.validate(info)?;
+
+        let scale = (1 << compression_level) as f32;
+        let sample_count =
+            frame_header_list.sample_count(frame_header_list.len()) / info.channels as u64;
+        let mut adhoc_codec = Self {
+            compression_level,
+            channel_state_list: (0..info.channels)
+                .map(|_| FrameCodec::new())
+                .collect::<Vec<_>>(),
+            stream,
+            frame_header_list,
+            quantized_channel_list: Vec::new(),
+            coded_channel_list: Vec::new(),
+            scale,
+            inv_scale: 1.0 / scale,
+            seq: PseudoRandom::new(314),
+            position: 0,
+            sample_count,
+            segment_size: DEFAULT_SEGMENT_SIZE,
+            frame_size: DEFAULT_FRAME_SIZE,
+            partition_depth: 0,
+            rate_control: RateControl::new(),
+            quantization_callback: None,
+        };
+        adhoc_codec.init();
+        Ok(adhoc_codec)
     }
 
-    fn decode(&mut self, samples: &mut [f32]) -> Option<usize> {
-        let samples_read = self.decode(samples);
-        (samples_read > 0).then(|| samples_read)
-    }
+    fn encode(&mut self, interleaved_pcm: &[f32]) -> Result<usize, Error> {
+        let info = self.try_info()?;
+        self.check_not_decoding()?;
+        let num_channels = info.channels();
+        let bits_per_sample = info.bits_per_sample();
+        let num_chunks = interleaved_pcm.len() / num_channels;
+        let inv_scale = self.inv_scale;
 
-    fn seek(&mut self, dt: SeekFrom) {
-        let info = self.stream.info().expect("info not initalized");
-        let channels = info.channels as u64;
-        let frequency_in_millis = info.sample_rate as f32 / 1000.0;
+        //controls the 'strength'/'influence' of dithering
+        const DITHER_AMPLITUDE: f32 = 0.001;
 
-        self.init();
+        let quantized_channel_list = &mut self.quantized_channel_list;
+        let seq = &mut self.seq;
 
-        if let SeekFrom::Start(0) = dt{
-            // this will skip the process of decoding the frame 
-            // so you can call encode(..) if you want to reuse data that has been 
-            // previously allocated 
-            return; 
+        quantized_channel_list.resize(num_channels, Vec::new());
+
+        //de-interleave pcm by channel, samples wait in `quantized_channel_list` until a frame is complete
+        for (channel_idx, quantized_channel) in quantized_channel_list.iter_mut().enumerate() {
+            quantized_channel.extend(
+                interleaved_pcm
+                    .iter()
+                    .skip(channel_idx)
+                    .step_by(num_channels)
+                    .take(num_chunks)
+                    .zip(seq.triangle())
+                    .map(|(&sample, noise)| {
+                        //dither signal s, before quantization
+                        let dithered = sample + noise * DITHER_AMPLITUDE * (1.0 - inv_scale);
+                        //scale sound down
+                        quantize_sample(dithered * inv_scale, bits_per_sample)
+                    }),
+            );
         }
 
+        self.encode_complete_frames(false)?;
+        Ok(num_chunks * num_channels)
+    }
 
-        let frame_header_list = &mut self.frame_header_list;
-        let channel_state_list = &mut self.channel_state_list;
-        let stream = &mut self.stream;
+    /// # Description
+    /// encodes interleaved 16-bit `samples` without ever converting them to floating point
+    /// ## Comments
+    /// - at compression level `0` this is bit-exact, `decode_i16(..)` returns exactly what was encoded
+    /// - at higher levels samples are quantized with an arithmetic shift, no dither is applied
+    /// - if the stream is wider than 16 bits samples are shifted up to the stream's bit depth
+    /// ## Returns
+    /// number of samples encoded
+    pub fn encode_i16(&mut self, samples: &[i16]) -> Result<usize, Error> {
+        self.encode_integer(samples, 16, |sample| sample as i32)
+    }
 
-        match dt {
-            SeekFrom::Start(dt) => {
-                let offset = (frequency_in_millis * (dt as f32)) as u64;
-                let offset_in_blocks = offset * channels;
+    /// # Description
+    /// decodes part of the stream into interleaved 16-bit `samples`
+    /// ## Comments
+    /// - see `encode_i16(..)`
+    /// ## Returns
+    /// number of samples decoded, `None` once the end of the stream has been reached
+    pub fn decode_i16(&mut self, samples: &mut [i16]) -> Result<Option<usize>, Error> {
+        self.decode_integer(samples, 16, |sample| sample as i16)
+    }
 
-                let mut samples_skipped = 0;
-                let mut header_block_index = 0;
+    /// # Description
+    /// encodes interleaved integer `samples` that are already at the stream's bit depth
+    /// ## Comments
+    /// - samples are right-justified, a 24-bit stream expects samples in `-8388608..=8388607`
+    /// - just like `encode_i16(..)` this is bit-exact at compression level `0`
+    /// ## Returns
+    /// number of samples encoded
+    pub fn encode_i32(&mut self, samples: &[i32]) -> Result<usize, Error> {
+        let bits_per_sample = self.try_info()?.bits_per_sample();
+        self.encode_integer(samples, bits_per_sample, |sample| sample)
+    }
 
-                //number of headers should be a multiple of channels
-                let header_block_len = frame_header_list.len() / channels as usize;
+    /// # Description
+    /// decodes part of the stream into interleaved integer `samples` at the stream's bit depth
+    /// ## Comments
+    /// - see `encode_i32(..)`
+    /// ## Returns
+    /// number of samples decoded, `None` once the end of the stream has been reached
+    pub fn decode_i32(&mut self, samples: &mut [i32]) -> Result<Option<usize>, Error> {
+        let bits_per_sample = self.try_info()?.bits_per_sample();
+        self.decode_integer(samples, bits_per_sample, |sample| sample)
+    }
 
-                //find frame that is within the seek interval
-                while header_block_index < header_block_len && samples_skipped < offset_in_blocks {
-                    let header = frame_header_list
-                        .get(header_block_index * channels as usize)
-                        .expect("error while fence seeking");
+    /// # Description
+    /// encodes `samples` like `encode(..)` does but spreads the work over rayon's thread pool
+    /// ## Comments
+    /// - `samples` is split into segments of `with_segment_size(..)` samples per channel, every segment
+    ///   starts with an init frame so it can be encoded (and decoded) without looking at the others
+    /// - samples still buffered by `encode(..)` get flushed first, each segment ends with a frame that may be short
+    /// - the output is a regular stream, it just isn't bit-identical to encoding serially
+    /// ## Returns
+    /// number of samples encoded
+    #[cfg(feature = "parallel")]
+    pub fn encode_parallel(&mut self, samples: &[f32]) -> Result<usize, Error> {
+        use rayon::prelude::*;
+
+        let info = self.try_info()?;
+        self.check_not_decoding()?;
+        let num_channels = info.channels();
+        let valid_len = (samples.len() / num_channels) * num_channels;
+        let compression_level = self.compression_level;
+        let frame_size = self.frame_size;
+        let partition_depth = self.partition_depth;
+        let rate_control = RateControl::new()
+            .with_target_kbps(self.rate_control.target_kbps())
+            .with_target_snr_db(self.rate_control.target_snr_db());
+
+        self.flush();
+        let segment_size = self.segment_size;
+        let sample_count = self.sample_count;
+        let quantization_callback = &self.quantization_callback;
+        let segment_list = samples[0..valid_len]
+            .par_chunks(segment_size * num_channels)
+            .enumerate()
+            .map(|(index, segment)| {
+                let mut codec = AdhocCodec::new()
+                    .with_compression_level(compression_level)
+                    .with_frame_size(frame_size)
+                    .with_partition_depth(partition_depth)
+                    .with_info(info);
+                codec.rate_control = rate_control;
+                // segments start counting from zero, callbacks should still see where frames are in the stream
+                if let Some(callback) = quantization_callback.clone() {
+                    let offset = sample_count + (index * segment_size) as u64;
+                    codec = codec.with_quantization_callback(move |frame| {
+                        let mut frame = frame.clone();
+                        frame.position += offset;
+                        callback(&frame)
+                    });
+                }
+                codec.encode(segment)?;
+                codec.flush();
+                Ok(codec)
+            })
+            .collect::<Result<Vec<_>, Error>>()?;
 
-                    let header_size = header.size as u64 * channels;
+        segment_list
+            .into_iter()
+            .for_each(|segment| self.append_segment(segment));
+        Ok(valid_len)
+    }
 
-                    if (samples_skipped + header_size) >= offset_in_blocks {
-                        break;
-                    }
+    /// # Description
+    /// stitches an independently encoded `segment` onto the end of the stream
+    /// ## Comments
+    /// - bit cursors of the segment's frames are moved to where its bits end up
+    /// - encoding carries on from the state the segment was left in
+    #[cfg(feature = "parallel")]
+    fn append_segment(&mut self, segment: AdhocCodec) {
+        let bit_offset = self.stream.bit_cursor();
+        self.stream.append(&segment.stream);
+        self.frame_header_list
+            .append(&segment.frame_header_list, bit_offset);
+        self.channel_state_list = segment.channel_state_list;
+        self.sample_count += segment.sample_count;
+        self.rate_control.merge(&segment.rate_control);
+    }
 
-                    samples_skipped += header_size;
-                    header_block_index += 1;
-                }
+    /// # Description
+    /// decodes the whole stream into interleaved samples, spreading the work over rayon's thread pool
+    /// ## Comments
+    /// - frame-blocks are grouped into runs of roughly `with_segment_size(..)` samples, every run is decoded
+    ///   on its own starting from the state recorded in its first `FrameHeader`
+    /// - the output is identical to decoding the stream front to back with `decode(..)`
+    /// - the codec isn't touched, just like `decode_range(..)`
+    #[cfg(feature = "parallel")]
+    pub fn decode_all_parallel(&self) -> Result<Vec<f32>, Error> {
+        self.compressed().decode_all_parallel(self.segment_size)
+    }
 
-                //pinned down start header
-                let start_header_index = header_block_index * channels as usize;
-                let start_header = frame_header_list
-                    .get(start_header_index)
-                    .expect("start frame failed to fetch");
-
-                //make sure cursor in bitstream is set properly
-                stream.set_bit_cursor(start_header.bit_cursor as u128);
-
-                //make sure cursor is set at the 'start header index'
-                frame_header_list.set_cursor(start_header_index as u32);
-
-                //for each frame in the frame-block
-                for offset in 0..channels {
-                    let offset = offset as usize;
-                    let header = frame_header_list
-                        .get(start_header_index + offset)
-                        .expect("frame fence post");
-
-                    //make sure stack history from the header is transferred to state
-                    // 0..3 because sample history is supposed to have 3 samples
-                    for k in 0..3 {
-                        channel_state_list[offset]
-                            .sample_history_mut()
-                            .push(header.stack_history[k]);
-                    }
+    /// # Description
+    /// decodes samples `start..end` (counted per channel) into interleaved `samples`
+    /// ## Comments
+    /// - only the frame-blocks holding the range get decoded, `FrameHeaders` says where each one starts
+    /// - the codec isn't touched, playback through `decode(..)` carries on where it was
+    /// - `end` is clamped to the end of the stream and at most `samples.len()` samples get written
+    /// ## Returns
+    /// number of samples written into `samples`
+    pub fn decode_range(&self, start: u64, end: u64, samples: &mut [f32]) -> Result<usize, Error> {
+        self.compressed().decode_range(start, end, samples)
+    }
 
-                    //make sure buffers are clear
-                    let codec = &mut channel_state_list[offset];
+    /// # Description
+    /// de-interleaves and encodes `sample_bits` wide integer samples
+    fn encode_integer<T, Convert>(
+        &mut self,
+        samples: &[T],
+        sample_bits: u32,
+        convert: Convert,
+    ) -> Result<usize, Error>
+    where
+        T: Copy,
+        Convert: Fn(T) -> i32,
+    {
+        let info = self.try_info()?;
+        self.check_not_decoding()?;
+        let num_channels = info.channels();
+        let bits_per_sample = info.bits_per_sample();
+        let num_chunks = samples.len() / num_channels;
+        let level = self.compression_level;
+        let sample_max = max_sample(bits_per_sample);
+        let sample_min = -sample_max - 1;
+
+        let quantized_channel_list = &mut self.quantized_channel_list;
+        quantized_channel_list.resize(num_channels, Vec::new());
+
+        //de-interleave samples by channel, samples wait in `quantized_channel_list` until a frame is complete
+        for (channel_idx, quantized_channel) in quantized_channel_list.iter_mut().enumerate() {
+            quantized_channel.extend(
+                samples
+                    .iter()
+                    .skip(channel_idx)
+                    .step_by(num_channels)
+                    .take(num_chunks)
+                    .map(|&sample| {
+                        let sample = rescale_sample(convert(sample), sample_bits, bits_per_sample);
+                        sample.clamp(sample_min, sample_max) >> level
+                    }),
+            );
+        }
 
-                    codec.buffered_channel_mut().clear();
+        self.encode_complete_frames(false)?;
+        Ok(num_chunks * num_channels)
+    }
 
-                    //transfer codec state from frame to codec
-                    *codec.state_mut() = header
-                        .is_init
-                        .then(|| CodecState::Init)
-                        .unwrap_or(CodecState::Decoding);
+    /// # Description
+    /// decodes part of the stream into `sample_bits` wide integer samples
+    fn decode_integer<T, Convert>(
+        &mut self,
+        samples: &mut [T],
+        sample_bits: u32,
+        convert: Convert,
+    ) -> Result<Option<usize>, Error>
+    where
+        T: Copy + Default,
+        Convert: Fn(i32) -> T,
+    {
+        let convert = self.compressed().integer_converter(sample_bits, convert)?;
+        let samples_read = self.decode_with(samples, convert)?;
+        Ok((samples_read > 0).then_some(samples_read))
+    }
 
-                    codec.decode_frame(stream, frame_header_list);
-                }
+    /// # Description
+    /// encodes frames out of the samples buffered in `quantized_channel_list`
+    /// ## Parameters
+    /// - `is_flush` also encodes the last frame even if it's smaller than `frame_size`
+    fn encode_complete_frames(&mut self, is_flush: bool) -> Result<(), Error> {
+        let buffered_len = self.buffered_len();
+        if buffered_len == 0 {
+            return Ok(());
+        }
+        self.check_not_decoding()?;
+        let info = self.try_info()?;
+        let bits_per_sample = info.bits_per_sample();
+
+        // encoding over previously written frames drops them, see `FrameHeaders::push(..)`
+        let block_info = &self.frame_header_list;
+        if buffered_len > 0 && block_info.cursor() < block_info.len() {
+            let num_channels = self.quantized_channel_list.len() as u64;
+            self.sample_count = block_info.sample_count(block_info.cursor()) / num_channels;
+            self.rate_control.reset();
+        }
 
-                //prune samples in buffer until the number of samples skipped has been reached
-                while samples_skipped < offset_in_blocks {
-                    for offset in 0..channels {
-                        let offset = offset as usize;
-                        let codec = &mut channel_state_list[offset];
-                        codec.buffered_channel_mut().pop_front();
-                        samples_skipped += 1;
-                    }
+        let mut offset = 0;
+        while buffered_len - offset >= self.frame_size || (is_flush && offset < buffered_len) {
+            let frame_len = (buffered_len - offset).min(self.frame_size);
+            let frame = offset..offset + frame_len;
+            let is_init = self.channel_state_list.iter().any(|cs| cs.is_init());
+            let quant_shift = self.choose_quant_shift(frame.clone(), is_init, info);
+            if self.partition_depth == 0 {
+                self.encode_quantized_channels(frame, quant_shift, bits_per_sample)?;
+            } else {
+                let depth = self.partition_depth;
+                let (_, ends) = self.search_partition(
+                    frame.clone(),
+                    depth,
+                    is_init,
+                    quant_shift,
+                    bits_per_sample,
+                );
+                let mut start = frame.start;
+                for end in ends {
+                    self.encode_quantized_channels(start..end, quant_shift, bits_per_sample)?;
+                    start = end;
                 }
             }
-            _ => {
-                unimplemented!("not implemented, SeekFrom::Start(..) is currently supported")
-            }
+            offset += frame_len;
         }
+        self.quantized_channel_list
+            .iter_mut()
+            .for_each(|quantized_channel| {
+                quantized_channel.drain(0..offset);
+            });
+        Ok(())
     }
-}
 
-mod test {
-    #[allow(unused_imports)]
-    use super::{AdhocCodec, StreamInfo, Streamable};
-
-    #[allow(unused_imports)]
-    use crate::codec::wav::WavCodec;
+    /// # Description
+    /// fails with `Error::Unsupported` if the codec was left in the decoding state
+    /// ## Comments
+    /// - `init()` or `seek(SeekFrom::Start(0))` gets it ready to encode again
+    fn check_not_decoding(&self) -> Result<(), Error> {
+        if self.channel_state_list.iter().any(|cs| cs.is_decoding()) {
+            return Err(Error::Unsupported(
+                "can't encode while decoding, call init() first",
+            ));
+        }
+        Ok(())
+    }
+
+    /// # Description
+    /// encodes `frame` of the quantized channels sitting in `quantized_channel_list` as a single frame-block
+    /// ## Comments
+    /// - samples are shifted down `quant_shift` bits first, the encoder's history keeps what the decoder
+    ///   will see once the shift is undone
+    fn encode_quantized_channels(
+        &mut self,
+        frame: Range<usize>,
+        quant_shift: u32,
+        bits_per_sample: u32,
+    ) -> Result<(), Error> {
+        //split borrows
+        let channel_list = &mut self.channel_state_list;
+        let stream = &mut self.stream;
+        let block_info = &mut self.frame_header_list;
+        let shifted_channel_list =
+            Self::shift_channels(&self.quantized_channel_list, frame.clone(), quant_shift);
+        let quantized_channel_list = shifted_channel_list
+            .iter()
+            .map(|quantized_channel| quantized_channel.as_ref())
+            .collect::<Vec<_>>();
+        let coded_channel_list = &mut self.coded_channel_list;
+        let bit_cursor = stream.bit_cursor();
+        self.sample_count += quantized_channel_list[0].len() as u64;
+
+        let histories = channel_list
+            .iter()
+            .map(|codec| codec.history().map(|s| s >> quant_shift))
+            .collect::<Vec<_>>();
+
+        let is_init = channel_list.iter().any(|codec| codec.is_init());
+        let mode = Self::choose_channel_mode(
+            &quantized_channel_list,
+            &histories,
+            bits_per_sample,
+            is_init,
+        );
+        Self::decorrelate_channels(mode, &quantized_channel_list, coded_channel_list);
+
+        for (channel_idx, codec) in channel_list.iter_mut().enumerate() {
+            let channel = mode.coded_channel(channel_idx, &histories, bits_per_sample);
+            let samples = if mode == ChannelMode::Independent {
+                quantized_channel_list[channel_idx]
+            } else {
+                &coded_channel_list[channel_idx]
+            };
+            codec.encode_frame(stream, block_info, samples, channel, quant_shift as u8)?;
+            if quant_shift == 0 {
+                codec.push_history(quantized_channel_list[channel_idx]);
+            } else {
+                let history_start = frame.len().saturating_sub(3);
+                let reconstructed = quantized_channel_list[channel_idx][history_start..]
+                    .iter()
+                    .map(|&s| FrameCodec::dequantize(s, bits_per_sample, quant_shift))
+                    .collect::<Vec<_>>();
+                codec.push_history(&reconstructed);
+            }
+        }
+
+        let header_bits = FrameHeader::WEIGHT_IN_BITS * channel_list.len() as u64;
+        let frame_bits = (stream.bit_cursor() - bit_cursor) as u64 + header_bits;
+        let distortion = Self::measure_distortion(
+            &self.quantized_channel_list,
+            frame.clone(),
+            quant_shift,
+            bits_per_sample,
+        );
+        self.rate_control
+            .record(frame.len(), frame_bits, quant_shift, distortion);
+        Ok(())
+    }
+
+    /// # Description
+    /// `frame` of every channel in `quantized_channel_list`, shifted down by `quant_shift` bits
+    /// ## Comments
+    /// - samples get rounded to the nearest step, nothing is copied when `quant_shift` is `0`
+    fn shift_channels(
+        quantized_channel_list: &[Vec<i32>],
+        frame: Range<usize>,
+        quant_shift: u32,
+    ) -> Vec<Cow<'_, [i32]>> {
+        quantized_channel_list
+            .iter()
+            .map(|quantized_channel| {
+                let samples = &quantized_channel[frame.clone()];
+                if quant_shift == 0 {
+                    return Cow::Borrowed(samples);
+                }
+                let half_step = 1i64 << (quant_shift - 1);
+                samples
+                    .iter()
+                    .map(|&s| ((s as i64 + half_step) >> quant_shift) as i32)
+                    .collect()
+            })
+            .collect()
+    }
+
+    /// # Description
+    /// picks the quantization shift of `frame`
+    /// ## Comments
+    /// - with a target bitrate it's the smallest shift that keeps `frame` within the rate control's budget,
+    ///   estimated sizes shrink as the shift grows so the shift is binary searched
+    /// - with a target quality it's the largest shift that still meets it, when both targets are set
+    ///   quality wins
+    /// - the quantization callback, if there is one, has the final say
+    /// - always `0` without a target or callback
+    fn choose_quant_shift(&mut self, frame: Range<usize>, is_init: bool, info: StreamInfo) -> u32 {
+        let bits_per_sample = info.bits_per_sample();
+        // always leave a couple bits so the signal doesn't vanish entirely
+        let max_shift = MAX_QUANT_SHIFT.min(bits_per_sample - 2);
+
+        let mut quant_shift = None;
+        if let Some(budget) = self.rate_control.budget(frame.len(), info) {
+            let (mut low, mut high) = (0, max_shift);
+            while low < high {
+                let mid = (low + high) / 2;
+                let bits =
+                    self.estimate_frame_block_bits(frame.clone(), is_init, mid, bits_per_sample);
+                if bits <= budget {
+                    high = mid;
+                } else {
+                    low = mid + 1;
+                }
+            }
+            quant_shift = Some(low);
+        }
+
+        if let Some(target_snr_db) = self.rate_control.target_snr_db() {
+            let quantized_channel_list = &self.quantized_channel_list;
+            let meets_target = |shift: u32| {
+                let (signal, noise) = Self::measure_distortion(
+                    quantized_channel_list,
+                    frame.clone(),
+                    shift,
+                    bits_per_sample,
+                );
+                snr_db(signal, noise) >= target_snr_db
+            };
+
+            // noise grows with the shift, so this is binary searched too
+            let (mut low, mut high) = (0, max_shift);
+            while low < high {
+                let mid = (low + high).div_ceil(2);
+                if meets_target(mid) {
+                    low = mid;
+                } else {
+                    high = mid - 1;
+                }
+            }
+            quant_shift = Some(quant_shift.map_or(low, |shift| shift.min(low)));
+        }
+
+        let suggested_shift = quant_shift.unwrap_or(0);
+        match &self.quantization_callback {
+            Some(callback) => {
+                let context = FrameContext {
+                    position: self.sample_count,
+                    channel_list: self
+                        .quantized_channel_list
+                        .iter()
+                        .map(|channel| &channel[frame.clone()])
+                        .collect(),
+                    bits_per_sample: info.bits_per_sample(),
+                    suggested_shift,
+                    max_shift,
+                };
+                callback(&context).min(max_shift)
+            }
+            None => suggested_shift,
+        }
+    }
+
+    /// # Description
+    /// how far `frame` of the buffered channels drifts from the original once it is shifted down
+    /// `quant_shift` bits and back up again
+    /// ## Returns
+    /// the tuple `(signal energy, noise energy)` of normalized samples, see `snr_db(..)`
+    fn measure_distortion(
+        quantized_channel_list: &[Vec<i32>],
+        frame: Range<usize>,
+        quant_shift: u32,
+        bits_per_sample: u32,
+    ) -> (f64, f64) {
+        let normalize = |sample: i32| sample as f32 / max_sample(bits_per_sample) as f32;
+        let shifted_channel_list =
+            Self::shift_channels(quantized_channel_list, frame.clone(), quant_shift);
+
+        let mut signal_energy = 0.0;
+        let mut noise_energy = 0.0;
+        for (quantized_channel, shifted_channel) in quantized_channel_list
+            .iter()
+            .zip(shifted_channel_list.iter())
+        {
+            let original = quantized_channel[frame.clone()]
+                .iter()
+                .map(|&s| normalize(s))
+                .collect::<Vec<_>>();
+            signal_energy += original.iter().map(|&s| (s * s) as f64).sum::<f64>();
+            if quant_shift == 0 {
+                continue;
+            }
+
+            let reconstructed = shifted_channel
+                .iter()
+                .map(|&s| normalize(FrameCodec::dequantize(s, bits_per_sample, quant_shift)))
+                .collect::<Vec<_>>();
+            noise_energy +=
+                math::compute_mse(&original, &reconstructed) as f64 * original.len() as f64;
+        }
+        (signal_energy, noise_energy)
+    }
+
+    /// # Description
+    /// picks how the channels of a frame-block get decorrelated
+    /// ## Comments
+    /// - stereo frames get decorrelated, init frames are left alone since they start with raw samples
+    ///   and 32-bit streams are left alone because the side channel wouldn't fit in 32 bits
+    fn choose_channel_mode(
+        quantized_channel_list: &[&[i32]],
+        histories: &[[i32; 3]],
+        bits_per_sample: u32,
+        is_init: bool,
+    ) -> ChannelMode {
+        let is_stereo = quantized_channel_list.len() == 2
+            && bits_per_sample < StreamInfo::MAX_BITS_PER_SAMPLE
+            && !is_init;
+        if is_stereo {
+            ChannelMode::choose(
+                quantized_channel_list[0],
+                quantized_channel_list[1],
+                histories[0],
+                histories[1],
+            )
+        } else {
+            ChannelMode::Independent
+        }
+    }
+
+    /// # Description
+    /// writes the coded channels of a stereo frame-block to `coded_channel_list`
+    /// ## Comments
+    /// - nothing happens for `ChannelMode::Independent`, the quantized channels are coded as is
+    fn decorrelate_channels(
+        mode: ChannelMode,
+        quantized_channel_list: &[&[i32]],
+        coded_channel_list: &mut Vec<Vec<i32>>,
+    ) {
+        if mode == ChannelMode::Independent {
+            return;
+        }
+
+        coded_channel_list.resize(2, Vec::new());
+        let (left, right) = (quantized_channel_list[0], quantized_channel_list[1]);
+        let [a, b] = &mut coded_channel_list[..] else {
+            unreachable!()
+        };
+        a.clear();
+        b.clear();
+        for (&l, &r) in left.iter().zip(right.iter()) {
+            let (x, y) = mode.decorrelate(l, r);
+            a.push(x);
+            b.push(y);
+        }
+    }
+
+    /// # Description
+    /// estimates how many bits `frame` of the buffered channels would take if it were encoded as one
+    /// frame-block, see `FrameCodec::estimate_frame_bits(..)`
+    /// ## Comments
+    /// - `frame` must start at or after the first sample that hasn't been encoded yet
+    /// - samples get shifted down `quant_shift` bits first, like `encode_quantized_channels(..)` does
+    fn estimate_frame_block_bits(
+        &mut self,
+        frame: Range<usize>,
+        is_init: bool,
+        quant_shift: u32,
+        bits_per_sample: u32,
+    ) -> u64 {
+        //split borrows
+        let channel_list = &mut self.channel_state_list;
+        let coded_channel_list = &mut self.coded_channel_list;
+
+        // the three samples before `frame` come from the buffer or from what was encoded last
+        let histories = channel_list
+            .iter()
+            .zip(self.quantized_channel_list.iter())
+            .map(|(codec, quantized_channel)| {
+                let encoded_history = codec.history();
+                let mut history = [0; 3];
+                for (k, h) in history.iter_mut().enumerate() {
+                    *h = match (frame.start + k).checked_sub(3) {
+                        Some(idx) => quantized_channel[idx],
+                        None => encoded_history[frame.start + k],
+                    };
+                    *h >>= quant_shift;
+                }
+                history
+            })
+            .collect::<Vec<_>>();
+        let shifted_channel_list =
+            Self::shift_channels(&self.quantized_channel_list, frame.clone(), quant_shift);
+        let quantized_channel_list = shifted_channel_list
+            .iter()
+            .map(|quantized_channel| quantized_channel.as_ref())
+            .collect::<Vec<_>>();
+
+        let mode = Self::choose_channel_mode(
+            &quantized_channel_list,
+            &histories,
+            bits_per_sample,
+            is_init,
+        );
+        Self::decorrelate_channels(mode, &quantized_channel_list, coded_channel_list);
+
+        let mut bits = 0;
+        for (channel_idx, codec) in channel_list.iter_mut().enumerate() {
+            let channel = mode.coded_channel(channel_idx, &histories, bits_per_sample);
+            let samples = if mode == ChannelMode::Independent {
+                quantized_channel_list[channel_idx]
+            } else {
+                &coded_channel_list[channel_idx]
+            };
+            bits += codec.estimate_frame_bits(samples, channel, is_init);
+        }
+        bits
+    }
+
+    /// # Description
+    /// finds the cheapest way of cutting `frame` into sub-blocks by recursively trying to halve it,
+    /// at most `depth` times
+    /// ## Returns
+    /// the tuple `(estimated bits, where every sub-block ends)`
+    fn search_partition(
+        &mut self,
+        frame: Range<usize>,
+        depth: u32,
+        is_init: bool,
+        quant_shift: u32,
+        bits_per_sample: u32,
+    ) -> (u64, Vec<usize>) {
+        let whole_bits =
+            self.estimate_frame_block_bits(frame.clone(), is_init, quant_shift, bits_per_sample);
+        let whole = (whole_bits, vec![frame.end]);
+        if depth == 0 || frame.len() < 2 * MIN_PARTITION_SIZE {
+            return whole;
+        }
+
+        let mid = frame.start + frame.len() / 2;
+        let (left_bits, mut ends) = self.search_partition(
+            frame.start..mid,
+            depth - 1,
+            is_init,
+            quant_shift,
+            bits_per_sample,
+        );
+        if left_bits >= whole_bits {
+            return whole;
+        }
+        let (right_bits, right_ends) = self.search_partition(
+            mid..frame.end,
+            depth - 1,
+            false,
+            quant_shift,
+            bits_per_sample,
+        );
+        if left_bits + right_bits < whole_bits {
+            ends.extend(right_ends);
+            (left_bits + right_bits, ends)
+        } else {
+            whole
+        }
+    }
+
+    /// # Description
+    /// decodes the next frame of every channel and buffers the samples
+    /// ## Returns
+    /// `None` when the stream has no frames left
+    /// ## Comments
+    /// - fails with `Error::CorruptFrame` if a frame reads past the end of the stream
+    fn decode_frame_block(
+        channel_list: &mut [FrameCodec],
+        stream: &mut AudioStream,
+        frame_header_list: &mut FrameHeaders,
+        bits_per_sample: u32,
+    ) -> Result<Option<()>, Error> {
+        let frame = frame_header_list.cursor();
+        let mut reader = stream.reader();
+        let decoded = Self::decode_frame_block_at(
+            channel_list,
+            &mut reader,
+            frame_header_list,
+            frame,
+            bits_per_sample,
+        )?;
+        let bit_cursor = reader.bit_cursor();
+        stream.set_bit_cursor(bit_cursor);
+        if decoded.is_some() {
+            frame_header_list.set_cursor((frame + channel_list.len()) as u32);
+        }
+        Ok(decoded)
+    }
+
+    /// # Description
+    /// `decode_frame_block(..)` for the frame-block starting at header `frame`, bits come from `stream`
+    /// ## Comments
+    /// - nothing but `channel_list` and `stream` is touched, which is how `decode_range(..)` leaves
+    ///   the codec alone
+    fn decode_frame_block_at(
+        channel_list: &mut [FrameCodec],
+        stream: &mut BitReader,
+        frame_header_list: &FrameHeaders,
+        frame: usize,
+        bits_per_sample: u32,
+    ) -> Result<Option<()>, Error> {
+        if frame + channel_list.len() > frame_header_list.len() {
+            return Ok(None);
+        }
+
+        // every frame of a frame-block has the same quantization shift
+        let quant_shift = frame_header_list
+            .get(frame)
+            .map_or(0, |header| header.quant_shift as u32);
+        let histories = channel_list
+            .iter()
+            .map(|codec| codec.history().map(|s| s >> quant_shift))
+            .collect::<Vec<_>>();
+
+        let mut mode = ChannelMode::Independent;
+        for (channel_idx, codec) in channel_list.iter_mut().enumerate() {
+            let frame = frame + channel_idx;
+            let header = match frame_header_list.get(frame) {
+                Some(header) => header,
+                None => return Ok(None),
+            };
+            mode = header.channel_mode;
+            let channel = mode.coded_channel(channel_idx, &histories, bits_per_sample);
+            codec.decode_frame(stream, &header, channel)?;
+
+            if stream.bit_cursor() > stream.capacity_upperbound() as u128 {
+                return Err(Error::CorruptFrame { frame });
+            }
+        }
+
+        if mode == ChannelMode::Independent || channel_list.len() != 2 {
+            channel_list
+                .iter_mut()
+                .for_each(|codec| codec.push_decoded_frame(bits_per_sample, quant_shift));
+        } else {
+            let (left, right): (Vec<_>, Vec<_>) = channel_list[0]
+                .frame_samples()
+                .iter()
+                .zip(channel_list[1].frame_samples().iter())
+                .map(|(&a, &b)| mode.correlate(a, b))
+                .unzip();
+            channel_list[0].push_decoded(&left, bits_per_sample, quant_shift);
+            channel_list[1].push_decoded(&right, bits_per_sample, quant_shift);
+        }
+
+        Ok(Some(()))
+    }
+
+    /// # Description
+    /// moves freshly initialized `channel_list` and `stream` to sample `offset` (counted per channel)
+    /// ## Comments
+    /// - the frame-block holding `offset` gets decoded and the samples before `offset` thrown away
+    /// ## Returns
+    /// index of the frame to decode next
+    fn seek_channels(
+        channel_list: &mut [FrameCodec],
+        frame_header_list: &FrameHeaders,
+        stream: &mut BitReader,
+        offset: u64,
+        bits_per_sample: u32,
+    ) -> Result<usize, Error> {
+        let channels = channel_list.len();
+
+        //find the frame-block that is within the seek interval
+        let (frame, block_start) = match frame_header_list.find_frame_block(offset, channels) {
+            Some(block) => block,
+            //seeked past the end, there is nothing left to decode
+            None => return Ok(frame_header_list.len()),
+        };
+
+        let bit_cursor = Self::prepare_frame_block(channel_list, frame_header_list, frame)?;
+        stream.set_bit_cursor(bit_cursor);
+        Self::decode_frame_block_at(
+            channel_list,
+            stream,
+            frame_header_list,
+            frame,
+            bits_per_sample,
+        )?;
+
+        //prune samples in buffer until the seek offset has been reached
+        for _ in block_start..offset {
+            for codec in channel_list.iter_mut() {
+                codec.buffered_channel_mut().pop_front();
+            }
+        }
+
+        Ok(frame + channels)
+    }
+
+    /// # Description
+    /// gets `channel_list` ready to decode the frame-block starting at header `frame`
+    /// ## Returns
+    /// the bit cursor the frame-block starts at
+    fn prepare_frame_block(
+        channel_list: &mut [FrameCodec],
+        frame_header_list: &FrameHeaders,
+        frame: usize,
+    ) -> Result<u128, Error> {
+        let mut bit_cursor = 0;
+
+        //for each frame in the frame-block
+        for (offset, codec) in channel_list.iter_mut().enumerate() {
+            let header = frame_header_list
+                .get(frame + offset)
+                .ok_or_else(|| Error::CorruptHeader("incomplete frame-block".to_string()))?;
+            if offset == 0 {
+                bit_cursor = header.bit_cursor;
+            }
+
+            //make sure stack history from the header is transferred to state
+            codec.push_history(&header.stack_history);
+
+            //make sure buffers are clear
+            codec.buffered_channel_mut().clear();
+
+            //transfer codec state from frame to codec
+            *codec.state_mut() = if header.is_init {
+                CodecState::Init
+            } else {
+                CodecState::Decoding
+            };
+        }
+
+        Ok(bit_cursor)
+    }
+
+    fn decode(&mut self, pcm_out: &mut [f32]) -> Result<usize, Error> {
+        let convert = self.compressed().float_converter()?;
+        self.decode_with(pcm_out, convert)
+    }
+
+    /// # Description
+    /// decodes part of the stream into `pcm_out`, `convert` maps quantized samples to the output format
+    fn decode_with<T, Convert>(
+        &mut self,
+        pcm_out: &mut [T],
+        convert: Convert,
+    ) -> Result<usize, Error>
+    where
+        T: Copy + Default,
+        Convert: Fn(i32) -> T,
+    {
+        self.decode_from(pcm_out, convert, |_, _| Ok(()))
+    }
+
+    /// # Description
+    /// same as `decode_with(..)` but calls `fill` right before every frame-block gets decoded
+    /// ## Comments
+    /// - `fill` gets the stream and the headers (cursor pointing at the frame-block), it's how
+    ///   `AdhocReader` makes sure the bits (and headers) of the frame-block are in memory
+    fn decode_from<T, Convert, Fill>(
+        &mut self,
+        pcm_out: &mut [T],
+        convert: Convert,
+        mut fill: Fill,
+    ) -> Result<usize, Error>
+    where
+        T: Copy + Default,
+        Convert: Fn(i32) -> T,
+        Fill: FnMut(&mut AudioStream, &mut FrameHeaders) -> Result<(), Error>,
+    {
+        let info = self.try_info()?;
+        // leftovers of `encode(..)` would otherwise be stuck in the buffer once decoding starts
+        self.flush();
+        let num_channels = info.channels();
+        let bits_per_sample = info.bits_per_sample();
+        let stream = &mut self.stream;
+        let block_info = &mut self.frame_header_list;
+
+        let samples_read = Self::drain_channels(
+            &mut self.channel_state_list,
+            pcm_out,
+            convert,
+            |channel_list| {
+                fill(stream, block_info)?;
+                Self::decode_frame_block(channel_list, stream, block_info, bits_per_sample)
+            },
+        )?;
+
+        self.position += (samples_read / num_channels) as u64;
+        Ok(samples_read)
+    }
+
+    /// # Description
+    /// interleaves the samples buffered in `channel_list` into `pcm_out`
+    /// ## Comments
+    /// - `next_block` decodes the next frame-block whenever the buffers run dry
+    /// - shared by every decoder in this crate, see `AdhocDecoder`
+    /// ## Returns
+    /// number of samples written into `pcm_out`
+    fn drain_channels<T, Convert, NextBlock>(
+        channel_list: &mut [FrameCodec],
+        pcm_out: &mut [T],
+        convert: Convert,
+        mut next_block: NextBlock,
+    ) -> Result<usize, Error>
+    where
+        T: Copy + Default,
+        Convert: Fn(i32) -> T,
+        NextBlock: FnMut(&mut [FrameCodec]) -> Result<Option<()>, Error>,
+    {
+        let num_channels = channel_list.len();
+        if num_channels == 0 {
+            return Ok(0);
+        }
+
+        let is_buffers_empty = |channel_list: &mut [FrameCodec]| {
+            channel_list
+                .iter()
+                .all(|cs| cs.buffered_channel().is_empty())
+        };
+
+        let mut buffer_audio = |channel_list: &mut [FrameCodec]| -> Result<usize, Error> {
+            let mut attempts = 0;
+            //decode and load buffers if empty
+            while attempts < MAX_DECODE_ATTEMPTS && is_buffers_empty(channel_list) {
+                //decode more data
+                next_block(channel_list)?;
+                attempts += 1;
+            }
+            Ok(attempts)
+        };
+
+        // number of samples that can be written has to number a multiple of `num_channels`
+        let legal_output_len = (pcm_out.len() / num_channels) * num_channels;
+        let mut pcm_out_cursor = 0;
+
+        while pcm_out_cursor < legal_output_len {
+            if buffer_audio(channel_list)? >= MAX_DECODE_ATTEMPTS {
+                break;
+            }
+
+            let mut channel_idx = 0;
+            while (pcm_out_cursor < legal_output_len) && (channel_idx < num_channels) {
+                let decoded_sample = channel_list[channel_idx]
+                    .buffered_channel_mut()
+                    .pop_front()
+                    .map(&convert)
+                    .unwrap_or_default();
+                pcm_out[pcm_out_cursor] = decoded_sample;
+                pcm_out_cursor += 1;
+                channel_idx += 1;
+            }
+        }
+
+        Ok(pcm_out_cursor)
+    }
+}
+
+/// # Description
+/// what a `.adhoc` file starts with
+enum Prelude {
+    /// a version `0` file, holds the bytes that were read while looking for the magic
+    Legacy([u8; 4]),
+    /// a `FileHeader` this version of the crate can decode
+    Versioned(FileHeader),
+}
+
+impl Prelude {
+    /// # Description
+    /// reads the magic and, if it's there, the rest of the `FileHeader`
+    /// ## Comments
+    /// - headers from newer versions of the format are rejected with `Error::UnsupportedFormat`
+    fn read_from<Resource>(mut res: Resource) -> Result<Self, Error>
+    where
+        Resource: Read,
+    {
+        let mut magic = [0u8; 4];
+        res.read_exact(&mut magic)?;
+        if magic != FileHeader::MAGIC {
+            return Ok(Prelude::Legacy(magic));
+        }
+
+        let header = FileHeader::read_after_magic(&mut res)?;
+        if header.version > FileHeader::VERSION {
+            return Err(Error::UnsupportedFormat(format!(
+                "adhoc format version {} (newest supported is {})",
+                header.version,
+                FileHeader::VERSION
+            )));
+        }
+        if !header.is_supported() {
+            return Err(Error::UnsupportedFormat(format!(
+                "unknown adhoc feature flags {:#06x}",
+                header.flags & !FileHeader::KNOWN_FLAGS
+            )));
+        }
+        Ok(Prelude::Versioned(header))
+    }
+}
+
+/// # Description
+/// the bincode configuration `save_to(..)` writes with, reads are capped at `limit` bytes
+fn bincode_options(limit: u64) -> impl bincode::Options {
+    bincode::DefaultOptions::new()
+        .with_fixint_encoding()
+        .allow_trailing_bytes()
+        .with_limit(limit)
+}
+
+/// # Description
+/// the parts of a stream decoding reads but never changes
+/// ## Comments
+/// - `AdhocCodec` and `AdhocAsset` both decode through this, the asset doesn't have to hold a whole codec
+#[derive(Clone, Copy)]
+struct CompressedStream<'a> {
+    stream: &'a AudioStream,
+    frame_header_list: &'a FrameHeaders,
+    compression_level: u32,
+    /// used to 'reverse' quantization on decoding step
+    scale: f32,
+    /// length of the stream in samples per channel
+    sample_count: u64,
+}
+
+impl CompressedStream<'_> {
+    /// # Description
+    /// see `AdhocCodec::try_info()`
+    fn try_info(&self) -> Result<StreamInfo, Error> {
+        let info = self.stream.info().ok_or(Error::InfoNotSet)?;
+        if info.channels() == 0 {
+            return Err(Error::UnsupportedFormat(
+                "streams need at least one channel".to_string(),
+            ));
+        }
+        Ok(info)
+    }
+
+    /// # Description
+    /// returns a tight upper-bound estimate on the number of bits needed to write this file
+    fn filesize_upperbound(&self) -> u64 {
+        /*
+            the final filesize is roughly equal to:
+                FrameHeaderList_bits+
+                AudioStream_bits +
+                compression_level_bits
+        */
+        let audio_stream_header =         
+            //number of bits AudioStream need to store info 
+            mem::size_of::<StreamInfo>() as u64 * 8;
+
+        let bitstream = self.stream.capacity_upperbound() as u64+ 
+          //streams internal cursor + capacity 
+          128*2;
+
+        let audio_stream_bits = audio_stream_header + bitstream;
+        let compression_level_bits = 32;
+        let frame_header_bits = self.frame_header_list.calculate_weight_upperbound();
+
+        //estimated upperbound is..
+        audio_stream_bits + compression_level_bits + frame_header_bits
+    }
+
+    #[cfg(feature = "parallel")]
+    pub fn decode_all_parallel(&self, segment_size: usize) -> Result<Vec<f32>, Error> {
+        use rayon::prelude::*;
+
+        let info = self.try_info()?;
+        let num_channels = info.channels();
+        let convert = self.float_converter()?;
+
+        // split the stream at frame-block boundaries
+        let mut run_list = Vec::new();
+        let mut run_start = 0;
+        let mut run_end = 0;
+        for size in self.frame_header_list.frame_block_sizes(num_channels) {
+            run_end += size;
+            if run_end - run_start >= segment_size as u64 {
+                run_list.push(run_start..run_end);
+                run_start = run_end;
+            }
+        }
+        if run_start < run_end {
+            run_list.push(run_start..run_end);
+        }
+
+        // every run gets its own piece of the output
+        let mut pcm = vec![0.0; run_end as usize * num_channels];
+        let mut pcm_remaining = &mut pcm[..];
+        let mut job_list = Vec::with_capacity(run_list.len());
+        for run in run_list {
+            let run_len = (run.end - run.start) as usize * num_channels;
+            let (pcm_out, rest) = pcm_remaining.split_at_mut(run_len);
+            job_list.push((run, pcm_out));
+            pcm_remaining = rest;
+        }
+
+        job_list.into_par_iter().try_for_each(|(run, pcm_out)| {
+            let samples_read = self.decode_range_with(run.start, run.end, pcm_out, &convert)?;
+            if samples_read < pcm_out.len() {
+                return Err(Error::CorruptHeader(
+                    "frame sizes don't add up to the length of the stream".to_string(),
+                ));
+            }
+            Ok(())
+        })?;
+
+        Ok(pcm)
+    }
+
+    pub fn decode_range(&self, start: u64, end: u64, samples: &mut [f32]) -> Result<usize, Error> {
+        let convert = self.float_converter()?;
+        self.decode_range_with(start, end, samples, convert)
+    }
+
+    /// # Description
+    /// `decode_range(..)` with `convert` mapping quantized samples to the output format
+    fn decode_range_with<T, Convert>(
+        &self,
+        start: u64,
+        end: u64,
+        samples: &mut [T],
+        convert: Convert,
+    ) -> Result<usize, Error>
+    where
+        Convert: Fn(i32) -> T,
+    {
+        let info = self.try_info()?;
+        let num_channels = info.channels();
+        let bits_per_sample = info.bits_per_sample();
+        let frame_header_list = &self.frame_header_list;
+
+        let end = end.min(self.sample_count);
+        let block = frame_header_list.find_frame_block(start, num_channels);
+        let (mut frame, block_start) = match block {
+            Some(block) if start < end => block,
+            _ => return Ok(0),
+        };
+
+        // number of samples that can be written has to number a multiple of `num_channels`
+        let legal_output_len = (samples.len() / num_channels) as u64;
+        let output_len = (end - start).min(legal_output_len) as usize * num_channels;
+
+        // a private set of channel states, the ones in `self` belong to `decode(..)`
+        let mut channel_list = (0..num_channels)
+            .map(|_| FrameCodec::new())
+            .collect::<Vec<_>>();
+        let bit_cursor =
+            AdhocCodec::prepare_frame_block(&mut channel_list, frame_header_list, frame)?;
+        let mut stream = self.stream.reader();
+        stream.set_bit_cursor(bit_cursor);
+
+        let mut samples_to_skip = start - block_start;
+        let mut output_cursor = 0;
+        while output_cursor < output_len {
+            if channel_list[0].buffered_channel().is_empty() {
+                let decoded = AdhocCodec::decode_frame_block_at(
+                    &mut channel_list,
+                    &mut stream,
+                    frame_header_list,
+                    frame,
+                    bits_per_sample,
+                )?;
+                if decoded.is_none() {
+                    break;
+                }
+                frame += num_channels;
+                continue;
+            }
+
+            for codec in channel_list.iter_mut() {
+                let sample = codec.buffered_channel_mut().pop_front().unwrap_or_default();
+                if samples_to_skip == 0 {
+                    samples[output_cursor] = convert(sample);
+                    output_cursor += 1;
+                }
+            }
+            samples_to_skip = samples_to_skip.saturating_sub(1);
+        }
+
+        Ok(output_cursor)
+    }
+
+    /// # Description
+    /// maps quantized samples to `sample_bits` wide integer samples, `convert` narrows them to `T`
+    fn integer_converter<T, Convert>(
+        &self,
+        sample_bits: u32,
+        convert: Convert,
+    ) -> Result<impl Fn(i32) -> T, Error>
+    where
+        Convert: Fn(i32) -> T,
+    {
+        let bits_per_sample = self.try_info()?.bits_per_sample();
+        let level = self.compression_level;
+        let sample_max = max_sample(bits_per_sample) as i64;
+
+        Ok(move |sample: i32| {
+            let sample = ((sample as i64) << level).clamp(-sample_max - 1, sample_max) as i32;
+            convert(rescale_sample(sample, bits_per_sample, sample_bits))
+        })
+    }
+
+    /// # Description
+    /// maps quantized samples to `f32` samples in `-1.0..=1.0`(before scaling)
+    fn float_converter(&self) -> Result<impl Fn(i32) -> f32, Error> {
+        let scale = self.scale;
+        let bits_per_sample = self.try_info()?.bits_per_sample();
+        Ok(move |sample: i32| {
+            //scale up signal
+            dequantize_sample(sample, bits_per_sample).clamp(-1.0, 1.0) * scale
+        })
+    }
+}
+
+/// # Description
+/// reads `FrameHeaders` written by format `version`, older layouts are converted
+fn read_frame_headers<Resource>(
+    res: Resource,
+    limit: u64,
+    version: u16,
+) -> Result<FrameHeaders, Error>
+where
+    Resource: Read,
+{
+    let frame_header_list = match version {
+        0..=1 => bincode_options(limit)
+            .deserialize_from::<_, FrameHeadersV1>(res)?
+            .into(),
+        2 => bincode_options(limit)
+            .deserialize_from::<_, FrameHeadersV2>(res)?
+            .into(),
+        _ => bincode_options(limit).deserialize_from::<_, FrameHeaders>(res)?,
+    };
+    Ok(frame_header_list)
+}
+
+impl Streamable for AdhocCodec {
+    fn info(&self) -> StreamInfo {
+        self.info()
+    }
+
+    fn filesize_upperbound(&self) -> u64 {
+        self.filesize_upperbound()
+    }
+
+    fn encode(&mut self, samples: &[f32]) -> Result<usize, Error> {
+        self.encode(samples)
+    }
+
+    fn decode(&mut self, samples: &mut [f32]) -> Result<Option<usize>, Error> {
+        let samples_read = self.decode(samples)?;
+        Ok((samples_read > 0).then_some(samples_read))
+    }
+
+    fn seek(&mut self, dt: SeekFrom) -> Result<(), Error> {
+        let info = self.try_info()?;
+        let target = resolve_seek(dt, self.position, self.sample_count, info.sample_rate);
+        self.seek_to_sample(target)
+    }
+
+    /// the frame-block holding `offset` gets decoded and the samples before `offset` thrown away
+    fn seek_to_sample(&mut self, offset: u64) -> Result<(), Error> {
+        let info = self.try_info()?;
+        let offset = offset.min(self.sample_count);
+
+        self.init();
+
+        if offset == 0 {
+            // this will skip the process of decoding the frame
+            // so you can call encode(..) if you want to reuse data that has been
+            // previously allocated
+            return Ok(());
+        }
+        self.position = offset;
+
+        let mut stream = self.stream.reader();
+        let frame = Self::seek_channels(
+            &mut self.channel_state_list,
+            &self.frame_header_list,
+            &mut stream,
+            offset,
+            info.bits_per_sample(),
+        )?;
+
+        //make sure cursors in the bitstream and headers are set properly
+        let bit_cursor = stream.bit_cursor();
+        self.stream.set_bit_cursor(bit_cursor);
+        self.frame_header_list.set_cursor(frame as u32);
+
+        Ok(())
+    }
+
+    fn position_in_samples(&self) -> u64 {
+        self.position
+    }
+
+    fn duration_in_samples(&self) -> u64 {
+        self.calculate_sample_count_per_channel()
+    }
+}
+
+mod test {
+    #[allow(unused_imports)]
+    use super::{
+        snr_db, AdhocCodec, AdhocReader, AdhocWriter, AudioStream, ChannelMode, Error, FileHeader,
+        FrameCodec, FrameHeaders, StreamInfo, Streamable, MAX_PARTITION_DEPTH, MIN_PARTITION_SIZE,
+    };
+
+    #[allow(unused_imports)]
+    use super::bincode_options;
+
+    #[allow(unused_imports)]
+    use bincode::Options;
+
+    #[allow(unused_imports)]
+    use crate::codec::wav::{SampleFormat, WavCodec};
 
     #[allow(unused_imports)]
     use crate::math::{self, signal};
@@ -447,6 +1899,7 @@ mod test {
     use std::{
         fs::File,
         io::{Cursor, Read, SeekFrom, Write},
+        time::Duration,
     };
 
     #[test]
@@ -456,16 +1909,13 @@ mod test {
             0.0, 0.0, 0.01, 0.01, 0.02, 0.02, 0.03, 0.03, 0.019, 0.020, 0.019, 0.018, 0.017,
         ];
 
-        codec.set_info(StreamInfo {
-            channels: 1,
-            sample_rate: 44100,
-        });
+        codec.set_info(StreamInfo::new(44100, 1));
 
-        codec.encode(&data);
+        codec.encode(&data).unwrap();
 
-        let mut out_buffer = data.clone();
+        let mut out_buffer = data;
         codec.init();
-        let samples_read = codec.decode(&mut out_buffer[..]);
+        let samples_read = codec.decode(&mut out_buffer[..]).unwrap();
         let mean_squared_error = math::compute_mse(&out_buffer, &data);
 
         println!("data   :{:?}", data);
@@ -480,7 +1930,7 @@ mod test {
 
         for (&input_sample, &decoded_sample) in data.iter().zip(out_buffer.iter()) {
             let close_enough = (input_sample - decoded_sample).abs() < 0.01;
-            assert_eq!(close_enough, true, "accuracy threshold not met");
+            assert!(close_enough, "accuracy threshold not met");
         }
     }
 
@@ -491,17 +1941,14 @@ mod test {
             0.0, 0.0, 0.1, 0.01, 0.2, 0.02, 0.3, 0.03, 0.4, 0.020, 0.5, 0.018, 0.6, 0.019,
         ];
 
-        codec.set_info(StreamInfo {
-            channels: 2,
-            sample_rate: 44100,
-        });
+        codec.set_info(StreamInfo::new(44100, 2));
 
-        codec.encode(&data[0..8]);
-        codec.encode(&data[8..]);
+        codec.encode(&data[0..8]).unwrap();
+        codec.encode(&data[8..]).unwrap();
 
-        let mut out_buffer = data.clone();
+        let mut out_buffer = data;
         codec.init();
-        let samples_read = codec.decode(&mut out_buffer[..]);
+        let samples_read = codec.decode(&mut out_buffer[..]).unwrap();
         let mean_squared_error = math::compute_mse(&out_buffer, &data);
 
         println!("data   :{:?}", data);
@@ -516,10 +1963,1070 @@ mod test {
 
         for (&input_sample, &decoded_sample) in data.iter().zip(out_buffer.iter()) {
             let close_enough = (input_sample - decoded_sample).abs() < 0.01;
-            assert_eq!(close_enough, true, "accuracy threshold not met");
+            assert!(close_enough, "accuracy threshold not met");
+        }
+    }
+
+    #[test]
+    fn lpc_predictor_is_used_for_tonal_audio() {
+        let data = (0..4096)
+            .flat_map(|k| {
+                let t = k as f32 / 44100.0;
+                [
+                    0.5 * (2.0 * std::f32::consts::PI * 440.0 * t).sin(),
+                    0.25 * (2.0 * std::f32::consts::PI * 660.0 * t).sin(),
+                ]
+            })
+            .collect::<Vec<_>>();
+
+        let mut codec = AdhocCodec::new().with_info(StreamInfo::new(44100, 2));
+        for chunk in data.chunks(1024) {
+            codec.encode(chunk).unwrap();
+        }
+
+        let lpc_frames = (0..codec.frame_header_list.len())
+            .filter_map(|idx| codec.frame_header_list.get(idx))
+            .filter(|header| header.predictor.order > 0)
+            .count();
+        assert!(lpc_frames > 0, "encoder never picked an lpc predictor");
+
+        let mut out_buffer = vec![0.0; data.len()];
+        codec.init();
+        let samples_read = codec.decode(&mut out_buffer[..]).unwrap();
+        assert_eq!(samples_read, data.len());
+
+        for (&input_sample, &decoded_sample) in data.iter().zip(out_buffer.iter()) {
+            let close_enough = (input_sample - decoded_sample).abs() < 0.001;
+            assert!(close_enough, "accuracy threshold not met");
+        }
+    }
+
+    #[test]
+    fn near_mono_stereo_is_decorrelated() {
+        let data = (0..4096)
+            .flat_map(|k| {
+                let t = k as f32 / 44100.0;
+                let s = 0.5 * (2.0 * std::f32::consts::PI * 440.0 * t).sin()
+                    + 0.2 * (2.0 * std::f32::consts::PI * 1250.0 * t).sin();
+                [s, s * 0.98]
+            })
+            .collect::<Vec<_>>();
+
+        let mut codec = AdhocCodec::new().with_info(StreamInfo::new(44100, 2));
+        for chunk in data.chunks(1024) {
+            codec.encode(chunk).unwrap();
+        }
+
+        let decorrelated_frames = (0..codec.frame_header_list.len())
+            .filter_map(|idx| codec.frame_header_list.get(idx))
+            .filter(|header| header.channel_mode != ChannelMode::Independent)
+            .count();
+        assert!(
+            decorrelated_frames > 0,
+            "encoder never decorrelated the channels"
+        );
+
+        let mut out_buffer = vec![0.0; data.len()];
+        codec.init();
+        let samples_read = codec.decode(&mut out_buffer[..]).unwrap();
+        assert_eq!(samples_read, data.len());
+
+        for (&input_sample, &decoded_sample) in data.iter().zip(out_buffer.iter()) {
+            let close_enough = (input_sample - decoded_sample).abs() < 0.001;
+            assert!(close_enough, "accuracy threshold not met");
+        }
+
+        // seeking into a decorrelated frame-block has to land on the same samples
+        let mut seeked = vec![0.0; 512];
+        codec.seek(SeekFrom::Start(50)).unwrap();
+        let samples_read = codec.decode(&mut seeked[..]).unwrap();
+        let offset = ((44100.0 / 1000.0) * 50.0) as usize * 2;
+        assert_eq!(
+            &seeked[0..samples_read],
+            &out_buffer[offset..offset + samples_read]
+        );
+    }
+
+    #[test]
+    fn level_zero_is_bit_exact() {
+        // 16-bit copy of taunt.wav, plus a loud stereo track that hits both rails
+        let mut wav_16bit = Vec::new();
+        WavCodec::load(File::open("./resources/taunt.wav").expect("file not found"))
+            .unwrap()
+            .with_sample_format(SampleFormat::I16)
+            .save_to(&mut wav_16bit)
+            .unwrap();
+
+        let mut loud = WavCodec::new(StreamInfo::new(44100, 2));
+        let mut seq = math::PseudoRandom::new(7);
+        let loud_samples = seq
+            .uniform()
+            .take(20_000)
+            .enumerate()
+            .map(|(k, noise)| match k % 997 {
+                0 => i16::MIN,
+                1 => i16::MAX,
+                _ => (noise * 32000.0) as i16 + (k % 61) as i16,
+            })
+            .collect::<Vec<_>>();
+        loud.encode_i16(&loud_samples).unwrap();
+        let mut wav_loud = Vec::new();
+        loud.save_to(&mut wav_loud).unwrap();
+
+        for wav_bytes in [wav_16bit, wav_loud] {
+            let mut wav_in = WavCodec::load(Cursor::new(wav_bytes.clone())).unwrap();
+            let mut adhoc_codec = AdhocCodec::new()
+                .with_compression_level(0)
+                .with_info(wav_in.info());
+
+            let mut buffer = [0i16; 1024];
+            while let Some(n) = wav_in.decode_i16(&mut buffer).unwrap() {
+                adhoc_codec.encode_i16(&buffer[0..n]).unwrap();
+            }
+
+            let mut adhoc_bytes = Vec::new();
+            adhoc_codec.save_to(&mut adhoc_bytes).unwrap();
+            let mut adhoc_codec = AdhocCodec::load(Cursor::new(adhoc_bytes)).unwrap();
+
+            let mut wav_out = WavCodec::new(wav_in.info());
+            while let Some(n) = adhoc_codec.decode_i16(&mut buffer).unwrap() {
+                wav_out.encode_i16(&buffer[0..n]).unwrap();
+            }
+            let mut wav_out_bytes = Vec::new();
+            wav_out.save_to(&mut wav_out_bytes).unwrap();
+
+            // both files are written by `WavCodec` so the data chunk starts at the same spot
+            const DATA_CHUNK_START: usize = 44;
+            assert_eq!(wav_out_bytes.len(), wav_bytes.len());
+            assert!(
+                wav_out_bytes[DATA_CHUNK_START..] == wav_bytes[DATA_CHUNK_START..],
+                "data chunk changed"
+            );
+        }
+    }
+
+    #[test]
+    fn wide_samples_are_bit_exact() {
+        for (bits_per_sample, channels) in [(24, 2), (24, 1), (32, 2)] {
+            let info = StreamInfo::new(96000, channels).with_bits_per_sample(bits_per_sample);
+            let sample_max = ((1i64 << (bits_per_sample - 1)) - 1) as f64;
+
+            // a tone near full scale with noise in the lower bits, plus both rails
+            let mut seq = math::PseudoRandom::new(99);
+            let samples = seq
+                .uniform()
+                .take(8192 * channels as usize)
+                .enumerate()
+                .map(|(k, noise)| {
+                    let t = (k / channels as usize) as f64 / 96000.0;
+                    let tone = (2.0 * std::f64::consts::PI * 440.0 * t).sin() * 0.9;
+                    match k % 1999 {
+                        0 => -(sample_max as i64) as i32 - 1,
+                        1 => sample_max as i32,
+                        _ => ((tone + noise as f64 * 0.05) * sample_max) as i32,
+                    }
+                })
+                .collect::<Vec<_>>();
+
+            let mut codec = AdhocCodec::new().with_info(info);
+            for chunk in samples.chunks(1000 * channels as usize) {
+                codec.encode_i32(chunk).unwrap();
+            }
+
+            let mut adhoc_bytes = Vec::new();
+            codec.save_to(&mut adhoc_bytes).unwrap();
+            let mut codec = AdhocCodec::load(Cursor::new(adhoc_bytes)).unwrap();
+            assert_eq!(codec.info().bits_per_sample(), bits_per_sample);
+
+            let mut decoded = vec![0; samples.len()];
+            let samples_read = codec.decode_i32(&mut decoded).unwrap().unwrap();
+            assert_eq!(samples_read, samples.len());
+            assert!(decoded == samples, "{}-bit stream changed", bits_per_sample);
+
+            // 16-bit callers get the upper bits
+            codec.seek(SeekFrom::Start(0)).unwrap();
+            let mut decoded_i16 = vec![0i16; 64];
+            codec.decode_i16(&mut decoded_i16).unwrap();
+            for (&wide, &narrow) in samples.iter().zip(decoded_i16.iter()) {
+                assert_eq!((wide >> (bits_per_sample - 16)) as i16, narrow);
+            }
+        }
+    }
+
+    #[test]
+    fn misuse_is_reported_as_errors() {
+        let mut codec = AdhocCodec::new();
+        assert!(matches!(codec.encode(&[0.0; 16]), Err(Error::InfoNotSet)));
+        assert!(matches!(codec.save_to(Vec::new()), Err(Error::InfoNotSet)));
+
+        assert!(matches!(
+            codec.seek(SeekFrom::End(0)),
+            Err(Error::InfoNotSet)
+        ));
+        assert_eq!(codec.info().channels(), 0);
+
+        // a stream without channels can't be encoded or decoded
+        let mut codec = AdhocCodec::new().with_info(StreamInfo::new(8000, 0));
+        assert!(matches!(
+            codec.encode(&[0.0; 16]),
+            Err(Error::UnsupportedFormat(_))
+        ));
+        assert!(matches!(
+            codec.encode_i16(&[0; 16]),
+            Err(Error::UnsupportedFormat(_))
+        ));
+        assert!(matches!(
+            codec.encode_i32(&[0; 16]),
+            Err(Error::UnsupportedFormat(_))
+        ));
+        #[cfg(feature = "parallel")]
+        assert!(matches!(
+            codec.encode_parallel(&[0.0; 16]),
+            Err(Error::UnsupportedFormat(_))
+        ));
+        assert!(matches!(
+            codec.decode_i16(&mut [0; 16]),
+            Err(Error::UnsupportedFormat(_))
+        ));
+        codec.flush();
+    }
+
+    #[test]
+    fn encoding_while_decoding_is_an_error() {
+        let samples = (0..4096)
+            .map(|k| (k * 37 % 2000) as i16 - 1000)
+            .collect::<Vec<_>>();
+        let mut codec = AdhocCodec::new()
+            .with_compression_level(0)
+            .with_frame_size(256)
+            .with_info(StreamInfo::new(8000, 1));
+        codec.encode_i16(&samples).unwrap();
+        let mut file = Vec::new();
+        codec.save_to(&mut file).unwrap();
+
+        let mut codec = AdhocCodec::load(&file[..]).unwrap();
+        let mut decoded = [0i16; 1000];
+        codec.decode_i16(&mut decoded).unwrap();
+        assert!(matches!(
+            codec.encode(&[0.0; 16]),
+            Err(Error::Unsupported(_))
+        ));
+        assert!(matches!(
+            codec.encode_i16(&[0; 16]),
+            Err(Error::Unsupported(_))
+        ));
+        codec.flush();
+
+        // nothing was buffered, decoding carries on where it stopped
+        let mut rest = [0i16; 4096];
+        assert_eq!(
+            codec.decode_i16(&mut rest).unwrap(),
+            Some(samples.len() - decoded.len())
+        );
+        assert_eq!(
+            &rest[0..samples.len() - decoded.len()],
+            &samples[decoded.len()..]
+        );
+
+        codec.init();
+        assert_eq!(codec.encode_i16(&samples[0..16]).unwrap(), 16);
+    }
+
+    #[test]
+    fn seeking_matches_wav_codec() {
+        let info = StreamInfo::new(1000, 2);
+        let samples = (0..4000)
+            .map(|k| ((k as f32 * 0.07).sin() * 9000.0) as i16 ^ (k % 3) as i16)
+            .collect::<Vec<_>>();
+
+        let mut adhoc = AdhocCodec::new().with_info(info);
+        adhoc.encode_i16(&samples).unwrap();
+        adhoc.seek(SeekFrom::Start(0)).unwrap();
+        let mut wav = WavCodec::new(info);
+        wav.encode_i16(&samples).unwrap();
+        wav.seek(SeekFrom::Start(0)).unwrap();
+
+        // at 1000hz a millisecond is exactly one sample per channel
+        let seeks = [
+            SeekFrom::Current(100),
+            SeekFrom::Current(-30),
+            SeekFrom::End(-250),
+            SeekFrom::Current(-5000),
+            SeekFrom::Start(1337),
+            SeekFrom::End(10),
+            SeekFrom::Current(-1),
+            SeekFrom::Start(u64::MAX),
+            SeekFrom::End(-2000),
+        ];
+        let mut adhoc_out = [0; 64];
+        let mut wav_out = [0; 64];
+        for dt in seeks {
+            adhoc.seek(dt).unwrap();
+            wav.seek(dt).unwrap();
+            let adhoc_read = adhoc.decode_i16(&mut adhoc_out).unwrap().unwrap_or(0);
+            let wav_read = wav.decode_i16(&mut wav_out).unwrap().unwrap_or(0);
+            assert_eq!(adhoc_read, wav_read, "{:?}", dt);
+            assert_eq!(&adhoc_out[0..adhoc_read], &wav_out[0..wav_read], "{:?}", dt);
+        }
+
+        adhoc.seek(SeekFrom::End(-1)).unwrap();
+        assert_eq!(adhoc.decode_i16(&mut adhoc_out).unwrap(), Some(2));
+        assert_eq!(&adhoc_out[0..2], &samples[3998..]);
+    }
+
+    #[test]
+    fn sample_accurate_seeking() {
+        let info = StreamInfo::new(44100, 2);
+        let samples = (0..20_000)
+            .map(|k| ((k as f32 * 0.011).sin() * 7000.0) as i16 ^ (k % 7) as i16)
+            .collect::<Vec<_>>();
+
+        let mut adhoc = AdhocCodec::new().with_info(info);
+        adhoc.encode_i16(&samples).unwrap();
+        let mut wav = WavCodec::new(info);
+        wav.encode_i16(&samples).unwrap();
+
+        for codec in [&mut adhoc as &mut dyn Streamable, &mut wav] {
+            assert_eq!(codec.duration_in_samples(), 10_000);
+            assert_eq!(codec.duration(), Duration::from_nanos(226_757_369));
+
+            let mut out = [0.0; 30];
+            for sample in [0, 1, 4095, 4096, 9999, 10_000, 1 << 40] {
+                codec.seek_to_sample(sample).unwrap();
+                let sample = sample.min(10_000);
+                assert_eq!(codec.position_in_samples(), sample);
+
+                let samples_read = codec.decode(&mut out).unwrap().unwrap_or(0);
+                assert_eq!(samples_read, 30.min(20_000 - sample as usize * 2));
+                assert_eq!(
+                    codec.position_in_samples(),
+                    sample + samples_read as u64 / 2
+                );
+                let expected = &samples[sample as usize * 2..][..samples_read];
+                for (&decoded, &expected) in out.iter().zip(expected) {
+                    assert_eq!((decoded * i16::MAX as f32).round() as i16, expected);
+                }
+            }
+        }
+
+        // the cached count follows frames being encoded over
+        adhoc.seek_to_sample(0).unwrap();
+        adhoc.encode_i16(&samples[0..500]).unwrap();
+        assert_eq!(adhoc.duration_in_samples(), 250);
+        loaded_duration(&mut adhoc, 250);
+
+        let mut writer = AdhocWriter::new(Vec::new()).with_info(info);
+        writer.encode_i16(&samples).unwrap();
+        assert_eq!(writer.duration_in_samples(), 10_000);
+        let bytes = writer.finalize().unwrap();
+
+        let mut reader = AdhocReader::new(Cursor::new(&bytes)).unwrap();
+        let mut out = vec![0.0; 20_000];
+        while reader.decode(&mut out).unwrap().is_some() {}
+        assert_eq!(reader.position_in_samples(), 10_000);
+        assert_eq!(reader.duration_in_samples(), 10_000);
+    }
+
+    #[test]
+    fn decode_range_matches_sequential_decoding() {
+        let info = StreamInfo::new(48000, 2);
+        let data = (0..30_000)
+            .map(|k| (k as f32 * 0.021).sin() * if k % 2 == 0 { 0.6 } else { 0.3 })
+            .collect::<Vec<_>>();
+        let mut codec = AdhocCodec::new().with_compression_level(2).with_info(info);
+        for chunk in data.chunks(4096) {
+            codec.encode(chunk).unwrap();
+        }
+        codec.seek(SeekFrom::Start(0)).unwrap();
+
+        let mut decoded = vec![0.0; data.len()];
+        assert_eq!(codec.decode(&mut decoded).unwrap(), data.len());
+        codec.seek(SeekFrom::Start(0)).unwrap();
+
+        // play a bit so there is a cursor to disturb
+        let mut playback = vec![0.0; 1000];
+        codec.decode(&mut playback).unwrap();
+
+        let mut out = vec![0.0; 10_000];
+        let ranges = [
+            (0, 1),
+            (0, 5000),
+            (2047, 2049),
+            (2048, 6144),
+            (7000, 7100),
+            (14_990, 20_000),
+        ];
+        for (start, end) in ranges {
+            let samples_read = codec.decode_range(start, end, &mut out).unwrap();
+            let end = end.min(15_000) as usize;
+            assert_eq!(
+                samples_read,
+                (end - start as usize) * 2,
+                "{}..{}",
+                start,
+                end
+            );
+            assert_eq!(&out[0..samples_read], &decoded[start as usize * 2..end * 2]);
+        }
+        assert_eq!(codec.decode_range(15_000, 16_000, &mut out).unwrap(), 0);
+        assert_eq!(codec.decode_range(10, 10, &mut out).unwrap(), 0);
+
+        // output is capped by the buffer
+        assert_eq!(codec.decode_range(100, 15_000, &mut out[0..7]).unwrap(), 6);
+        assert_eq!(&out[0..6], &decoded[200..206]);
+
+        // playback carries on as if nothing happened
+        assert_eq!(codec.position_in_samples(), 500);
+        codec.decode(&mut playback).unwrap();
+        assert_eq!(&playback[..], &decoded[1000..2000]);
+    }
+
+    #[test]
+    fn frames_do_not_depend_on_chunking() {
+        let info = StreamInfo::new(44100, 2);
+        let data = (0..2 * 70_000)
+            .map(|k| (k as f32 * 0.0031).sin() * if k % 2 == 0 { 0.8 } else { 0.4 })
+            .collect::<Vec<_>>();
+
+        let encode_chunked = |chunk_size: usize| {
+            let mut codec = AdhocCodec::new().with_frame_size(4096).with_info(info);
+            assert_eq!(codec.encode(&[]).unwrap(), 0);
+            for chunk in data.chunks(chunk_size) {
+                codec.encode(chunk).unwrap();
+            }
+            codec.flush();
+            codec
+        };
+
+        let reference = encode_chunked(data.len());
+        for chunk_size in [2, 334, 8192, 100_000] {
+            let codec = encode_chunked(chunk_size);
+            assert_eq!(
+                codec.stream.chunks(),
+                reference.stream.chunks(),
+                "{}",
+                chunk_size
+            );
+            assert_eq!(
+                codec.frame_header_list.len(),
+                reference.frame_header_list.len()
+            );
+        }
+
+        let sizes = reference
+            .frame_header_list
+            .frame_block_sizes(2)
+            .collect::<Vec<_>>();
+        assert_eq!(sizes.len(), 18);
+        assert!(sizes[0..17].iter().all(|&size| size == 4096));
+        assert_eq!(sizes[17], 70_000 - 17 * 4096);
+
+        // frame sizes are stored in 16 bits, a single huge encode used to overflow them
+        let mut codec = AdhocCodec::new().with_frame_size(1 << 20).with_info(info);
+        codec.encode(&data).unwrap();
+        codec.seek(SeekFrom::Start(0)).unwrap();
+        assert_eq!(codec.duration_in_samples(), 70_000);
+        let mut decoded = vec![0.0; data.len()];
+        assert_eq!(codec.decode(&mut decoded).unwrap(), data.len());
+        let mut expected = reference;
+        let mut decoded_reference = vec![0.0; data.len()];
+        expected.seek(SeekFrom::Start(0)).unwrap();
+        expected.decode(&mut decoded_reference).unwrap();
+        let max_error = decoded
+            .iter()
+            .zip(decoded_reference.iter())
+            .map(|(a, b)| (a - b).abs())
+            .fold(0.0, f32::max);
+        assert!(max_error < 0.01, "{}", max_error);
+    }
+
+    #[test]
+    fn partition_search_shrinks_transients() {
+        // silence, a click, silence and then a burst of noise, all inside 4096-sample frames
+        let channel = (0..40_000)
+            .map(|k| match k % 8192 {
+                0..=1999 => 0,
+                2000..=2015 => 20_000,
+                2016..=5999 => ((k as f32 * 0.05).sin() * 50.0) as i16,
+                _ => ((k as u32).wrapping_mul(2_654_435_761) >> 18) as i16 - 8192,
+            })
+            .collect::<Vec<_>>();
+
+        for channels in [1, 2] {
+            let samples = channel
+                .iter()
+                .flat_map(|&s| std::iter::repeat_n(s, channels as usize))
+                .collect::<Vec<_>>();
+            let encode = |depth: u32| {
+                let mut codec = AdhocCodec::new()
+                    .with_frame_size(4096)
+                    .with_partition_depth(depth)
+                    .with_info(StreamInfo::new(44100, channels));
+                codec.encode_i16(&samples).unwrap();
+                codec.flush();
+                codec
+            };
+
+            let fixed = encode(0);
+            let mut adaptive = encode(MAX_PARTITION_DEPTH + 10);
+            assert!(
+                adaptive.filesize_upperbound() < fixed.filesize_upperbound(),
+                "{} {}",
+                adaptive.filesize_upperbound(),
+                fixed.filesize_upperbound()
+            );
+            assert!(adaptive.frame_header_list.len() > fixed.frame_header_list.len());
+            let sizes = adaptive
+                .frame_header_list
+                .frame_block_sizes(channels as usize);
+            assert!(sizes
+                .into_iter()
+                .all(|size| size >= MIN_PARTITION_SIZE as u64));
+
+            adaptive.seek(SeekFrom::Start(0)).unwrap();
+            let mut decoded = vec![0; samples.len()];
+            assert_eq!(
+                adaptive.decode_i16(&mut decoded).unwrap(),
+                Some(samples.len())
+            );
+            assert_eq!(decoded, samples);
+        }
+    }
+
+    #[test]
+    fn target_bitrate_is_reached() {
+        let info = StreamInfo::new(44100, 1);
+        let samples = (0..88_200)
+            .map(|k| {
+                let t = k as f32 / 44100.0;
+                let noise = ((k as u32).wrapping_mul(2_654_435_761) >> 20) as f32 / 4096.0 - 0.5;
+                0.4 * (t * 2.0 * std::f32::consts::PI * 220.0).sin() + 0.2 * noise
+            })
+            .collect::<Vec<_>>();
+
+        let lossless = {
+            let mut codec = AdhocCodec::new().with_info(info);
+            codec.encode(&samples).unwrap();
+            codec.flush();
+            codec.encode_report()
+        };
+        assert_eq!(lossless.mean_quant_shift, 0.0);
+        assert_eq!(lossless.target_bitrate_kbps, None);
+        assert_eq!(lossless.samples, 88_200);
+
+        let mut previous_error = 0.0;
+        // residuals cost at least 3 bits a sample, so ~140kbps is as low as 44.1kHz mono goes
+        for kbps in [400, 300, 200] {
+            let mut codec = AdhocCodec::new().with_target_bitrate(kbps).with_info(info);
+            for chunk in samples.chunks(5000) {
+                codec.encode(chunk).unwrap();
+            }
+            codec.flush();
+
+            let report = codec.encode_report();
+            assert!(report.bitrate_kbps < lossless.bitrate_kbps);
+            let error = (report.bitrate_kbps - kbps as f64).abs() / kbps as f64;
+            assert!(error < 0.1, "{} kbps: got {:.1}", kbps, report.bitrate_kbps);
+            assert!(report.mean_quant_shift > 0.0);
+
+            // shifts are in the frame headers, so a saved file decodes exactly like the codec does
+            let mut bytes = Vec::new();
+            codec.save_to(&mut bytes).unwrap();
+            let flags = u16::from_le_bytes([bytes[6], bytes[7]]);
+            assert_ne!(flags & FileHeader::FLAG_FRAME_QUANTIZATION, 0);
+
+            let mut decoded = vec![0.0; samples.len()];
+            codec.seek(SeekFrom::Start(0)).unwrap();
+            codec.decode(&mut decoded).unwrap();
+            let mut loaded = AdhocCodec::load(Cursor::new(&bytes)).unwrap();
+            let mut reloaded = vec![0.0; samples.len()];
+            loaded.decode(&mut reloaded).unwrap();
+            assert_eq!(decoded, reloaded);
+
+            // fewer bits, more noise
+            let quantization_error = math::compute_mse(&samples, &decoded);
+            assert!(
+                quantization_error > previous_error,
+                "{}",
+                quantization_error
+            );
+            assert!(quantization_error < 0.01, "{}", quantization_error);
+            previous_error = quantization_error;
+        }
+    }
+
+    #[test]
+    fn encode_report_without_info() {
+        let report = AdhocCodec::new().encode_report();
+        assert_eq!(report.samples, 0);
+        assert_eq!(report.bitrate_kbps, 0.0);
+    }
+
+    #[test]
+    fn target_snr_is_met() {
+        let info = StreamInfo::new(44100, 1);
+        let samples = (0..88_200)
+            .map(|k| {
+                let t = k as f32 / 44100.0;
+                // a fade in, so quiet frames need finer quantization than loud ones
+                let envelope = (t * 0.5).min(1.0);
+                envelope * 0.6 * (t * 2.0 * std::f32::consts::PI * 330.0).sin()
+            })
+            .collect::<Vec<_>>();
+
+        let mut previous_shift = f64::INFINITY;
+        for db in [30.0, 50.0, 70.0] {
+            let mut codec = AdhocCodec::new().with_target_snr(db).with_info(info);
+            codec.encode(&samples).unwrap();
+            codec.flush();
+
+            let report = codec.encode_report();
+            assert_eq!(report.target_snr_db, Some(db as f64));
+            assert!(
+                report.snr_db >= db as f64,
+                "{} dB: got {:.1}",
+                db,
+                report.snr_db
+            );
+            assert!(report.max_quant_shift > 0);
+            // more quality, fewer bits quantized away
+            assert!(report.mean_quant_shift < previous_shift);
+            previous_shift = report.mean_quant_shift;
+
+            // the report agrees with what a decoder actually gets back
+            let mut decoded = vec![0.0; samples.len()];
+            codec.seek(SeekFrom::Start(0)).unwrap();
+            codec.decode(&mut decoded).unwrap();
+            let signal = samples.iter().map(|&s| (s * s) as f64).sum::<f64>();
+            let noise = math::compute_mse(&samples, &decoded) as f64 * samples.len() as f64;
+            assert!(snr_db(signal, noise) > db as f64 - 1.0);
+        }
+
+        // quality wins over the budget
+        let mut codec = AdhocCodec::new()
+            .with_target_bitrate(150)
+            .with_target_snr(60.0)
+            .with_info(info);
+        codec.encode(&samples).unwrap();
+        codec.flush();
+        assert!(codec.encode_report().snr_db >= 60.0);
+    }
+
+    #[test]
+    fn quantization_callback_varies_quality() {
+        use std::sync::{Arc, Mutex};
+
+        let info = StreamInfo::new(22050, 1);
+        let samples = (0..8192)
+            .map(|k| ((k as f32 * 0.021).sin() * 12_000.0) as i16)
+            .collect::<Vec<_>>();
+
+        // the first half gets quantized hard, the second half is left alone
+        let encode = |parallel: bool| {
+            let position_list = Arc::new(Mutex::new(Vec::new()));
+            let seen = position_list.clone();
+            let mut codec = AdhocCodec::new()
+                .with_frame_size(1024)
+                .with_segment_size(2048)
+                .with_quantization_callback(move |frame| {
+                    seen.lock().unwrap().push(frame.position);
+                    assert_eq!(frame.suggested_shift, 0);
+                    if frame.position < 4096 {
+                        8
+                    } else {
+                        0
+                    }
+                })
+                .with_info(info);
+            if parallel {
+                let data = samples
+                    .iter()
+                    .map(|&s| s as f32 / 32767.0)
+                    .collect::<Vec<_>>();
+                #[cfg(feature = "parallel")]
+                codec.encode_parallel(&data).unwrap();
+                #[cfg(not(feature = "parallel"))]
+                codec.encode(&data).unwrap();
+            } else {
+                codec.encode_i16(&samples).unwrap();
+            }
+            codec.flush();
+            let mut position_list = position_list.lock().unwrap().clone();
+            position_list.sort();
+            (codec, position_list)
+        };
+
+        let (mut codec, position_list) = encode(false);
+        assert_eq!(
+            position_list,
+            (0..8).map(|k| k * 1024).collect::<Vec<u64>>()
+        );
+        assert_eq!(codec.encode_report().max_quant_shift, 8);
+
+        let mut bytes = Vec::new();
+        codec.save_to(&mut bytes).unwrap();
+        let mut decoded = vec![0; samples.len()];
+        let mut loaded = AdhocCodec::load(Cursor::new(&bytes)).unwrap();
+        assert_eq!(
+            loaded.decode_i16(&mut decoded).unwrap(),
+            Some(samples.len())
+        );
+        assert_eq!(&decoded[4096..], &samples[4096..]);
+        assert_ne!(&decoded[..4096], &samples[..4096]);
+        for (&d, &s) in decoded.iter().zip(samples.iter()) {
+            assert!((d as i32 - s as i32).abs() <= 128, "{} {}", d, s);
+        }
+
+        // segments see the same positions serial encoding does
+        let (_, parallel_position_list) = encode(true);
+        assert_eq!(parallel_position_list, position_list);
+
+        let mut writer = AdhocWriter::new(Vec::new())
+            .with_quantization_callback(|_| 3)
+            .with_info(info);
+        writer.encode_i16(&samples).unwrap();
+        let bytes = writer.finalize().unwrap();
+        let flags = u16::from_le_bytes([bytes[6], bytes[7]]);
+        assert_ne!(flags & FileHeader::FLAG_FRAME_QUANTIZATION, 0);
+        let mut loaded = AdhocCodec::load(Cursor::new(&bytes)).unwrap();
+        assert_eq!(
+            loaded.decode_i16(&mut decoded).unwrap(),
+            Some(samples.len())
+        );
+        assert_ne!(decoded, samples);
+    }
+
+    #[test]
+    fn older_versions_still_open() {
+        let info = StreamInfo::new(22050, 2);
+        let samples = (0..20_000)
+            .map(|k| ((k as f32 * 0.01).sin() * 9000.0) as i16)
+            .collect::<Vec<_>>();
+        let mut codec = AdhocCodec::new().with_info(info);
+        codec.encode_i16(&samples).unwrap();
+        let mut current = Vec::new();
+        codec.save_to(&mut current).unwrap();
+
+        // the headers end with the channel modes, the quantization shifts and the header cursor,
+        // both lists are a vec of nibbles followed by the nibble count
+        let num_frames = codec.frame_header_list.len();
+        let headers_len = bincode_options(u64::MAX)
+            .serialized_size(&codec.frame_header_list)
+            .unwrap() as usize;
+        let headers_end = FileHeader::LEN as usize + 4 + headers_len;
+        let shifts_len = 8 + num_frames.div_ceil(2) + 4;
+        let channel_modes_start = headers_end - 4 - 2 * shifts_len;
+
+        // versions 1 and 2 stored lpc coefficient offsets ahead of the channel modes
+        let mut coef_offset_list = (num_frames as u64).to_le_bytes().to_vec();
+        let mut coef_offset = 0u32;
+        for header in (0..num_frames).filter_map(|idx| codec.frame_header_list.get(idx)) {
+            coef_offset_list.extend_from_slice(&coef_offset.to_le_bytes());
+            coef_offset += header.predictor.order as u32;
+        }
+
+        for version in [1u16, 2] {
+            let mut bytes = current.clone();
+            // version 1 headers have no quantization shifts
+            if version == 1 {
+                bytes.drain(headers_end - 4 - shifts_len..headers_end - 4);
+            }
+            bytes.splice(
+                channel_modes_start..channel_modes_start,
+                coef_offset_list.clone(),
+            );
+            bytes[4..6].copy_from_slice(&version.to_le_bytes());
+
+            let mut loaded = AdhocCodec::load(Cursor::new(&bytes)).unwrap();
+            let mut decoded = vec![0; samples.len()];
+            assert_eq!(
+                loaded.decode_i16(&mut decoded).unwrap(),
+                Some(samples.len())
+            );
+            assert_eq!(decoded, samples);
+
+            let mut reader = AdhocReader::new(Cursor::new(&bytes)).unwrap();
+            let mut decoded = vec![0; samples.len()];
+            assert_eq!(
+                reader.decode_i16(&mut decoded).unwrap(),
+                Some(samples.len())
+            );
+            assert_eq!(decoded, samples);
+        }
+    }
+
+    #[test]
+    fn oversized_frames_are_refused() {
+        let mut stream = AudioStream::new();
+        let mut frame_headers = FrameHeaders::new();
+        let channel = ChannelMode::Independent.coded_channel(0, &[[0; 3]], 16);
+        let samples = vec![0; u16::MAX as usize + 1];
+
+        let mut codec = FrameCodec::new();
+        assert!(matches!(
+            codec.encode_frame(&mut stream, &mut frame_headers, &samples, channel, 0),
+            Err(Error::Unsupported(_))
+        ));
+        assert_eq!(stream.bit_cursor(), 0);
+        assert_eq!(frame_headers.len(), 0);
+
+        codec
+            .encode_frame(&mut stream, &mut frame_headers, &samples[1..], channel, 0)
+            .unwrap();
+        assert_eq!(frame_headers.get(0).unwrap().size, u16::MAX);
+    }
+
+    #[test]
+    fn tiny_init_frames_round_trip() {
+        for len in 1..=3 {
+            let samples = (0..len).map(|k| k * 1000 - 700).collect::<Vec<i16>>();
+            let mut codec = AdhocCodec::new()
+                .with_compression_level(0)
+                .with_frame_size(len as usize)
+                .with_info(StreamInfo::new(8000, 1));
+            codec.encode_i16(&samples).unwrap();
+            codec.encode_i16(&samples).unwrap();
+            codec.seek(SeekFrom::Start(0)).unwrap();
+
+            let mut decoded = [0; 8];
+            assert_eq!(
+                codec.decode_i16(&mut decoded).unwrap(),
+                Some(2 * len as usize)
+            );
+            assert_eq!(&decoded[0..len as usize], &samples[..]);
+            assert_eq!(&decoded[len as usize..2 * len as usize], &samples[..]);
+        }
+    }
+
+    #[test]
+    #[cfg(feature = "parallel")]
+    fn parallel_segments_decode_like_serial_encoding() {
+        let info = StreamInfo::new(44100, 2);
+        let data = (0..2 * 10_301)
+            .map(|k| (k as f32 * 0.013).sin() * if k % 2 == 0 { 0.8 } else { 0.5 })
+            .collect::<Vec<_>>();
+
+        let mut serial = AdhocCodec::new().with_info(info);
+        for frame in data.chunks(778) {
+            serial.encode(frame).unwrap();
+        }
+        serial.seek(SeekFrom::Start(0)).unwrap();
+        let mut expected = vec![0; data.len()];
+        serial.decode_i16(&mut expected).unwrap();
+
+        let mut parallel = AdhocCodec::new().with_segment_size(1000).with_info(info);
+        assert_eq!(parallel.encode_parallel(&data[0..4000]).unwrap(), 4000);
+        assert_eq!(
+            parallel.encode_parallel(&data[4000..]).unwrap(),
+            data.len() - 4000
+        );
+        assert_eq!(parallel.duration_in_samples(), 10_301);
+
+        let mut writer = AdhocWriter::new(Vec::new())
+            .with_segment_size(1000)
+            .with_packet_size(256)
+            .with_info(info);
+        writer.encode_parallel(&data[0..4000]).unwrap();
+        writer.encode_parallel(&data[4000..]).unwrap();
+        let streamed = AdhocCodec::load(Cursor::new(writer.finalize().unwrap())).unwrap();
+
+        for mut codec in [parallel, streamed] {
+            // every segment restarts with an init frame
+            let init_frames = (0..codec.frame_header_list.len())
+                .filter(|&idx| codec.frame_header_list.get(idx).unwrap().is_init)
+                .count();
+            assert_eq!(init_frames, 2 * 11);
+
+            codec.seek(SeekFrom::Start(0)).unwrap();
+            let mut decoded = vec![0; data.len()];
+            assert_eq!(codec.decode_i16(&mut decoded).unwrap(), Some(data.len()));
+            assert_eq!(decoded, expected);
+
+            codec.seek_to_sample(2999).unwrap();
+            let mut samples = [0; 4];
+            codec.decode_i16(&mut samples).unwrap();
+            assert_eq!(&samples[..], &expected[5998..6002]);
         }
     }
-    
+
+    #[test]
+    #[cfg(feature = "parallel")]
+    fn decode_all_parallel_matches_sequential_decoding() {
+        let info = StreamInfo::new(32000, 3);
+        let data = (0..3 * 20_000)
+            .map(|k| (k as f32 * 0.007).sin() * (k % 3) as f32 * 0.3)
+            .collect::<Vec<_>>();
+
+        for level in [0, 4] {
+            let mut codec = AdhocCodec::new()
+                .with_compression_level(level)
+                .with_segment_size(1500)
+                .with_info(info);
+            for chunk in data.chunks(999) {
+                codec.encode(chunk).unwrap();
+            }
+            codec.seek(SeekFrom::Start(0)).unwrap();
+
+            let mut expected = vec![0.0; data.len()];
+            assert_eq!(codec.decode(&mut expected).unwrap(), data.len());
+            assert_eq!(codec.decode_all_parallel().unwrap(), expected);
+        }
+
+        let empty = AdhocCodec::new().with_info(info);
+        assert!(empty.decode_all_parallel().unwrap().is_empty());
+        assert!(matches!(
+            AdhocCodec::new().decode_all_parallel(),
+            Err(Error::InfoNotSet)
+        ));
+    }
+
+    #[allow(dead_code)]
+    fn loaded_duration(codec: &mut AdhocCodec, expected: u64) {
+        let mut bytes = Vec::new();
+        codec.save_to(&mut bytes).unwrap();
+        let loaded = AdhocCodec::load(Cursor::new(&bytes)).unwrap();
+        assert_eq!(loaded.duration_in_samples(), expected);
+        let reader = AdhocReader::new(Cursor::new(&bytes)).unwrap();
+        assert_eq!(reader.duration_in_samples(), expected);
+    }
+
+    #[test]
+    fn corrupt_files_never_panic() {
+        let mut codec = AdhocCodec::new()
+            .with_compression_level(1)
+            .with_info(StreamInfo::new(44100, 2));
+        let data = (0..4096)
+            .map(|k| (k as f32 * 0.031).sin() * if k % 2 == 0 { 0.5 } else { 0.45 })
+            .collect::<Vec<_>>();
+        codec.encode(&data).unwrap();
+
+        let mut bytes = Vec::new();
+        codec.save_to(&mut bytes).unwrap();
+
+        // a limit smaller than the file is refused up front
+        assert!(matches!(
+            AdhocCodec::load_with_limit(Cursor::new(bytes.clone()), 64),
+            Err(Error::LimitExceeded)
+        ));
+
+        // a huge length prefix only fails once the data runs out
+        let mut huge_prefix = bytes[0..FileHeader::LEN as usize + 4].to_vec();
+        huge_prefix.extend_from_slice(&(1u64 << 60).to_le_bytes());
+        assert!(AdhocCodec::load(Cursor::new(huge_prefix)).is_err());
+
+        // the same stream in the layout `AdhocWriter` uses
+        let mut writer = AdhocWriter::new(Vec::new())
+            .with_compression_level(1)
+            .with_info(StreamInfo::new(44100, 2))
+            .with_packet_size(256);
+        for chunk in data.chunks(512) {
+            writer.encode(chunk).unwrap();
+        }
+        let streamed_bytes = writer.finalize().unwrap();
+
+        let mut seq = math::PseudoRandom::new(7);
+        let mut out_buffer = vec![0.0; 1024];
+        for trial in 0..400 {
+            let source = if trial % 2 == 0 {
+                &bytes
+            } else {
+                &streamed_bytes
+            };
+            let mut corrupted = source.clone();
+            let flips = seq.uniform().take(4).collect::<Vec<_>>();
+            for pair in flips.chunks(2) {
+                let idx = (pair[0] * corrupted.len() as f32) as usize % corrupted.len();
+                corrupted[idx] ^= (pair[1] * 255.0) as u8 | 1;
+            }
+            if trial % 8 < 2 {
+                let len = (seq.uniform().next().unwrap() * corrupted.len() as f32) as usize;
+                corrupted.truncate(len);
+            }
+
+            if let Ok(mut reader) = AdhocReader::new(Cursor::new(&corrupted)) {
+                while let Ok(Some(_)) = reader.decode(&mut out_buffer) {}
+            }
+
+            let mut codec = match AdhocCodec::load(Cursor::new(corrupted)) {
+                Ok(codec) => codec,
+                Err(_) => continue,
+            };
+            while let Ok(Some(_)) = Streamable::decode(&mut codec, &mut out_buffer) {}
+            let _ = codec.seek(SeekFrom::Start(20));
+            let _ = Streamable::decode(&mut codec, &mut out_buffer);
+        }
+    }
+
+    #[test]
+    fn files_start_with_a_versioned_header() {
+        let data = [0.0, 0.1, 0.2, 0.3, 0.2, 0.1, 0.0, -0.1];
+        let mut codec = AdhocCodec::new().with_info(StreamInfo::new(44100, 1));
+        codec.encode(&data).unwrap();
+
+        let mut bytes = Vec::new();
+        codec.save_to(&mut bytes).unwrap();
+        assert_eq!(&bytes[0..4], &FileHeader::MAGIC);
+        assert!(AdhocCodec::load(Cursor::new(bytes.clone())).is_ok());
+
+        // files from a newer version of the format are rejected
+        let mut future = bytes.clone();
+        future[4..6].copy_from_slice(&(FileHeader::VERSION + 1).to_le_bytes());
+        assert!(matches!(
+            AdhocCodec::load(Cursor::new(future)),
+            Err(Error::UnsupportedFormat(_))
+        ));
+
+        // so are files using features this version doesn't know about
+        let mut unknown_flags = bytes;
+        unknown_flags[6..8].copy_from_slice(&0x8000u16.to_le_bytes());
+        assert!(matches!(
+            AdhocCodec::load(Cursor::new(unknown_flags)),
+            Err(Error::UnsupportedFormat(_))
+        ));
+
+        assert!(matches!(
+            AdhocCodec::load(Cursor::new(b"RIFF garbage".to_vec())),
+            Err(Error::BadMagic)
+        ));
+    }
+
+    #[test]
+    fn legacy_files_still_open() {
+        // written by the original headerless format: stereo 44.1kHz, 440Hz left and 660Hz right, level 0
+        let mut codec = AdhocCodec::load(
+            File::open("./resources/legacy_v0.adhoc").expect("legacy_v0.adhoc missing"),
+        )
+        .expect("legacy file failed to migrate");
+
+        let info = codec.info();
+        assert_eq!((info.frequency(), info.channels()), (44100, 2));
+
+        let mut out_buffer = vec![0.0; 4096 * 2];
+        let samples_read = codec.decode(&mut out_buffer[..]).unwrap();
+        assert_eq!(samples_read, out_buffer.len());
+
+        for (k, frame) in out_buffer.chunks(2).enumerate() {
+            let t = k as f32 / 44100.0;
+            let left = 0.5 * (2.0 * std::f32::consts::PI * 440.0 * t).sin();
+            let right = 0.25 * (2.0 * std::f32::consts::PI * 660.0 * t).sin();
+            assert!(
+                (frame[0] - left).abs() < 0.001,
+                "left channel drifted at {}",
+                k
+            );
+            assert!(
+                (frame[1] - right).abs() < 0.001,
+                "right channel drifted at {}",
+                k
+            );
+        }
+
+        // re-saving upgrades the file
+        let mut bytes = Vec::new();
+        codec.save_to(&mut bytes).unwrap();
+        assert_eq!(&bytes[0..4], &FileHeader::MAGIC);
+    }
+
     #[test]
     fn re_encode() {
         let mut wav_data =
@@ -529,22 +3036,26 @@ mod test {
         let mut adhoc_codec = AdhocCodec::new().with_compression_level(4);
 
         adhoc_codec.set_info(wav_data.info());
-        while let Some(n) = wav_data.decode(&mut buffer) {
-            adhoc_codec.encode(&buffer[0..n]);
+        while let Some(n) = wav_data.decode(&mut buffer).unwrap() {
+            adhoc_codec.encode(&buffer[0..n]).unwrap();
         }
 
-        adhoc_codec.seek(SeekFrom::Start(0));
-        adhoc_codec.save_to(File::create("./resources/taunt.adhoc").unwrap());
+        adhoc_codec.seek(SeekFrom::Start(0)).unwrap();
+        adhoc_codec
+            .save_to(File::create("./resources/taunt.adhoc").unwrap())
+            .unwrap();
 
         //convert compressed audio back to wav so i can listen
-        adhoc_codec.seek(SeekFrom::Start(0));
+        adhoc_codec.seek(SeekFrom::Start(0)).unwrap();
         let mut decompressed = WavCodec::new(wav_data.info());
-        while let Some(n) = <AdhocCodec as Streamable>::decode(&mut adhoc_codec, &mut buffer) {
+        while let Some(n) =
+            <AdhocCodec as Streamable>::decode(&mut adhoc_codec, &mut buffer).unwrap()
+        {
             for e in &mut buffer {
                 *e *= -1.0;
             }
 
-            decompressed.encode(&buffer[0..n]);
+            decompressed.encode(&buffer[0..n]).unwrap();
         }
 
         decompressed
@@ -0,0 +1,335 @@
+use super::*;
+
+use std::sync::Arc;
+
+/// # Description
+/// Compressed audio that never changes once it's been created, meant to be put in an `Arc`
+/// and shared between any number of `AdhocDecoder`s
+/// ## Comments
+/// - `Send + Sync`, so decoders can live on different threads
+/// - decoders only hold per-channel state and a position, the bitstream is never copied
+/// ## Example
+/// ```
+/// use adhoc_audio::{AdhocAsset, AdhocCodec, StreamInfo, Streamable};
+/// use std::sync::Arc;
+///
+/// let mut codec = AdhocCodec::new().with_info(StreamInfo::new(44100, 1));
+/// codec.encode(&[0.5; 4096]).unwrap();
+///
+/// let asset = Arc::new(AdhocAsset::from(codec));
+/// let mut voices = (0..8).map(|_| asset.decoder()).collect::<Vec<_>>();
+///
+/// let mut samples = [0.0; 256];
+/// for voice in voices.iter_mut() {
+///     voice.decode(&mut samples).unwrap();
+/// }
+/// ```
+pub struct AdhocAsset {
+    frame_header_list: FrameHeaders,
+    /// the compressed audio and its `StreamInfo`
+    stream: AudioStream,
+    /// needed to undo the quantization, the rest of the encoder's configuration isn't kept
+    compression_level: u32,
+    scale: f32,
+    /// length of the stream in samples per channel
+    sample_count: u64,
+}
+
+impl AdhocAsset {
+    /// # Description
+    /// same as `AdhocCodec::load(..)`
+    pub fn load<Resource>(res: Resource) -> Result<Self, Error>
+    where
+        Resource: Read,
+    {
+        AdhocCodec::load(res).map(Self::from)
+    }
+
+    /// # Description
+    /// creates a new decoder that starts at the beginning of the stream
+    pub fn decoder(self: &Arc<Self>) -> AdhocDecoder {
+        AdhocDecoder::new(self.clone())
+    }
+
+    /// # Description
+    /// same as `AdhocCodec::info()`, `StreamInfo::default()` if the codec never had any
+    pub fn info(&self) -> StreamInfo {
+        self.stream.info().unwrap_or_default()
+    }
+
+    /// # Description
+    /// length of the stream in samples per channel
+    pub fn duration_in_samples(&self) -> u64 {
+        self.sample_count
+    }
+
+    /// # Description
+    /// see `AdhocCodec::decode_range(..)`
+    pub fn decode_range(&self, start: u64, end: u64, samples: &mut [f32]) -> Result<usize, Error> {
+        self.compressed().decode_range(start, end, samples)
+    }
+
+    /// # Description
+    /// see `AdhocCodec::decode_all_parallel(..)`
+    /// ## Comments
+    /// - runs are `DEFAULT_SEGMENT_SIZE` samples long, the asset doesn't keep the codec's segment size
+    #[cfg(feature = "parallel")]
+    pub fn decode_all_parallel(&self) -> Result<Vec<f32>, Error> {
+        self.compressed().decode_all_parallel(DEFAULT_SEGMENT_SIZE)
+    }
+
+    /// # Description
+    /// turns the asset back into a codec, the stream is not copied
+    /// ## Comments
+    /// - the codec starts out with the default encoder configuration, only the compression level is kept
+    pub fn into_codec(self) -> AdhocCodec {
+        let mut codec = AdhocCodec::new().with_compression_level(self.compression_level);
+        if let Some(info) = self.stream.info() {
+            codec.set_info(info);
+        }
+        codec.stream = self.stream;
+        codec.frame_header_list = self.frame_header_list;
+        codec.sample_count = self.sample_count;
+        codec.init();
+        codec
+    }
+
+    fn compressed(&self) -> CompressedStream<'_> {
+        CompressedStream {
+            stream: &self.stream,
+            frame_header_list: &self.frame_header_list,
+            compression_level: self.compression_level,
+            scale: self.scale,
+            sample_count: self.sample_count,
+        }
+    }
+}
+
+impl From<AdhocCodec> for AdhocAsset {
+    /// buffered samples are flushed, then everything but the compressed audio gets dropped
+    fn from(mut codec: AdhocCodec) -> Self {
+        codec.flush();
+        Self {
+            frame_header_list: codec.frame_header_list,
+            stream: codec.stream,
+            compression_level: codec.compression_level,
+            scale: codec.scale,
+            sample_count: codec.sample_count,
+        }
+    }
+}
+
+/// # Description
+/// A cursor into an `AdhocAsset`, decodes the asset independently of every other decoder
+/// ## Comments
+/// - holds nothing but a `FrameCodec` per channel and where it is in the stream, so they are cheap to create
+pub struct AdhocDecoder {
+    asset: Arc<AdhocAsset>,
+    channel_list: Vec<FrameCodec>,
+    /// index of the frame header that gets decoded next
+    frame: usize,
+    /// where in the bitstream the next frame-block starts
+    bit_cursor: u128,
+    /// number of samples (per channel) decoded so far
+    position: u64,
+}
+
+impl AdhocDecoder {
+    pub fn new(asset: Arc<AdhocAsset>) -> Self {
+        let channels = asset.stream.info().map_or(0, |info| info.channels());
+        Self {
+            asset,
+            channel_list: (0..channels).map(|_| FrameCodec::new()).collect(),
+            frame: 0,
+            bit_cursor: 0,
+            position: 0,
+        }
+    }
+
+    pub fn asset(&self) -> &Arc<AdhocAsset> {
+        &self.asset
+    }
+
+    pub fn info(&self) -> StreamInfo {
+        self.asset.info()
+    }
+
+    /// # Description
+    /// decodes part of the stream into interleaved 16-bit `samples`
+    /// ## Comments
+    /// - same as `AdhocCodec::decode_i16(..)`
+    /// ## Returns
+    /// number of samples decoded, `None` once the end of the stream has been reached
+    pub fn decode_i16(&mut self, samples: &mut [i16]) -> Result<Option<usize>, Error> {
+        let convert = self
+            .asset
+            .compressed()
+            .integer_converter(16, |sample| sample as i16)?;
+        self.decode_with(samples, convert)
+    }
+
+    /// # Description
+    /// decodes part of the stream into interleaved integer `samples` at the stream's bit depth
+    /// ## Comments
+    /// - same as `AdhocCodec::decode_i32(..)`
+    /// ## Returns
+    /// number of samples decoded, `None` once the end of the stream has been reached
+    pub fn decode_i32(&mut self, samples: &mut [i32]) -> Result<Option<usize>, Error> {
+        let compressed = self.asset.compressed();
+        let bits_per_sample = compressed.try_info()?.bits_per_sample();
+        let convert = compressed.integer_converter(bits_per_sample, |sample| sample)?;
+        self.decode_with(samples, convert)
+    }
+
+    fn decode_with<T, Convert>(
+        &mut self,
+        samples: &mut [T],
+        convert: Convert,
+    ) -> Result<Option<usize>, Error>
+    where
+        T: Copy + Default,
+        Convert: Fn(i32) -> T,
+    {
+        let asset = &self.asset;
+        let info = asset.compressed().try_info()?;
+        let bits_per_sample = info.bits_per_sample();
+        let frame = &mut self.frame;
+
+        let mut stream = asset.stream.reader();
+        stream.set_bit_cursor(self.bit_cursor);
+
+        let samples_read =
+            AdhocCodec::drain_channels(&mut self.channel_list, samples, convert, |channel_list| {
+                let decoded = AdhocCodec::decode_frame_block_at(
+                    channel_list,
+                    &mut stream,
+                    &asset.frame_header_list,
+                    *frame,
+                    bits_per_sample,
+                )?;
+                if decoded.is_some() {
+                    *frame += channel_list.len();
+                }
+                Ok(decoded)
+            })?;
+
+        self.bit_cursor = stream.bit_cursor();
+        self.position += (samples_read / info.channels()) as u64;
+        Ok((samples_read > 0).then_some(samples_read))
+    }
+}
+
+impl Streamable for AdhocDecoder {
+    fn info(&self) -> StreamInfo {
+        self.info()
+    }
+
+    fn filesize_upperbound(&self) -> u64 {
+        self.asset.compressed().filesize_upperbound()
+    }
+
+    fn encode(&mut self, _samples: &[f32]) -> Result<usize, Error> {
+        Err(Error::Unsupported("AdhocAsset can't be changed"))
+    }
+
+    fn decode(&mut self, samples: &mut [f32]) -> Result<Option<usize>, Error> {
+        let convert = self.asset.compressed().float_converter()?;
+        self.decode_with(samples, convert)
+    }
+
+    fn seek(&mut self, dt: SeekFrom) -> Result<(), Error> {
+        let info = self.asset.compressed().try_info()?;
+        let len = self.duration_in_samples();
+        self.seek_to_sample(resolve_seek(dt, self.position, len, info.sample_rate))
+    }
+
+    fn seek_to_sample(&mut self, sample: u64) -> Result<(), Error> {
+        let asset = &self.asset;
+        let info = asset.compressed().try_info()?;
+        let sample = sample.min(asset.sample_count);
+
+        self.channel_list.iter_mut().for_each(|cs| cs.init());
+        let mut stream = asset.stream.reader();
+        stream.set_bit_cursor(0);
+
+        self.frame = if sample == 0 {
+            0
+        } else {
+            AdhocCodec::seek_channels(
+                &mut self.channel_list,
+                &asset.frame_header_list,
+                &mut stream,
+                sample,
+                info.bits_per_sample(),
+            )?
+        };
+        self.bit_cursor = stream.bit_cursor();
+        self.position = sample;
+        Ok(())
+    }
+
+    fn position_in_samples(&self) -> u64 {
+        self.position
+    }
+
+    fn duration_in_samples(&self) -> u64 {
+        self.asset.duration_in_samples()
+    }
+}
+
+mod tests {
+    #[allow(unused_imports)]
+    use super::*;
+
+    #[test]
+    fn decoders_share_one_asset() {
+        fn is_send_sync<T: Send + Sync>(_: &T) {}
+
+        let info = StreamInfo::new(22050, 2);
+        let data = (0..24_000)
+            .map(|k| (k as f32 * 0.017).sin() * if k % 2 == 0 { 0.7 } else { 0.2 })
+            .collect::<Vec<_>>();
+        let mut codec = AdhocCodec::new().with_compression_level(1).with_info(info);
+        codec.encode(&data).unwrap();
+        codec.seek(SeekFrom::Start(0)).unwrap();
+        let mut expected = vec![0.0; data.len()];
+        codec.decode(&mut expected).unwrap();
+
+        let asset = Arc::new(AdhocAsset::from(codec));
+        is_send_sync(&asset);
+
+        // voices started at different times, each one decodes in its own thread
+        let handles = (0..4)
+            .map(|voice| {
+                let mut decoder = asset.decoder();
+                std::thread::spawn(move || {
+                    decoder.seek_to_sample(voice * 1000).unwrap();
+                    let mut decoded = Vec::new();
+                    let mut buffer = [0.0; 333];
+                    while let Some(samples_read) = decoder.decode(&mut buffer).unwrap() {
+                        decoded.extend_from_slice(&buffer[0..samples_read]);
+                    }
+                    assert_eq!(decoder.position_in_samples(), 12_000);
+                    decoded
+                })
+            })
+            .collect::<Vec<_>>();
+
+        for (voice, handle) in handles.into_iter().enumerate() {
+            let decoded = handle.join().unwrap();
+            assert_eq!(&decoded[..], &expected[voice * 2000..]);
+        }
+
+        let mut decoder = asset.decoder();
+        decoder.seek(SeekFrom::End(-10)).unwrap();
+        let mut samples = [0; 1000];
+        assert_eq!(decoder.decode_i16(&mut samples).unwrap(), Some(440));
+        assert!(decoder.encode(&[0.0; 2]).is_err());
+        drop(decoder);
+
+        let mut codec = Arc::try_unwrap(asset).ok().unwrap().into_codec();
+        let mut decoded = vec![0.0; data.len()];
+        codec.decode(&mut decoded).unwrap();
+        assert_eq!(decoded, expected);
+    }
+}
@@ -8,6 +8,12 @@ pub struct AudioStream {
     stream: BitStream,
 }
 
+impl Default for AudioStream {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
 impl AudioStream {
     pub fn new() -> Self {
         Self {
@@ -15,11 +21,16 @@ impl AudioStream {
             stream: BitStream::new(),
         }
     }
-    pub fn set_info(&mut self,info:Option<StreamInfo>){
+    /// # Description
+    /// wraps an existing `stream`
+    pub fn from_bitstream(info: Option<StreamInfo>, stream: BitStream) -> Self {
+        Self { info, stream }
+    }
+    pub fn set_info(&mut self, info: Option<StreamInfo>) {
         self.info = info;
     }
     pub fn info(&self) -> Option<StreamInfo> {
-        self.info.clone()
+        self.info
     }
 }
 
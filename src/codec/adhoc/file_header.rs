@@ -0,0 +1,134 @@
+use std::io::{self, Read, Write};
+
+/// # Description
+/// The fixed header every `.adhoc` file starts with
+/// ## Comments
+/// - layout (little endian): `magic: [u8; 4]`, `version: u16`, `flags: u16`, `header_len: u32`
+/// - `header_len` is the size of the whole header in bytes, fields added by future versions go
+///   after `header_len` so older readers can skip them
+/// - files written before the header existed are version `0`, they start straight away with bincode data
+#[derive(Copy, Clone, PartialEq, Eq, Debug)]
+pub struct FileHeader {
+    pub version: u16,
+    pub flags: u16,
+}
+
+impl FileHeader {
+    pub const MAGIC: [u8; 4] = *b"ADHC";
+
+    /// the version `AdhocCodec::save_to` writes
+    /// ## Comments
+    /// - version `2` gave every frame header a quantization shift, see `FrameHeadersV1` for what came before
+    /// - version `3` stopped storing lpc coefficient offsets, see `FrameHeadersV2`
+    pub const VERSION: u16 = 3;
+
+    /// size in bytes of the fields this version knows about
+    pub const LEN: u32 = 12;
+
+    /// at least one frame uses an lpc predictor
+    pub const FLAG_LPC: u16 = 1 << 0;
+
+    /// at least one frame-block has decorrelated stereo channels
+    pub const FLAG_STEREO_DECORRELATION: u16 = 1 << 1;
+
+    /// samples are not 16 bits wide
+    pub const FLAG_WIDE_SAMPLES: u16 = 1 << 2;
+
+    /// written by `AdhocWriter`, frame headers and audio data are interleaved in `StreamPacket`s
+    /// instead of all the headers coming first
+    pub const FLAG_STREAMED: u16 = 1 << 3;
+
+    /// at least one frame was quantized beyond the compression level, see `AdhocCodec::with_target_bitrate(..)`
+    pub const FLAG_FRAME_QUANTIZATION: u16 = 1 << 4;
+
+    /// every flag this version knows how to decode, files with other flags set get rejected
+    pub const KNOWN_FLAGS: u16 = Self::FLAG_LPC
+        | Self::FLAG_STEREO_DECORRELATION
+        | Self::FLAG_WIDE_SAMPLES
+        | Self::FLAG_STREAMED
+        | Self::FLAG_FRAME_QUANTIZATION;
+
+    /// # Description
+    /// returns `true` if the file uses the `StreamPacket` layout
+    pub fn is_streamed(&self) -> bool {
+        (self.flags & Self::FLAG_STREAMED) != 0
+    }
+
+    pub fn new(flags: u16) -> Self {
+        Self {
+            version: Self::VERSION,
+            flags,
+        }
+    }
+
+    /// # Description
+    /// returns `true` if a decoder of this version can make sense of the file
+    pub fn is_supported(&self) -> bool {
+        self.version <= Self::VERSION && (self.flags & !Self::KNOWN_FLAGS) == 0
+    }
+
+    pub fn write_to<Resource>(&self, mut dst: Resource) -> io::Result<()>
+    where
+        Resource: Write,
+    {
+        dst.write_all(&Self::MAGIC)?;
+        dst.write_all(&self.version.to_le_bytes())?;
+        dst.write_all(&self.flags.to_le_bytes())?;
+        dst.write_all(&Self::LEN.to_le_bytes())
+    }
+
+    /// # Description
+    /// reads everything that comes after the magic bytes, fields this version doesn't know about are skipped
+    pub fn read_after_magic<Resource>(mut src: Resource) -> io::Result<Self>
+    where
+        Resource: Read,
+    {
+        let mut fields = [0u8; 8];
+        src.read_exact(&mut fields)?;
+
+        let version = u16::from_le_bytes([fields[0], fields[1]]);
+        let flags = u16::from_le_bytes([fields[2], fields[3]]);
+        let header_len = u32::from_le_bytes([fields[4], fields[5], fields[6], fields[7]]);
+
+        if header_len < Self::LEN {
+            return Err(io::Error::new(
+                io::ErrorKind::InvalidData,
+                "adhoc header length is too small",
+            ));
+        }
+
+        let unknown_len = (header_len - Self::LEN) as u64;
+        let skipped = io::copy(&mut src.by_ref().take(unknown_len), &mut io::sink())?;
+        if skipped != unknown_len {
+            return Err(io::ErrorKind::UnexpectedEof.into());
+        }
+
+        Ok(Self { version, flags })
+    }
+}
+
+mod tests {
+    #[allow(unused_imports)]
+    use super::FileHeader;
+
+    #[test]
+    fn header_round_trip_and_skip() {
+        let mut bytes = Vec::new();
+        let header = FileHeader::new(FileHeader::FLAG_LPC);
+        header.write_to(&mut bytes).unwrap();
+        assert_eq!(bytes.len(), FileHeader::LEN as usize);
+        assert_eq!(&bytes[0..4], b"ADHC");
+        assert_eq!(FileHeader::read_after_magic(&bytes[4..]).unwrap(), header);
+
+        // a header from the future with a couple extra bytes
+        let mut future = bytes.clone();
+        future[4..6].copy_from_slice(&(FileHeader::VERSION + 1).to_le_bytes());
+        future[8..12].copy_from_slice(&(FileHeader::LEN + 3).to_le_bytes());
+        future.extend_from_slice(&[7, 7, 7, 42]);
+        let mut reader = &future[4..];
+        let parsed = FileHeader::read_after_magic(&mut reader).unwrap();
+        assert_eq!(parsed.version, FileHeader::VERSION + 1);
+        assert!(!parsed.is_supported());
+        assert_eq!(reader, &[42]);
+    }
+}
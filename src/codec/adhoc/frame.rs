@@ -1,43 +1,211 @@
 use serde::{Deserialize, Serialize};
 
-use crate::collections::BitVec;
+use crate::{
+    collections::BitVec,
+    math::{
+        compute_autocorrelation, levinson_durbin, LinearPredictor, LPC_COEF_PRECISION,
+        MAX_LPC_ORDER,
+    },
+};
 
 use super::*;
 
-#[derive(Serialize, Deserialize)]
 pub struct FrameHeader {
     pub exponent: u8,
     pub size: u16,
     pub bit_cursor: u128,
     pub is_init: bool,
-    pub stack_history: [i16; 3],
+    pub stack_history: [i32; 3],
+    /// an order of `0` means the frame is predicted with `FixedParabola`
+    pub predictor: LinearPredictor,
+    /// how this frame's channel was decorrelated from its sibling
+    pub channel_mode: ChannelMode,
+    /// samples were shifted down by this many bits (on top of the compression level) before being coded
+    pub quant_shift: u8,
+}
+
+impl FrameHeader {
+    /// # Description
+    /// rough number of bits a header takes up in `FrameHeaders`, LPC coefficients not included
+    /// ## Comments
+    /// - exponent(8) + init(1) + size(16) + bit cursor(64) + history(96) + order(8) + shift(4)
+    ///   + channel mode(4) + quantization shift(4)
+    pub const WEIGHT_IN_BITS: u64 = 205;
 }
 
 /// Encoder compresses audio in 'blocks'
 /// this struct stores compact infomation about every block in the stream
 #[derive(Serialize, Deserialize)]
+#[serde(from = "FrameHeadersV3")]
 pub struct FrameHeaders {
     /// its stores `log_2(divisor)`, where `divisor =  2^k`, for some k
-    divisor_exp_list: NibbleList,
+    /// ## Comments
+    /// - wide samples (24/32-bit) need exponents past `15` so this can't be a `NibbleList`
+    divisor_exp_list: Vec<u8>,
+    is_init_frame_list: BitVec,
+    frame_size_list: Vec<u16>,
+    bit_cursor_list: Vec<u64>,
+    stack_history_list: Vec<[i32; 3]>,
+    /// prediction order of every frame
+    lpc_order_list: Vec<u8>,
+    /// stores the coefficient shift of every frame
+    lpc_shift_list: NibbleList,
+    /// quantized coefficients of every frame packed back-to-back
+    lpc_coef_list: Vec<i16>,
+    /// index into `lpc_coef_list` where the coefficients of a frame start
+    /// ## Comments
+    /// - isn't serialized since version `3`, it's rebuilt from `lpc_order_list` on load
+    #[serde(skip)]
+    lpc_coef_offset_list: Vec<u32>,
+    /// stores the `ChannelMode` of every frame
+    channel_mode_list: NibbleList,
+    /// stores the quantization shift of every frame, added in version `2` of the format
+    quant_shift_list: NibbleList,
+    header_cursor: u32,
+}
+
+/// # Description
+/// `FrameHeaders` the way version `3` of the format writes them, without coefficient offsets
+#[derive(Deserialize)]
+pub struct FrameHeadersV3 {
+    divisor_exp_list: Vec<u8>,
+    is_init_frame_list: BitVec,
+    frame_size_list: Vec<u16>,
+    bit_cursor_list: Vec<u64>,
+    stack_history_list: Vec<[i32; 3]>,
+    lpc_order_list: Vec<u8>,
+    lpc_shift_list: NibbleList,
+    lpc_coef_list: Vec<i16>,
+    channel_mode_list: NibbleList,
+    quant_shift_list: NibbleList,
+    header_cursor: u32,
+}
+
+impl From<FrameHeadersV3> for FrameHeaders {
+    fn from(v3: FrameHeadersV3) -> Self {
+        Self {
+            divisor_exp_list: v3.divisor_exp_list,
+            is_init_frame_list: v3.is_init_frame_list,
+            frame_size_list: v3.frame_size_list,
+            bit_cursor_list: v3.bit_cursor_list,
+            stack_history_list: v3.stack_history_list,
+            lpc_order_list: v3.lpc_order_list,
+            lpc_shift_list: v3.lpc_shift_list,
+            lpc_coef_list: v3.lpc_coef_list,
+            lpc_coef_offset_list: Vec::new(),
+            channel_mode_list: v3.channel_mode_list,
+            quant_shift_list: v3.quant_shift_list,
+            header_cursor: v3.header_cursor,
+        }
+        .with_coef_offsets()
+    }
+}
+
+/// # Description
+/// `FrameHeaders` the way version `2` of the format wrote them, coefficient offsets were still stored
+#[derive(Deserialize)]
+pub struct FrameHeadersV2 {
+    divisor_exp_list: Vec<u8>,
+    is_init_frame_list: BitVec,
+    frame_size_list: Vec<u16>,
+    bit_cursor_list: Vec<u64>,
+    stack_history_list: Vec<[i32; 3]>,
+    lpc_order_list: Vec<u8>,
+    lpc_shift_list: NibbleList,
+    lpc_coef_list: Vec<i16>,
+    /// ignored, the offsets are rebuilt from `lpc_order_list`
+    #[allow(dead_code)]
+    lpc_coef_offset_list: Vec<u32>,
+    channel_mode_list: NibbleList,
+    quant_shift_list: NibbleList,
+    header_cursor: u32,
+}
+
+impl From<FrameHeadersV2> for FrameHeaders {
+    fn from(v2: FrameHeadersV2) -> Self {
+        Self {
+            divisor_exp_list: v2.divisor_exp_list,
+            is_init_frame_list: v2.is_init_frame_list,
+            frame_size_list: v2.frame_size_list,
+            bit_cursor_list: v2.bit_cursor_list,
+            stack_history_list: v2.stack_history_list,
+            lpc_order_list: v2.lpc_order_list,
+            lpc_shift_list: v2.lpc_shift_list,
+            lpc_coef_list: v2.lpc_coef_list,
+            lpc_coef_offset_list: Vec::new(),
+            channel_mode_list: v2.channel_mode_list,
+            quant_shift_list: v2.quant_shift_list,
+            header_cursor: v2.header_cursor,
+        }
+        .with_coef_offsets()
+    }
+}
+
+/// # Description
+/// `FrameHeaders` the way version `1` of the format wrote them, frames had no quantization shift back then
+#[derive(Deserialize)]
+pub struct FrameHeadersV1 {
+    divisor_exp_list: Vec<u8>,
     is_init_frame_list: BitVec,
     frame_size_list: Vec<u16>,
     bit_cursor_list: Vec<u64>,
-    stack_history_list: Vec<[i16; 3]>,
+    stack_history_list: Vec<[i32; 3]>,
+    lpc_order_list: Vec<u8>,
+    lpc_shift_list: NibbleList,
+    lpc_coef_list: Vec<i16>,
+    /// ignored, the offsets are rebuilt from `lpc_order_list`
+    #[allow(dead_code)]
+    lpc_coef_offset_list: Vec<u32>,
+    channel_mode_list: NibbleList,
     header_cursor: u32,
 }
 
+impl From<FrameHeadersV1> for FrameHeaders {
+    fn from(v1: FrameHeadersV1) -> Self {
+        let mut quant_shift_list = NibbleList::new();
+        (0..v1.divisor_exp_list.len()).for_each(|_| quant_shift_list.push(0));
+        Self {
+            divisor_exp_list: v1.divisor_exp_list,
+            is_init_frame_list: v1.is_init_frame_list,
+            frame_size_list: v1.frame_size_list,
+            bit_cursor_list: v1.bit_cursor_list,
+            stack_history_list: v1.stack_history_list,
+            lpc_order_list: v1.lpc_order_list,
+            lpc_shift_list: v1.lpc_shift_list,
+            lpc_coef_list: v1.lpc_coef_list,
+            lpc_coef_offset_list: Vec::new(),
+            channel_mode_list: v1.channel_mode_list,
+            quant_shift_list,
+            header_cursor: v1.header_cursor,
+        }
+        .with_coef_offsets()
+    }
+}
+
+impl Default for FrameHeaders {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
 impl FrameHeaders {
     pub fn new() -> Self {
         Self {
             is_init_frame_list: BitVec::new(),
-            divisor_exp_list: NibbleList::new(),
+            divisor_exp_list: Vec::new(),
             frame_size_list: Vec::new(),
             bit_cursor_list: Vec::new(),
             stack_history_list: Vec::new(),
+            lpc_order_list: Vec::new(),
+            lpc_shift_list: NibbleList::new(),
+            lpc_coef_list: Vec::new(),
+            lpc_coef_offset_list: Vec::new(),
+            channel_mode_list: NibbleList::new(),
+            quant_shift_list: NibbleList::new(),
             header_cursor: 0,
         }
     }
-    
+
     /// # Description
     /// returns a tight upperbound of **bits** required to serialize
     pub fn calculate_weight_upperbound(&self) -> u64 {
@@ -47,7 +215,8 @@ impl FrameHeaders {
         //written out, this is my guess at the size(in bits) of the length value
         let vector_header_size = 8;
 
-        let divisor_weight_in_bits = self.divisor_exp_list.capacity() as u64 + vector_header_size;
+        let divisor_weight_in_bits =
+            len * Self::get_elem_size_in_bits(&self.divisor_exp_list) + vector_header_size;
 
         let is_init_weight_in_bits = self.is_init_frame_list.capacity() as u64 + vector_header_size;
 
@@ -60,11 +229,25 @@ impl FrameHeaders {
         let stack_history_list_in_bits =
             len * Self::get_elem_size_in_bits(&self.stack_history_list) + vector_header_size;
 
+        let lpc_order_list_in_bits =
+            len * Self::get_elem_size_in_bits(&self.lpc_order_list) + vector_header_size;
+
+        let lpc_shift_list_in_bits = self.lpc_shift_list.capacity() as u64 + vector_header_size;
+
+        let lpc_coef_list_in_bits = self.lpc_coef_list.len() as u64
+            * Self::get_elem_size_in_bits(&self.lpc_coef_list)
+            + vector_header_size;
+
+        let channel_mode_list_in_bits =
+            self.channel_mode_list.capacity() as u64 + vector_header_size;
+
+        let quant_shift_list_in_bits = self.quant_shift_list.capacity() as u64 + vector_header_size;
+
         let frame_list_header_cursor_in_bits =
             std::mem::size_of_val(&self.header_cursor) as u64 * 8;
 
-        //internally NibbleList has a cursor
-        let nibble_list_cursor_in_bits = 32;
+        //internally NibbleList has a cursor (there are three of them)
+        let nibble_list_cursor_in_bits = 32 * 3;
 
         //internally BitVec has a 128 bit cursor
         let bit_vec_cursor_in_bits = 128;
@@ -74,6 +257,11 @@ impl FrameHeaders {
             + frame_size_list_weight_in_bits
             + bit_cursor_list_weight_in_bits
             + stack_history_list_in_bits
+            + lpc_order_list_in_bits
+            + lpc_shift_list_in_bits
+            + lpc_coef_list_in_bits
+            + channel_mode_list_in_bits
+            + quant_shift_list_in_bits
             + frame_list_header_cursor_in_bits
             + nibble_list_cursor_in_bits
             + bit_vec_cursor_in_bits
@@ -83,6 +271,19 @@ impl FrameHeaders {
         std::mem::size_of::<T>() as u64 * 8
     }
 
+    /// # Description
+    /// pushes every header of `other`, bit cursors get moved `bit_offset` bits forward
+    /// ## Comments
+    /// - used to stitch independently encoded segments back together
+    pub fn append(&mut self, other: &FrameHeaders, bit_offset: u128) {
+        for header in (0..other.len()).filter_map(|idx| other.get(idx)) {
+            self.push(FrameHeader {
+                bit_cursor: header.bit_cursor + bit_offset,
+                ..header
+            });
+        }
+    }
+
     pub fn push(&mut self, header: FrameHeader) {
         let FrameHeader {
             exponent,
@@ -90,45 +291,116 @@ impl FrameHeaders {
             bit_cursor,
             is_init,
             stack_history,
+            predictor,
+            channel_mode,
+            quant_shift,
         } = header;
 
         let header_cursor = self.header_cursor as usize;
-        let is_init = is_init as u64;
-
-        if header_cursor >= self.divisor_exp_list.len() {
-            self.divisor_exp_list.push(exponent);
-            self.frame_size_list.push(num_samples);
-            self.bit_cursor_list.push(bit_cursor as u64);
-            self.is_init_frame_list.push(is_init);
-            self.stack_history_list.push(stack_history);
-        } else {
-            self.divisor_exp_list.set(header_cursor, exponent);
-            self.is_init_frame_list.set(header_cursor, is_init);
-            self.frame_size_list[header_cursor] = num_samples;
-            self.bit_cursor_list[header_cursor] = bit_cursor as u64;
-            self.stack_history_list[header_cursor] = stack_history;
+
+        // we are encoding over frames that were previously written,
+        // so everything from the cursor onwards is stale
+        if header_cursor < self.len() {
+            self.truncate(header_cursor);
         }
 
+        self.divisor_exp_list.push(exponent);
+        self.frame_size_list.push(num_samples);
+        self.bit_cursor_list.push(bit_cursor as u64);
+        self.is_init_frame_list.push(is_init as u64);
+        self.stack_history_list.push(stack_history);
+        self.lpc_order_list.push(predictor.order);
+        self.lpc_shift_list.push(predictor.shift);
+        self.lpc_coef_offset_list
+            .push(self.lpc_coef_list.len() as u32);
+        self.lpc_coef_list.extend_from_slice(predictor.coefs());
+        self.channel_mode_list.push(channel_mode.as_bits());
+        self.quant_shift_list.push(quant_shift);
+
         self.header_cursor += 1;
     }
 
     pub fn get(&self, index: usize) -> Option<FrameHeader> {
         (index < self.len()).then(|| {
-            let exponent = self.divisor_exp_list.get(index);
+            let exponent = self.divisor_exp_list[index];
             let is_init = self.is_init_frame_list.get(index);
             let size = self.frame_size_list[index];
             let cursor = self.bit_cursor_list[index];
             let history = self.stack_history_list[index];
+
+            let order = self.lpc_order_list[index];
+            let coef_offset = self.lpc_coef_offset_list[index] as usize;
+            let mut predictor = LinearPredictor {
+                order,
+                shift: self.lpc_shift_list.get(index),
+                coefs: [0; MAX_LPC_ORDER],
+            };
+            predictor.coefs[0..order as usize]
+                .copy_from_slice(&self.lpc_coef_list[coef_offset..coef_offset + order as usize]);
+
             FrameHeader {
                 exponent,
                 size,
                 is_init: is_init == 1,
                 bit_cursor: cursor as u128,
                 stack_history: history,
+                predictor,
+                channel_mode: ChannelMode::from_bits(self.channel_mode_list.get(index)),
+                quant_shift: self.quant_shift_list.get(index),
             }
         })
     }
 
+    /// # Description
+    /// makes sure headers that came from an untrusted source can be decoded without panicking
+    /// ## Comments
+    /// - every list must describe the same number of frames and every frame-block must be complete
+    /// - stereo decorrelation is only valid in 2-channel streams narrower than 32 bits
+    pub fn validate(&self, info: StreamInfo) -> Result<(), Error> {
+        let len = self.len();
+        let corrupt = |what: &str| Err(Error::CorruptHeader(what.to_string()));
+
+        let is_consistent = self.is_init_frame_list.len() == len
+            && self.frame_size_list.len() == len
+            && self.bit_cursor_list.len() == len
+            && self.stack_history_list.len() == len
+            && self.lpc_order_list.len() == len
+            && self.lpc_shift_list.len() == len
+            && self.lpc_coef_offset_list.len() == len
+            && self.channel_mode_list.len() == len
+            && self.quant_shift_list.len() == len;
+        if !is_consistent {
+            return corrupt("frame header lists have different lengths");
+        }
+
+        if !len.is_multiple_of(info.channels().max(1)) {
+            return corrupt("incomplete frame-block");
+        }
+
+        let allows_stereo =
+            info.channels() == 2 && info.bits_per_sample() < StreamInfo::MAX_BITS_PER_SAMPLE;
+
+        for index in 0..len {
+            // `BitStream::write_compressed(..)` can't handle anything larger
+            if self.divisor_exp_list[index] >= 64 {
+                return corrupt("rice exponent out of range");
+            }
+
+            let order = self.lpc_order_list[index] as usize;
+            let coef_offset = self.lpc_coef_offset_list[index] as usize;
+            if order > MAX_LPC_ORDER || coef_offset + order > self.lpc_coef_list.len() {
+                return corrupt("lpc coefficients out of range");
+            }
+
+            let channel_mode = ChannelMode::from_bits(self.channel_mode_list.get(index));
+            if channel_mode != ChannelMode::Independent && !allows_stereo {
+                return corrupt("stereo decorrelation in a stream that can't have it");
+            }
+        }
+
+        Ok(())
+    }
+
     pub fn set_cursor(&mut self, idx: u32) {
         self.header_cursor = idx;
     }
@@ -137,26 +409,116 @@ impl FrameHeaders {
         self.divisor_exp_list.len()
     }
 
+    /// # Description
+    /// index of the header `next()` will return
+    pub fn cursor(&self) -> usize {
+        self.header_cursor as usize
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+
+    /// # Description
+    /// number of samples in the first `len` frames, counting every channel
+    pub fn sample_count(&self, len: usize) -> u64 {
+        self.frame_size_list
+            .iter()
+            .take(len)
+            .map(|&size| size as u64)
+            .sum()
+    }
+
+    /// # Description
+    /// number of samples (per channel) in every frame-block
+    pub fn frame_block_sizes(&self, channels: usize) -> impl Iterator<Item = u64> + '_ {
+        self.frame_size_list
+            .iter()
+            .step_by(channels.max(1))
+            .map(|&size| size as u64)
+    }
+
+    /// # Description
+    /// finds the frame-block holding sample `offset` (counted per channel)
+    /// ## Returns
+    /// index of the first frame in the frame-block along with the sample (per channel) the block starts at,
+    /// `None` if the stream ends before `offset`
+    pub fn find_frame_block(&self, offset: u64, channels: usize) -> Option<(usize, u64)> {
+        let mut block_start = 0;
+        for (block, size) in self.frame_block_sizes(channels).enumerate() {
+            if offset < block_start + size {
+                return Some((block * channels, block_start));
+            }
+            block_start += size;
+        }
+        None
+    }
+
     pub fn reset(&mut self) {
         self.header_cursor = 0;
     }
+
+    /// # Description
+    /// drops every header
+    pub fn clear(&mut self) {
+        self.truncate(0);
+        self.header_cursor = 0;
+    }
+
+    /// # Description
+    /// moves the bit cursor of every frame `offset` bits forward
+    /// ## Comments
+    /// - `AdhocWriter` encodes into a stream that has had its front cut off, this turns the cursors back
+    ///   into positions within the whole stream
+    pub fn offset_bit_cursors(&mut self, offset: u64) {
+        self.bit_cursor_list
+            .iter_mut()
+            .for_each(|cursor| *cursor += offset);
+    }
+
+    /// # Description
+    /// works out where the coefficients of every frame start, they're packed back-to-back in `lpc_coef_list`
+    /// ## Comments
+    /// - offsets past the end of `lpc_coef_list` are caught by `validate(..)`
+    fn with_coef_offsets(mut self) -> Self {
+        let mut coef_offset = 0u32;
+        self.lpc_coef_offset_list = self
+            .lpc_order_list
+            .iter()
+            .map(|&order| {
+                let offset = coef_offset;
+                coef_offset = coef_offset.saturating_add(order as u32);
+                offset
+            })
+            .collect();
+        self
+    }
+
+    /// # Description
+    /// drops every header from index `len` onwards
+    fn truncate(&mut self, len: usize) {
+        if let Some(&coef_offset) = self.lpc_coef_offset_list.get(len) {
+            self.lpc_coef_list.truncate(coef_offset as usize);
+        }
+        self.divisor_exp_list.truncate(len);
+        self.is_init_frame_list.truncate(len);
+        self.frame_size_list.truncate(len);
+        self.bit_cursor_list.truncate(len);
+        self.stack_history_list.truncate(len);
+        self.lpc_order_list.truncate(len);
+        self.lpc_shift_list.truncate(len);
+        self.lpc_coef_offset_list.truncate(len);
+        self.channel_mode_list.truncate(len);
+        self.quant_shift_list.truncate(len);
+    }
 }
 
 impl Iterator for FrameHeaders {
     type Item = FrameHeader;
     fn next(&mut self) -> Option<Self::Item> {
-        let header_cursor = self.header_cursor as usize;
-        (header_cursor < self.divisor_exp_list.len()).then(|| {
-            let header = FrameHeader {
-                exponent: self.divisor_exp_list.get(header_cursor),
-                size: self.frame_size_list[header_cursor],
-                bit_cursor: self.bit_cursor_list[header_cursor] as u128,
-                is_init: self.is_init_frame_list.get(header_cursor) == 1,
-                stack_history: self.stack_history_list[header_cursor],
-            };
-            self.header_cursor += 1;
-            header
-        })
+        let header = self.get(self.header_cursor as usize)?;
+        self.header_cursor += 1;
+        Some(header)
     }
 }
 
@@ -165,8 +527,20 @@ impl Iterator for FrameHeaders {
 /// Codec for compressing a single frame of audio, where a frame is a collection of samples from a single channel
 pub struct FrameCodec {
     state: CodecState,
-    sample_history: CircularStack<i16>,
-    buffered_channel: VecDeque<f32>,
+    /// the last few samples of the original (not decorrelated) channel
+    sample_history: CircularStack<i32>,
+    /// decoded samples of the original channel, still quantized
+    buffered_channel: VecDeque<i32>,
+    /// samples of the frame currently being worked on, LPC needs more history than `sample_history` has
+    frame_samples: Vec<i32>,
+    /// encoder scratch space for prediction residuals
+    residuals: Vec<i64>,
+}
+
+impl Default for FrameCodec {
+    fn default() -> Self {
+        Self::new()
+    }
 }
 
 impl FrameCodec {
@@ -175,12 +549,14 @@ impl FrameCodec {
             state: CodecState::Init,
             sample_history: CircularStack::new(),
             buffered_channel: VecDeque::new(),
+            frame_samples: Vec::new(),
+            residuals: Vec::new(),
         }
     }
     pub fn state_mut(&mut self) -> &mut CodecState {
         &mut self.state
     }
-    pub fn sample_history_mut(&mut self) -> &mut CircularStack<i16> {
+    pub fn sample_history_mut(&mut self) -> &mut CircularStack<i32> {
         &mut self.sample_history
     }
 
@@ -189,210 +565,426 @@ impl FrameCodec {
         self.buffered_channel.clear();
     }
 
+    pub fn is_init(&self) -> bool {
+        matches!(self.state, CodecState::Init)
+    }
+
+    pub fn is_decoding(&self) -> bool {
+        matches!(self.state, CodecState::Decoding)
+    }
+
+    /// # Description
+    /// the last three samples of the original channel, oldest first
+    pub fn history(&self) -> [i32; 3] {
+        [
+            self.sample_history.prev(3),
+            self.sample_history.prev(2),
+            self.sample_history.prev(1),
+        ]
+    }
+
+    /// # Description
+    /// records the last few `samples` of the original channel, predictors of the next frame start from these
+    pub fn push_history(&mut self, samples: &[i32]) {
+        let start = samples.len().saturating_sub(3);
+        for &samp in samples[start..].iter() {
+            self.sample_history.push(samp);
+        }
+    }
+
+    /// # Description
+    /// buffers decoded `samples` of the original channel and records them as history
+    /// ## Comments
+    /// - samples are shifted back up by `quant_shift` bits and clamped to what a `bits_per_sample`
+    ///   channel can hold, see `FrameCodec::dequantize(..)`
+    pub fn push_decoded(&mut self, samples: &[i32], bits_per_sample: u32, quant_shift: u32) {
+        for &samp in samples.iter() {
+            let samp = Self::dequantize(samp, bits_per_sample, quant_shift);
+            self.buffered_channel.push_back(samp);
+            self.sample_history.push(samp);
+        }
+    }
+
+    /// # Description
+    /// buffers the frame that was just decoded as is
+    /// ## Comments
+    /// - only makes sense when the frame was coded with `ChannelMode::Independent`
+    pub fn push_decoded_frame(&mut self, bits_per_sample: u32, quant_shift: u32) {
+        let frame_samples = mem::take(&mut self.frame_samples);
+        self.push_decoded(&frame_samples, bits_per_sample, quant_shift);
+        self.frame_samples = frame_samples;
+    }
+
+    /// # Description
+    /// undoes the quantization shift of a frame
+    /// ## Comments
+    /// - the encoder keeps its history with this too, so both ends predict from the same samples
+    pub fn dequantize(sample: i32, bits_per_sample: u32, quant_shift: u32) -> i32 {
+        let (sample_min, sample_max) = Self::sample_range(bits_per_sample);
+        ((sample as i64) << quant_shift).clamp(sample_min, sample_max) as i32
+    }
+
+    /// # Description
+    /// samples of the last frame that was encoded/decoded
+    /// ## Comments
+    /// - these belong to the *coded* channel, which isn't the original channel when `ChannelMode` isn't `Independent`
+    pub fn frame_samples(&self) -> &[i32] {
+        &self.frame_samples
+    }
+
     /// encodes a single channel
+    /// ## Comments
+    /// - `samples` belong to the coded channel described by `channel`
+    /// - `sample_history` is left alone, the caller is expected to push the original channel's samples
+    /// - fails if the codec was left in the decoding state, or if `samples` don't fit a frame's 16-bit size
     pub fn encode_frame(
         &mut self,
         stream: &mut AudioStream,
         frame_headers: &mut FrameHeaders,
-        pcm: &[f32],
-    ) {
-        let mut parabola = FixedParabola::new();
-        let mut max_entropy: i16 = 0;
-        let sample_history = &mut self.sample_history;
-
-        match self.state {
-            CodecState::Init => {
-                // save bit cursor before modifying stream
-                let bit_cursor = stream.bit_cursor();
-
-                // write first three samples into the stream
-                // and also record then in the history queue
-                for k in 0..3 {
-                    let samp = pcm[k];
-                    let truncated = truncate_sample(samp);
-                    stream.write::<u16>(truncated as u16);
-                    sample_history.push(truncated);
-                }
-
-                let divisor_exp = Self::compute_optimal_divisor_exponent(sample_history, &pcm[3..]);
-
-                frame_headers.push(FrameHeader {
-                    exponent: divisor_exp as u8,
-                    size: pcm.len() as u16,
-                    bit_cursor,
-                    is_init: true,
-                    stack_history: [
-                        sample_history.prev(3),
-                        sample_history.prev(2),
-                        sample_history.prev(1),
-                    ],
-                });
-
-                //entropy encode
-                for &sample in pcm[3..].iter() {
-                    let current = truncate_sample(sample);
-
-                    parabola.f = [
-                        normalize_sample(sample_history.prev(3)),
-                        normalize_sample(sample_history.prev(2)),
-                        normalize_sample(sample_history.prev(1)),
-                    ];
-                    parabola.compute_coefs();
-                    let predicted = truncate_sample(parabola.eval(3.0));
-
-                    let entropy = (current as i32 - predicted as i32)
-                        .clamp(i16::MIN as i32 + 1, i16::MAX as i32 - 1)
-                        as i16;
-
-                    max_entropy = max_entropy.max(entropy);
-                    stream.write_compressed(divisor_exp, entropy);
-                    sample_history.push(current);
-                }
-                // println!("MAX ENTROPY={}", max_entropy);
-                self.state = CodecState::Encoding;
+        samples: &[i32],
+        channel: CodedChannel,
+        quant_shift: u8,
+    ) -> Result<(), Error> {
+        let is_init = match self.state {
+            CodecState::Init => true,
+            CodecState::Encoding => false,
+            CodecState::Decoding => {
+                return Err(Error::Unsupported(
+                    "can't encode while decoding, call init() first",
+                ))
             }
-            CodecState::Encoding => {
-                let divisor_exp = Self::compute_optimal_divisor_exponent(&sample_history, &pcm[..]);
-
-                frame_headers.push(FrameHeader {
-                    exponent: divisor_exp as u8,
-                    size: pcm.len() as u16,
-                    bit_cursor: stream.bit_cursor(),
-                    is_init: false,
-                    stack_history: [
-                        sample_history.prev(3),
-                        sample_history.prev(2),
-                        sample_history.prev(1),
-                    ],
-                });
-
-                for &sample in pcm[..].iter() {
-                    let current = truncate_sample(sample);
-
-                    parabola.f = [
-                        normalize_sample(sample_history.prev(3)),
-                        normalize_sample(sample_history.prev(2)),
-                        normalize_sample(sample_history.prev(1)),
-                    ];
-                    parabola.compute_coefs();
-                    let predicted = truncate_sample(parabola.eval(3.0));
-
-                    let entropy = (current as i32 - predicted as i32)
-                        .clamp(i16::MIN as i32 + 1, i16::MAX as i32 - 1)
-                        as i16;
-
-                    max_entropy = max_entropy.max(entropy);
-                    stream.write_compressed(divisor_exp, entropy);
-                    sample_history.push(current);
-                }
-            }
-            _ => panic!("invalid state"),
+        };
+        let size = u16::try_from(samples.len())
+            .map_err(|_| Error::Unsupported("frames hold at most 65535 samples"))?;
+
+        // init frames don't depend on anything that came before them, that matters
+        // once they are too short to fill the history with raw samples
+        if is_init {
+            self.sample_history = CircularStack::new();
         }
+
+        let mut stack_history = self.history();
+        let frame_samples = &mut self.frame_samples;
+        let residuals = &mut self.residuals;
+        let sample_bits = channel.sample_bits;
+
+        let mut history = CircularStack::new();
+        if !is_init {
+            channel.history.iter().for_each(|&s| history.push(s));
+        }
+
+        // save bit cursor before modifying stream
+        let bit_cursor = stream.bit_cursor();
+
+        // the first frame starts with (up to) three uncompressed samples
+        // so the predictor has something to work with
+        let num_raw_samples = if is_init { samples.len().min(3) } else { 0 };
+
+        frame_samples.clear();
+        for &samp in samples[0..num_raw_samples].iter() {
+            // for 16-bit channels this is the same as `stream.write::<u16>(..)`
+            let mask = (1u64 << sample_bits) - 1;
+            stream.write_bits(samp as i64 as u64 & mask, sample_bits as usize);
+            history.push(samp);
+            frame_samples.push(samp);
+        }
+
+        // init frames are never decorrelated, so their raw samples double as history
+        if is_init {
+            stack_history = [history.prev(3), history.prev(2), history.prev(1)];
+        }
+
+        let remaining_samples = &samples[num_raw_samples..];
+        let (predictor, _) = Self::choose_predictor(
+            &history,
+            frame_samples,
+            remaining_samples,
+            sample_bits,
+            residuals,
+        );
+
+        Self::compute_residuals(
+            &mut history,
+            &predictor,
+            frame_samples,
+            remaining_samples,
+            sample_bits,
+            residuals,
+        );
+        let (divisor_exp, _) = Self::compute_optimal_divisor_exponent(residuals);
+
+        frame_headers.push(FrameHeader {
+            exponent: divisor_exp as u8,
+            size,
+            bit_cursor,
+            is_init,
+            stack_history,
+            predictor,
+            channel_mode: channel.mode,
+            quant_shift,
+        });
+
+        //entropy encode
+        for &entropy in residuals.iter() {
+            stream.write_compressed(divisor_exp, entropy);
+        }
+
+        self.state = CodecState::Encoding;
+        Ok(())
     }
 
     /// decodes a single channel
+    /// ## Comments
+    /// - decoded samples end up in `frame_samples()`, turning them back into the original
+    ///   channel(and buffering them) is up to the caller
+    /// - fails if the codec was left in the encoding state
     pub fn decode_frame(
         &mut self,
-        stream: &mut AudioStream,
-        frame_info: &mut FrameHeaders,
-    ) -> Option<usize> {
-        let mut parabola = FixedParabola::new();
-        let sample_history = &mut self.sample_history;
-        let sample_buffer = &mut self.buffered_channel;
-
-        frame_info.next().map(|FrameHeader { exponent, size, .. }| {
-            match self.state {
-                CodecState::Init => {
-                    let num_samples_pre_read = 3;
-                    //write starting samples into the stream
-                    for _ in 0..num_samples_pre_read {
-                        let samp = stream.read::<u16>() as i16;
-                        sample_history.push(samp);
-                    }
-                    for k in 0..num_samples_pre_read {
-                        let offset = num_samples_pre_read as u32 - k as u32;
-                        let decoded_sample = normalize_sample(sample_history.prev(offset));
-                        sample_buffer.push_back(decoded_sample);
-                    }
-                    let mut samples_read = num_samples_pre_read as usize;
-                    //entropy decode
-                    for _ in 3..size {
-                        let entropy = stream.read_compressed(exponent as i16);
-                        parabola.f = [
-                            normalize_sample(sample_history.prev(3)),
-                            normalize_sample(sample_history.prev(2)),
-                            normalize_sample(sample_history.prev(1)),
-                        ];
-                        parabola.compute_coefs();
-                        let predicted = truncate_sample(parabola.eval(3.0));
-
-                        //casted to i32 to avoid overflow issues
-                        let current = (entropy as i32 + predicted as i32)
-                            .clamp(i16::MIN as i32, i16::MAX as i32)
-                            as i16;
-
-                        let decoded_sample = normalize_sample(current).clamp(-1.0, 1.0);
-                        sample_buffer.push_back(decoded_sample);
-                        sample_history.push(current);
-                        samples_read += 1;
-                    }
-
-                    self.state = CodecState::Decoding;
-                    samples_read
-                }
-                CodecState::Decoding => {
-                    let mut samples_read = 0;
-                    //entropy decode
-                    for _ in 0..size {
-                        let entropy = stream.read_compressed(exponent as i16);
-                        parabola.f = [
-                            normalize_sample(sample_history.prev(3)),
-                            normalize_sample(sample_history.prev(2)),
-                            normalize_sample(sample_history.prev(1)),
-                        ];
-                        parabola.compute_coefs();
-                        let predicted = truncate_sample(parabola.eval(3.0));
-
-                        //casted to i32 to avoid overflow issues
-                        let current = (entropy as i32 + predicted as i32)
-                            .clamp(i16::MIN as i32, i16::MAX as i32)
-                            as i16;
-
-                        let decoded_sample = normalize_sample(current).clamp(-1.0, 1.0);
-                        sample_buffer.push_back(decoded_sample);
-                        sample_history.push(current);
-                        samples_read += 1;
-                    }
-                    samples_read
-                }
-                _ => panic!("invalid state"),
+        stream: &mut BitReader,
+        header: &FrameHeader,
+        channel: CodedChannel,
+    ) -> Result<usize, Error> {
+        let frame_samples = &mut self.frame_samples;
+        let sample_bits = channel.sample_bits;
+        let (sample_min, sample_max) = Self::sample_range(sample_bits);
+
+        let mut history = CircularStack::new();
+        if !header.is_init {
+            channel.history.iter().for_each(|&s| history.push(s));
+        }
+
+        let num_samples_pre_read = match self.state {
+            CodecState::Encoding => {
+                return Err(Error::Unsupported(
+                    "can't decode while encoding, call seek(SeekFrom::Start(0)) first",
+                ))
             }
-        })
+            // segments encoded in parallel restart with an init frame part way through the stream
+            CodecState::Init | CodecState::Decoding if header.is_init => header.size.min(3),
+            CodecState::Init | CodecState::Decoding => 0,
+        };
+
+        // same as the encoder, init frames start from a blank history
+        if header.is_init {
+            self.sample_history = CircularStack::new();
+        }
+
+        frame_samples.clear();
+
+        //read starting samples from the stream
+        for _ in 0..num_samples_pre_read {
+            //sign extend
+            let unused_bits = 128 - sample_bits;
+            let samp = stream.read_bits(sample_bits as usize) << unused_bits;
+            let samp = (samp as i128 >> unused_bits) as i32;
+            history.push(samp);
+            frame_samples.push(samp);
+        }
+
+        //entropy decode
+        for _ in num_samples_pre_read..header.size {
+            let entropy = stream.read_compressed(header.exponent as i16);
+            let predicted = Self::predict(&history, &header.predictor, frame_samples, sample_bits);
+
+            //casted to i64 to avoid overflow issues
+            let current = entropy
+                .saturating_add(predicted as i64)
+                .clamp(sample_min, sample_max) as i32;
+
+            history.push(current);
+            frame_samples.push(current);
+        }
+
+        self.state = CodecState::Decoding;
+        Ok(frame_samples.len())
     }
 
-    fn compute_optimal_divisor_exponent(
-        sample_history_ref: &CircularStack<i16>,
-        remaining_samples: &[f32],
-    ) -> i16 {
-        let mut bit_sum_table = [0; 16];
-        let mut parabola = FixedParabola::new();
-        let mut sample_history = sample_history_ref.clone();
-        for &sample in remaining_samples.iter() {
-            let current = truncate_sample(sample);
-            parabola.f = [
+    /// # Description
+    /// smallest and largest sample a `sample_bits` channel can have
+    fn sample_range(sample_bits: u32) -> (i64, i64) {
+        let max = (1i64 << (sample_bits - 1)) - 1;
+        (-max - 1, max)
+    }
+
+    /// # Description
+    /// predicts the next sample of a frame
+    /// ## Comments
+    /// - the first `order` samples of a frame are always predicted with `FixedParabola`, that way a frame only
+    ///   ever depends on the three samples stored in `FrameHeader::stack_history`
+    fn predict(
+        sample_history: &CircularStack<i32>,
+        predictor: &LinearPredictor,
+        frame_samples: &[i32],
+        sample_bits: u32,
+    ) -> i32 {
+        let (sample_min, sample_max) = Self::sample_range(sample_bits);
+        let order = predictor.order();
+        if order > 0 && frame_samples.len() >= order {
+            predictor
+                .predict(frame_samples)
+                .clamp(sample_min, sample_max) as i32
+        } else {
+            let mut parabola = FixedParabola::from_samples([
                 normalize_sample(sample_history.prev(3)),
                 normalize_sample(sample_history.prev(2)),
                 normalize_sample(sample_history.prev(1)),
-            ];
+            ]);
             parabola.compute_coefs();
-            let predicted = truncate_sample(parabola.eval(3.0));
-            let entropy = (current as i32 - predicted as i32).abs() as u32;
-            for k in 1..16 {
+
+            // for 16-bit channels this is exactly `quantize_sample(.., 16)`
+            let limit = sample_max as f32 / i16::MAX as f32;
+            let predicted = (parabola.eval(3.0).clamp(-limit, limit) * i16::MAX as f32) as i64;
+            predicted.clamp(sample_min, sample_max) as i32
+        }
+    }
+
+    /// # Description
+    /// computes the prediction residuals of `samples`
+    /// ## Comments
+    /// - `sample_history` and `frame_samples` get updated with `samples`
+    fn compute_residuals(
+        sample_history: &mut CircularStack<i32>,
+        predictor: &LinearPredictor,
+        frame_samples: &mut Vec<i32>,
+        samples: &[i32],
+        sample_bits: u32,
+        residuals: &mut Vec<i64>,
+    ) {
+        residuals.clear();
+        for &current in samples.iter() {
+            let predicted = Self::predict(sample_history, predictor, frame_samples, sample_bits);
+            residuals.push(current as i64 - predicted as i64);
+            sample_history.push(current);
+            frame_samples.push(current);
+        }
+    }
+
+    /// # Description
+    /// estimates how many bits `encode_frame(..)` would spend on `samples`, the header included
+    /// ## Comments
+    /// - nothing is written and the codec's state is left alone, `self` only lends its scratch space
+    /// - `is_init` says whether the frame would be an init frame, `channel.history` is ignored if so
+    pub fn estimate_frame_bits(
+        &mut self,
+        samples: &[i32],
+        channel: CodedChannel,
+        is_init: bool,
+    ) -> u64 {
+        let mut history = CircularStack::new();
+        if !is_init {
+            channel.history.iter().for_each(|&s| history.push(s));
+        }
+
+        let num_raw_samples = if is_init { samples.len().min(3) } else { 0 };
+        let raw_samples = &samples[0..num_raw_samples];
+        raw_samples.iter().for_each(|&s| history.push(s));
+
+        let (_, residual_bits) = Self::choose_predictor(
+            &history,
+            raw_samples,
+            &samples[num_raw_samples..],
+            channel.sample_bits,
+            &mut self.residuals,
+        );
+        let raw_bits = num_raw_samples as u64 * channel.sample_bits as u64;
+        FrameHeader::WEIGHT_IN_BITS + raw_bits + residual_bits
+    }
+
+    /// # Description
+    /// estimates the bits needed to encode `samples` with `FixedParabola` and with the best LPC predictor
+    /// and returns whichever is cheaper
+    /// ## Returns
+    /// the tuple `(predictor, estimated bits)`, coefficients are included in the estimate
+    fn choose_predictor(
+        sample_history: &CircularStack<i32>,
+        frame_samples: &[i32],
+        samples: &[i32],
+        sample_bits: u32,
+        residuals: &mut Vec<i64>,
+    ) -> (LinearPredictor, u64) {
+        let parabola_predictor = LinearPredictor::new();
+        let lpc_predictor = Self::compute_lpc_predictor(frame_samples, samples);
+
+        let mut estimate_bits = |predictor: &LinearPredictor| {
+            let mut history = *sample_history;
+            let mut frame = frame_samples.to_vec();
+            Self::compute_residuals(
+                &mut history,
+                predictor,
+                &mut frame,
+                samples,
+                sample_bits,
+                residuals,
+            );
+            let (_, residual_bits) = Self::compute_optimal_divisor_exponent(residuals);
+            residual_bits + predictor.order() as u64 * LPC_COEF_PRECISION as u64
+        };
+
+        let parabola_bits = estimate_bits(&parabola_predictor);
+        match lpc_predictor {
+            Some(lpc_predictor) => {
+                let lpc_bits = estimate_bits(&lpc_predictor);
+                if lpc_bits < parabola_bits {
+                    (lpc_predictor, lpc_bits)
+                } else {
+                    (parabola_predictor, parabola_bits)
+                }
+            }
+            None => (parabola_predictor, parabola_bits),
+        }
+    }
+
+    /// # Description
+    /// runs levinson-durbin on the frame and quantizes the predictor whose order looks the cheapest
+    fn compute_lpc_predictor(frame_samples: &[i32], samples: &[i32]) -> Option<LinearPredictor> {
+        let samples = frame_samples
+            .iter()
+            .chain(samples.iter())
+            .map(|&s| s as f64)
+            .collect::<Vec<_>>();
+
+        let n = samples.len();
+        let max_order = MAX_LPC_ORDER.min(n / 4);
+        if max_order == 0 {
+            return None;
+        }
+
+        let mut autoc = [0.0; MAX_LPC_ORDER + 1];
+        let autoc = &mut autoc[0..=max_order];
+        compute_autocorrelation(&samples, autoc);
+
+        // residuals cost at least a couple bits per sample so there's no point
+        // in paying for coefficients once the prediction error is tiny
+        const MIN_BITS_PER_SAMPLE: f64 = 2.0;
+
+        let estimated_bits = |order: usize, error: f64| {
+            let bits_per_sample = (0.5 * (error / n as f64).max(1e-9).log2()).max(0.0);
+            (bits_per_sample + MIN_BITS_PER_SAMPLE) * n as f64
+                + (order * LPC_COEF_PRECISION as usize) as f64
+        };
+
+        levinson_durbin(autoc)
+            .into_iter()
+            .enumerate()
+            .map(|(k, (coefs, error))| (estimated_bits(k + 1, error), coefs))
+            .min_by(|(a, _), (b, _)| a.total_cmp(b))
+            .and_then(|(_, coefs)| LinearPredictor::quantize(&coefs))
+    }
+
+    /// # Description
+    /// finds the rice exponent that minimizes the bits needed to encode `residuals`
+    /// ## Returns
+    /// the tuple `(exponent, bits needed)`
+    fn compute_optimal_divisor_exponent(residuals: &[i64]) -> (i16, u64) {
+        // exponents up to 31 cover the side channel of 32-bit audio
+        let mut bit_sum_table = [0u64; 32];
+        for &entropy in residuals.iter() {
+            let entropy = entropy.unsigned_abs();
+            for (k, bit_sum) in bit_sum_table.iter_mut().enumerate().skip(1) {
                 let quotient_bits = (entropy >> k) + 1;
-                let remainder_bits = k as u32;
-                bit_sum_table[k] += quotient_bits + remainder_bits + 1;
+                let remainder_bits = k as u64;
+                *bit_sum += quotient_bits + remainder_bits + 1;
             }
-            sample_history.push(current);
         }
 
         bit_sum_table
@@ -401,15 +993,15 @@ impl FrameCodec {
             .skip(1)
             .filter(|&(_, &b)| b > 0)
             .min_by(|(_, a), (_, b)| a.cmp(b))
-            .map(|(idx, _)| idx as i16)
-            .unwrap_or(1)
+            .map(|(idx, &bits)| (idx as i16, bits))
+            .unwrap_or((1, 0))
     }
 
-    pub fn buffered_channel(&self) -> &VecDeque<f32> {
+    pub fn buffered_channel(&self) -> &VecDeque<i32> {
         &self.buffered_channel
     }
 
-    pub fn buffered_channel_mut(&mut self) -> &mut VecDeque<f32> {
+    pub fn buffered_channel_mut(&mut self) -> &mut VecDeque<i32> {
         &mut self.buffered_channel
     }
 }
@@ -0,0 +1,90 @@
+//! Layout of `.adhoc` files written before the format had a `FileHeader` (version `0`)
+//! ## Comments
+//! - the bitstream syntax of version `0` is a subset of the current one so only the metadata
+//!   needs to be migrated, the audio data is used as is
+
+use serde::Deserialize;
+
+use crate::{collections::BitVec, math::LinearPredictor};
+
+use super::*;
+
+#[derive(Deserialize)]
+pub struct LegacyStreamInfo {
+    sample_rate: u32,
+    channels: u32,
+}
+
+#[derive(Deserialize)]
+struct LegacyAudioStream {
+    info: Option<LegacyStreamInfo>,
+    stream: BitStream,
+}
+
+#[derive(Deserialize)]
+pub struct LegacyFrameHeaders {
+    divisor_exp_list: NibbleList,
+    is_init_frame_list: BitVec,
+    frame_size_list: Vec<u16>,
+    bit_cursor_list: Vec<u64>,
+    stack_history_list: Vec<[i16; 3]>,
+    #[allow(dead_code)]
+    header_cursor: u32,
+}
+
+#[derive(Deserialize)]
+pub struct LegacyAdhocCodec {
+    compression_level: u32,
+    frame_header_list: LegacyFrameHeaders,
+    stream: LegacyAudioStream,
+}
+
+impl LegacyAdhocCodec {
+    /// # Description
+    /// converts a version `0` file into `(compression_level, frame headers, audio stream)`
+    /// ## Comments
+    /// - every legacy frame is an independent 16-bit frame predicted with `FixedParabola`
+    pub fn migrate(self) -> Result<(u32, FrameHeaders, AudioStream), Error> {
+        let frame_header_list = self.frame_header_list.migrate()?;
+        let info = self.stream.info.map(LegacyStreamInfo::migrate);
+        let stream = AudioStream::from_bitstream(info, self.stream.stream);
+        Ok((self.compression_level, frame_header_list, stream))
+    }
+}
+
+impl LegacyFrameHeaders {
+    pub fn migrate(self) -> Result<FrameHeaders, Error> {
+        let len = self.divisor_exp_list.len();
+
+        let is_consistent = self.frame_size_list.len() == len
+            && self.bit_cursor_list.len() == len
+            && self.stack_history_list.len() == len;
+        if !is_consistent {
+            return Err(Error::CorruptHeader(
+                "legacy frame header lists have different lengths".to_string(),
+            ));
+        }
+
+        let mut frame_header_list = FrameHeaders::new();
+        for idx in 0..len {
+            frame_header_list.push(FrameHeader {
+                exponent: self.divisor_exp_list.get(idx),
+                size: self.frame_size_list[idx],
+                bit_cursor: self.bit_cursor_list[idx] as u128,
+                is_init: self.is_init_frame_list.get(idx) == 1,
+                stack_history: self.stack_history_list[idx].map(|s| s as i32),
+                predictor: LinearPredictor::new(),
+                channel_mode: ChannelMode::Independent,
+                quant_shift: 0,
+            });
+        }
+        frame_header_list.reset();
+        Ok(frame_header_list)
+    }
+}
+
+impl LegacyStreamInfo {
+    pub fn migrate(self) -> StreamInfo {
+        StreamInfo::new(self.sample_rate, self.channels)
+    }
+}
@@ -0,0 +1,226 @@
+use super::*;
+
+/// largest quantization shift a frame can have, `FrameHeaders` stores them in a nibble
+pub const MAX_QUANT_SHIFT: u32 = 15;
+
+/// # Description
+/// A summary of what the encoder has produced so far, see `AdhocCodec::encode_report()`
+#[derive(Copy, Clone, Debug, Default, PartialEq)]
+pub struct EncodeReport {
+    /// number of samples (per channel) encoded, samples still buffered by `encode(..)` aren't counted
+    pub samples: u64,
+    /// bits spent on compressed audio and frame headers
+    pub bits: u64,
+    /// `bits` spread over the duration of `samples`, in kilobits per second
+    pub bitrate_kbps: f64,
+    /// the bitrate asked for with `with_target_bitrate(..)`, if any
+    pub target_bitrate_kbps: Option<f64>,
+    /// average number of bits frames were quantized by on top of the compression level
+    pub mean_quant_shift: f64,
+    /// largest number of bits a frame was quantized by on top of the compression level
+    pub max_quant_shift: u32,
+    /// signal to noise ratio (in dB) of the encoded frames against the samples handed to the encoder,
+    /// infinite when nothing was lost
+    pub snr_db: f64,
+    /// the quality asked for with `with_target_snr(..)`, if any
+    pub target_snr_db: Option<f64>,
+}
+
+/// # Description
+/// Picks the quantization shift of a frame, see `AdhocCodec::with_quantization_callback(..)`
+pub type QuantizationCallback = dyn Fn(&FrameContext) -> u32 + Send + Sync;
+
+/// # Description
+/// What a `QuantizationCallback` gets to see of a frame before it's encoded
+#[derive(Clone)]
+pub struct FrameContext<'a> {
+    /// sample (per channel) the frame starts at
+    pub position: u64,
+    /// the frame's samples, one slice per channel, already quantized by the compression level
+    pub channel_list: Vec<&'a [i32]>,
+    pub bits_per_sample: u32,
+    /// the shift the rate control picked, `0` when there is no target
+    pub suggested_shift: u32,
+    /// largest shift the frame can have, bigger ones get clamped
+    pub max_shift: u32,
+}
+
+impl FrameContext<'_> {
+    /// # Description
+    /// largest magnitude of any sample in the frame, normalized to `[0,1]`
+    pub fn peak(&self) -> f32 {
+        let peak = self
+            .channel_list
+            .iter()
+            .flat_map(|channel| channel.iter())
+            .map(|&s| s.unsigned_abs())
+            .max()
+            .unwrap_or(0);
+        peak as f32 / max_sample(self.bits_per_sample) as f32
+    }
+
+    /// # Description
+    /// root mean square of every sample in the frame, normalized to `[0,1]`
+    pub fn rms(&self) -> f32 {
+        let normalize = max_sample(self.bits_per_sample) as f64;
+        let (sum, count) = self
+            .channel_list
+            .iter()
+            .flat_map(|channel| channel.iter())
+            .fold((0.0, 0usize), |(sum, count), &s| {
+                let s = s as f64 / normalize;
+                (sum + s * s, count + 1)
+            });
+        if count == 0 {
+            0.0
+        } else {
+            (sum / count as f64).sqrt() as f32
+        }
+    }
+}
+
+/// # Description
+/// Keeps track of how many bits the encoder spends (and how much noise it adds) and decides how many bits
+/// the next frame gets
+/// ## Comments
+/// - the budget of a frame is its share of the target plus a quarter of whatever was over/under spent
+///   so far, that way the average bitrate converges on the target without frames swinging wildly
+#[derive(Copy, Clone, Default)]
+pub struct RateControl {
+    /// the bitrate the encoder aims for in kilobits per second, `None` leaves quantization alone
+    target_kbps: Option<f64>,
+    /// the lowest signal to noise ratio (in dB) a frame may have
+    target_snr_db: Option<f64>,
+    /// bits spent on frames so far, headers included
+    spent_bits: u64,
+    /// samples (per channel) in the frames encoded so far
+    encoded_samples: u64,
+    /// number of frame-blocks encoded so far
+    frame_blocks: u64,
+    /// sum of the quantization shift of every frame-block
+    quant_shift_sum: u64,
+    /// largest quantization shift so far
+    max_quant_shift: u32,
+    /// sum of the squared (normalized) samples handed to the encoder
+    signal_energy: f64,
+    /// sum of the squared difference between what was handed to the encoder and what a decoder gets back
+    noise_energy: f64,
+}
+
+impl RateControl {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn with_target_kbps(mut self, kbps: Option<f64>) -> Self {
+        self.target_kbps = kbps;
+        self
+    }
+
+    pub fn target_kbps(&self) -> Option<f64> {
+        self.target_kbps
+    }
+
+    pub fn with_target_snr_db(mut self, db: Option<f64>) -> Self {
+        self.target_snr_db = db;
+        self
+    }
+
+    pub fn target_snr_db(&self) -> Option<f64> {
+        self.target_snr_db
+    }
+
+    /// # Description
+    /// `true` if frames get a quantization shift of their own
+    pub fn is_active(&self) -> bool {
+        self.target_kbps.is_some() || self.target_snr_db.is_some()
+    }
+
+    /// # Description
+    /// number of bits the next frame-block of `frame_len` samples (per channel) should fit in
+    /// ## Returns
+    /// `None` if there is no target bitrate
+    pub fn budget(&self, frame_len: usize, info: StreamInfo) -> Option<u64> {
+        let bits_per_tick = self.target_kbps? * 1000.0 / info.sample_rate.max(1) as f64;
+        let ideal = bits_per_tick * frame_len as f64;
+        let debt = bits_per_tick * self.encoded_samples as f64 - self.spent_bits as f64;
+        Some((ideal + debt * 0.25).max(ideal * 0.25) as u64)
+    }
+
+    /// # Description
+    /// records a frame-block of `frame_len` samples (per channel) that took `bits` to encode
+    /// ## Parameters
+    /// - `(signal_energy, noise_energy)` see `snr_db(..)`
+    pub fn record(
+        &mut self,
+        frame_len: usize,
+        bits: u64,
+        quant_shift: u32,
+        (signal_energy, noise_energy): (f64, f64),
+    ) {
+        self.spent_bits += bits;
+        self.encoded_samples += frame_len as u64;
+        self.frame_blocks += 1;
+        self.quant_shift_sum += quant_shift as u64;
+        self.max_quant_shift = self.max_quant_shift.max(quant_shift);
+        self.signal_energy += signal_energy;
+        self.noise_energy += noise_energy;
+    }
+
+    /// # Description
+    /// forgets about everything encoded so far, the targets are kept
+    pub fn reset(&mut self) {
+        *self = Self::new()
+            .with_target_kbps(self.target_kbps)
+            .with_target_snr_db(self.target_snr_db);
+    }
+
+    /// # Description
+    /// adds up what two independently encoded pieces of a stream spent
+    pub fn merge(&mut self, other: &RateControl) {
+        self.spent_bits += other.spent_bits;
+        self.encoded_samples += other.encoded_samples;
+        self.frame_blocks += other.frame_blocks;
+        self.quant_shift_sum += other.quant_shift_sum;
+        self.max_quant_shift = self.max_quant_shift.max(other.max_quant_shift);
+        self.signal_energy += other.signal_energy;
+        self.noise_energy += other.noise_energy;
+    }
+
+    pub fn report(&self, info: StreamInfo) -> EncodeReport {
+        let seconds = self.encoded_samples as f64 / info.sample_rate.max(1) as f64;
+        EncodeReport {
+            samples: self.encoded_samples,
+            bits: self.spent_bits,
+            bitrate_kbps: if seconds > 0.0 {
+                self.spent_bits as f64 / seconds / 1000.0
+            } else {
+                0.0
+            },
+            target_bitrate_kbps: self.target_kbps,
+            mean_quant_shift: if self.frame_blocks > 0 {
+                self.quant_shift_sum as f64 / self.frame_blocks as f64
+            } else {
+                0.0
+            },
+            max_quant_shift: self.max_quant_shift,
+            snr_db: snr_db(self.signal_energy, self.noise_energy),
+            target_snr_db: self.target_snr_db,
+        }
+    }
+}
+
+/// # Description
+/// signal to noise ratio in dB
+/// ## Parameters
+/// - `signal_energy` sum of the squared samples of the original signal
+/// - `noise_energy` sum of the squared differences between the original and the reconstructed signal
+/// ## Returns
+/// infinity if there is no noise at all
+pub fn snr_db(signal_energy: f64, noise_energy: f64) -> f64 {
+    if noise_energy <= 0.0 {
+        f64::INFINITY
+    } else {
+        10.0 * (signal_energy / noise_energy).log10()
+    }
+}
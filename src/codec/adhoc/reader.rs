@@ -0,0 +1,470 @@
+use super::*;
+
+use legacy::{LegacyFrameHeaders, LegacyStreamInfo};
+
+/// default number of bytes of compressed audio an `AdhocReader` is allowed to buffer
+pub const DEFAULT_READER_BUFFER_LIMIT: usize = 16 << 20;
+
+/// # Description
+/// Decodes a `.adhoc` stream straight out of a `Read`, audio data is pulled in as it's needed
+/// ## Comments
+/// - only the frame headers are read upfront, compressed audio sits in a bounded buffer that
+///   never holds more than the frame-block being decoded
+/// - meant for sockets and partially downloaded files, decoding starts as soon as the first frames arrive
+/// - files written by `AdhocWriter` carry their headers along with the audio, so nothing but the file header
+///   is read upfront
+/// - the stream is read front to back, `seek(..)`, `seek_to_sample(..)` and `encode(..)` return `Error::Unsupported`
+/// - files written by `AdhocWriter` don't say how long they are, `duration_in_samples()` only counts
+///   the packets read so far
+pub struct AdhocReader<R> {
+    /// does the actual decoding, its `AudioStream` only holds the buffered chunks
+    codec: AdhocCodec,
+    source: ChunkSource<R>,
+}
+
+/// # Description
+/// hands out the 128-bit chunks of the serialized `BitStream` as frame-blocks need them
+struct ChunkSource<R> {
+    res: R,
+    /// `true` if the stream is made of `StreamPacket`s, frame headers come along with the chunks
+    is_streamed: bool,
+    /// `true` once the `StreamPacket` that marks the end of the stream has been read
+    is_finished: bool,
+    /// format version of the file, the layout of `StreamPacket`s depends on it
+    version: u16,
+    /// number of chunks in the whole bitstream, for packets it's the number of chunks seen so far
+    total_chunks: u64,
+    /// index of the first buffered chunk
+    window_start: u64,
+    /// number of chunks read from `res`, buffered chunks are `window_start..chunks_read`
+    chunks_read: u64,
+    /// number of samples (per channel) in the packets read so far
+    packet_samples: u64,
+    max_buffered_chunks: u64,
+}
+
+impl<R> AdhocReader<R>
+where
+    R: Read,
+{
+    /// # Description
+    /// reads the file header and frame headers of a stream written by `AdhocCodec::save_to(..)`
+    /// ## Comments
+    /// - version `0` files are migrated on the fly just like `AdhocCodec::load(..)` does
+    /// - at most `DEFAULT_LOAD_LIMIT` bytes of headers get deserialized
+    pub fn new(mut res: R) -> Result<Self, Error> {
+        let limit = DEFAULT_LOAD_LIMIT;
+
+        let mut is_streamed = false;
+        let mut version = FileHeader::VERSION;
+        let (compression_level, frame_header_list, info) = match Prelude::read_from(&mut res)? {
+            Prelude::Legacy(magic) => {
+                // the bytes that weren't the magic are the compression level
+                let mut legacy_metadata = || -> Result<_, bincode::Error> {
+                    let headers = bincode_options(limit)
+                        .deserialize_from::<_, LegacyFrameHeaders>(&mut res)?;
+                    let info = bincode_options(limit)
+                        .deserialize_from::<_, Option<LegacyStreamInfo>>(&mut res)?;
+                    Ok((headers, info))
+                };
+                let (headers, info) = legacy_metadata().map_err(|err| match *err {
+                    bincode::ErrorKind::SizeLimit => Error::LimitExceeded,
+                    _ => Error::BadMagic,
+                })?;
+                (
+                    u32::from_le_bytes(magic),
+                    headers.migrate()?,
+                    info.map(LegacyStreamInfo::migrate),
+                )
+            }
+            Prelude::Versioned(header) if header.is_streamed() => {
+                // headers show up packet by packet
+                is_streamed = true;
+                version = header.version;
+                let (compression_level, info) =
+                    bincode_options(limit).deserialize_from::<_, (u32, StreamInfo)>(&mut res)?;
+                (compression_level, FrameHeaders::new(), Some(info))
+            }
+            Prelude::Versioned(header) => (
+                bincode_options(limit).deserialize_from::<_, u32>(&mut res)?,
+                read_frame_headers(&mut res, limit, header.version)?,
+                bincode_options(limit).deserialize_from::<_, Option<StreamInfo>>(&mut res)?,
+            ),
+        };
+
+        // bincode puts the number of chunks right before the chunks themselves
+        let total_chunks = if is_streamed {
+            0
+        } else {
+            bincode_options(limit).deserialize_from::<_, u64>(&mut res)?
+        };
+
+        let stream = AudioStream::from_bitstream(info, BitStream::new());
+        let codec = AdhocCodec::from_parts(compression_level, frame_header_list, stream)?;
+
+        Ok(Self {
+            codec,
+            source: ChunkSource {
+                res,
+                is_streamed,
+                is_finished: false,
+                version,
+                total_chunks,
+                window_start: 0,
+                chunks_read: 0,
+                packet_samples: 0,
+                max_buffered_chunks: 0,
+            },
+        }
+        .with_buffer_limit(DEFAULT_READER_BUFFER_LIMIT))
+    }
+
+    /// # Description
+    /// caps the compressed audio kept in memory to roughly `bytes`
+    /// ## Comments
+    /// - a frame-block that doesn't fit makes `decode(..)` fail with `Error::LimitExceeded`
+    pub fn with_buffer_limit(mut self, bytes: usize) -> Self {
+        self.source.max_buffered_chunks = (bytes as u64 / 16).max(2);
+        self
+    }
+
+    pub fn info(&self) -> StreamInfo {
+        self.codec.info()
+    }
+
+    /// # Description
+    /// number of bytes of compressed audio currently buffered
+    pub fn buffered_bytes(&self) -> usize {
+        self.codec.stream.blocks_allocated() * 16
+    }
+
+    /// # Description
+    /// decodes part of the stream into interleaved 16-bit `samples`
+    /// ## Comments
+    /// - same as `AdhocCodec::decode_i16(..)`
+    /// ## Returns
+    /// number of samples decoded, `None` once the end of the stream has been reached
+    pub fn decode_i16(&mut self, samples: &mut [i16]) -> Result<Option<usize>, Error> {
+        let convert = self
+            .codec
+            .compressed()
+            .integer_converter(16, |sample| sample as i16)?;
+        self.decode_with(samples, convert)
+    }
+
+    /// # Description
+    /// decodes part of the stream into interleaved integer `samples` at the stream's bit depth
+    /// ## Comments
+    /// - same as `AdhocCodec::decode_i32(..)`
+    /// ## Returns
+    /// number of samples decoded, `None` once the end of the stream has been reached
+    pub fn decode_i32(&mut self, samples: &mut [i32]) -> Result<Option<usize>, Error> {
+        let bits_per_sample = self.codec.try_info()?.bits_per_sample();
+        let convert = self
+            .codec
+            .compressed()
+            .integer_converter(bits_per_sample, |sample| sample)?;
+        self.decode_with(samples, convert)
+    }
+
+    fn decode_with<T, Convert>(
+        &mut self,
+        samples: &mut [T],
+        convert: Convert,
+    ) -> Result<Option<usize>, Error>
+    where
+        T: Copy + Default,
+        Convert: Fn(i32) -> T,
+    {
+        let info = self.codec.try_info()?;
+        let source = &mut self.source;
+        let samples_read = self
+            .codec
+            .decode_from(samples, convert, |stream, headers| {
+                if source.is_streamed {
+                    source.fill_from_packets(stream, headers, info)
+                } else {
+                    source.fill(stream, headers, info.channels())
+                }
+            })?;
+        Ok((samples_read > 0).then_some(samples_read))
+    }
+}
+
+impl<R> ChunkSource<R>
+where
+    R: Read,
+{
+    /// # Description
+    /// makes sure `stream` holds every bit of the frame-block `headers` is pointing at and
+    /// moves the stream's cursor to the start of the frame-block
+    /// ## Comments
+    /// - chunks belonging to earlier frame-blocks are dropped
+    fn fill(
+        &mut self,
+        stream: &mut AudioStream,
+        headers: &FrameHeaders,
+        channels: usize,
+    ) -> Result<(), Error> {
+        let frame = headers.cursor();
+        let start = match headers.get(frame) {
+            Some(header) => header.bit_cursor,
+            // out of frames, there's nothing to buffer
+            None => return Ok(()),
+        };
+
+        let total_bits = self.total_chunks as u128 * 128;
+        let end = headers
+            .get(frame + channels)
+            .map(|header| header.bit_cursor)
+            .unwrap_or(total_bits);
+
+        let first_chunk = (start / 128) as u64;
+        let last_chunk = end.div_ceil(128) as u64;
+        if end < start || end > total_bits || first_chunk < self.window_start {
+            return Err(Error::CorruptFrame { frame });
+        }
+        if last_chunk - first_chunk > self.max_buffered_chunks {
+            return Err(Error::LimitExceeded);
+        }
+
+        // drop the chunks before the frame-block
+        let stale_chunks = first_chunk.min(self.chunks_read) - self.window_start;
+        stream.discard_chunks(stale_chunks as usize);
+        self.window_start += stale_chunks;
+
+        // skip chunks no frame-block uses, the buffer is empty if this runs
+        while self.chunks_read < first_chunk {
+            self.read_chunk()?;
+            self.window_start = self.chunks_read;
+        }
+
+        while self.chunks_read < last_chunk {
+            let chunk = self.read_chunk()?;
+            stream.push_chunk(chunk);
+        }
+
+        stream.set_bit_cursor(start - self.window_start as u128 * 128);
+        Ok(())
+    }
+
+    /// # Description
+    /// `fill(..)` for streams made of `StreamPacket`s
+    /// ## Comments
+    /// - once the headers run out the next packet replaces both the headers and the buffered chunks,
+    ///   a packet always holds every bit its frames need
+    fn fill_from_packets(
+        &mut self,
+        stream: &mut AudioStream,
+        headers: &mut FrameHeaders,
+        info: StreamInfo,
+    ) -> Result<(), Error> {
+        let packet_limit = self.max_buffered_chunks * 16;
+
+        while headers.cursor() >= headers.len() && !self.is_finished {
+            let packet = match StreamPacket::read_from(&mut self.res, packet_limit, self.version)? {
+                Some(packet) => packet,
+                None => {
+                    self.is_finished = true;
+                    break;
+                }
+            };
+
+            packet.frame_header_list.validate(info)?;
+            if packet.first_chunk < self.window_start || packet.first_chunk > self.total_chunks {
+                return Err(Error::CorruptHeader(
+                    "packet doesn't continue the stream".to_string(),
+                ));
+            }
+
+            let stale_chunks = stream.blocks_allocated();
+            stream.discard_chunks(stale_chunks);
+            packet
+                .chunk_list
+                .iter()
+                .for_each(|&chunk| stream.push_chunk(chunk));
+            self.window_start = packet.first_chunk;
+            self.total_chunks = packet.first_chunk + packet.chunk_list.len() as u64;
+            self.packet_samples += packet
+                .frame_header_list
+                .sample_count(packet.frame_header_list.len())
+                / info.channels as u64;
+
+            *headers = packet.frame_header_list;
+            headers.reset();
+        }
+
+        let frame = headers.cursor();
+        let start = match headers.get(frame) {
+            Some(header) => header.bit_cursor,
+            None => return Ok(()),
+        };
+
+        let window_start_bits = self.window_start as u128 * 128;
+        if start < window_start_bits || start > self.total_chunks as u128 * 128 {
+            return Err(Error::CorruptFrame { frame });
+        }
+        stream.set_bit_cursor(start - window_start_bits);
+        Ok(())
+    }
+
+    fn read_chunk(&mut self) -> Result<u128, Error> {
+        let mut bytes = [0u8; 16];
+        self.res.read_exact(&mut bytes)?;
+        self.chunks_read += 1;
+        Ok(u128::from_le_bytes(bytes))
+    }
+}
+
+impl<R> Streamable for AdhocReader<R>
+where
+    R: Read,
+{
+    fn info(&self) -> StreamInfo {
+        self.info()
+    }
+
+    fn filesize_upperbound(&self) -> u64 {
+        // same estimate `AdhocCodec` makes, with the whole bitstream instead of the buffer
+        let buffered_bits = self.codec.stream.capacity_upperbound() as u64;
+        self.codec.filesize_upperbound() - buffered_bits + self.source.total_chunks * 128
+    }
+
+    fn encode(&mut self, _samples: &[f32]) -> Result<usize, Error> {
+        Err(Error::Unsupported("AdhocReader can only decode"))
+    }
+
+    fn decode(&mut self, samples: &mut [f32]) -> Result<Option<usize>, Error> {
+        let convert = self.codec.compressed().float_converter()?;
+        self.decode_with(samples, convert)
+    }
+
+    fn seek(&mut self, _dt: SeekFrom) -> Result<(), Error> {
+        Err(Error::Unsupported(
+            "AdhocReader reads the stream front to back",
+        ))
+    }
+
+    fn seek_to_sample(&mut self, _sample: u64) -> Result<(), Error> {
+        Err(Error::Unsupported(
+            "AdhocReader reads the stream front to back",
+        ))
+    }
+
+    fn position_in_samples(&self) -> u64 {
+        self.codec.position_in_samples()
+    }
+
+    fn duration_in_samples(&self) -> u64 {
+        if self.source.is_streamed {
+            self.source.packet_samples
+        } else {
+            self.codec.duration_in_samples()
+        }
+    }
+}
+
+mod tests {
+    #[allow(unused_imports)]
+    use super::*;
+
+    #[allow(unused_imports)]
+    use std::{fs::File, io::Cursor};
+
+    /// hands data out a few bytes at a time, like a slow socket would
+    #[allow(dead_code)]
+    struct Trickle<'a>(&'a [u8]);
+
+    impl<'a> Read for Trickle<'a> {
+        fn read(&mut self, buf: &mut [u8]) -> std::io::Result<usize> {
+            let len = buf.len().min(self.0.len()).min(7);
+            buf[0..len].copy_from_slice(&self.0[0..len]);
+            self.0 = &self.0[len..];
+            Ok(len)
+        }
+    }
+
+    #[allow(dead_code)]
+    fn decode_everything<Decode>(mut decode: Decode) -> Vec<i32>
+    where
+        Decode: FnMut(&mut [i32]) -> Option<usize>,
+    {
+        let mut out = Vec::new();
+        let mut buffer = [0; 333];
+        while let Some(samples_read) = decode(&mut buffer) {
+            out.extend_from_slice(&buffer[0..samples_read]);
+        }
+        out
+    }
+
+    #[test]
+    fn reader_matches_load() {
+        let info = StreamInfo::new(44100, 2).with_bits_per_sample(24);
+        let mut codec = AdhocCodec::new().with_info(info);
+        let samples = (0..20_000)
+            .map(|k| ((k as f32 * 0.01).sin() * 4_000_000.0) as i32 + (k % 7) * 11)
+            .collect::<Vec<_>>();
+        for chunk in samples.chunks(1024) {
+            codec.encode_i32(chunk).unwrap();
+        }
+
+        let mut bytes = Vec::new();
+        codec.save_to(&mut bytes).unwrap();
+
+        let mut loaded = AdhocCodec::load(Cursor::new(&bytes)).unwrap();
+        let expected = decode_everything(|buf| loaded.decode_i32(buf).unwrap());
+        assert_eq!(expected, samples);
+
+        let mut reader = AdhocReader::new(Trickle(&bytes)).unwrap();
+        let mut max_buffered = 0;
+        let streamed = decode_everything(|buf| {
+            let samples_read = reader.decode_i32(buf).unwrap();
+            max_buffered = max_buffered.max(reader.buffered_bytes());
+            samples_read
+        });
+        assert_eq!(streamed, expected);
+
+        // only a frame-block or so is ever held in memory
+        assert!(
+            max_buffered * 4 < bytes.len(),
+            "{} {}",
+            max_buffered,
+            bytes.len()
+        );
+
+        let too_small = AdhocReader::new(Cursor::new(&bytes))
+            .unwrap()
+            .with_buffer_limit(64);
+        assert!(matches!(
+            decode_everything_result(too_small),
+            Err(Error::LimitExceeded)
+        ));
+
+        // truncated data is an error instead of a panic or a silent end of stream
+        let truncated = &bytes[0..bytes.len() * 2 / 3];
+        let reader = AdhocReader::new(Cursor::new(truncated)).unwrap();
+        assert!(matches!(
+            decode_everything_result(reader),
+            Err(Error::Io(_))
+        ));
+    }
+
+    #[allow(dead_code)]
+    fn decode_everything_result<R: Read>(mut reader: AdhocReader<R>) -> Result<(), Error> {
+        let mut buffer = [0.0; 512];
+        while reader.decode(&mut buffer)?.is_some() {}
+        Ok(())
+    }
+
+    #[test]
+    fn reader_reads_legacy_files() {
+        let bytes = std::fs::read("./resources/legacy_v0.adhoc").unwrap();
+
+        let mut loaded = AdhocCodec::load(Cursor::new(&bytes)).unwrap();
+        let expected = decode_everything(|buf| loaded.decode_i32(buf).unwrap());
+
+        let mut reader = AdhocReader::new(Trickle(&bytes)).unwrap();
+        let streamed = decode_everything(|buf| reader.decode_i32(buf).unwrap());
+        assert_eq!(streamed, expected);
+    }
+}
@@ -0,0 +1,195 @@
+/// # Description
+/// How the two channels of a stereo frame-block were decorrelated before being encoded
+/// ## Comments
+/// - `side = left - right` and `mid = (left + right) >> 1`, the bit `mid` loses is recovered from `side`
+/// - every variant is exactly reversible, the decoder always gets the original left/right pair back
+#[derive(Copy, Clone, PartialEq, Eq, Debug, Default)]
+pub enum ChannelMode {
+    /// channels are coded as is
+    #[default]
+    Independent,
+    /// coded channels are `(left, side)`
+    LeftSide,
+    /// coded channels are `(side, right)`
+    RightSide,
+    /// coded channels are `(mid, side)`
+    MidSide,
+}
+
+/// # Description
+/// Everything a `FrameCodec` needs to know about the (possibly decorrelated) channel it is coding
+#[derive(Copy, Clone)]
+pub struct CodedChannel {
+    pub mode: ChannelMode,
+    /// number of bits needed to represent a sample of the coded channel
+    pub sample_bits: u32,
+    /// the last three samples of the coded channel, oldest first
+    pub history: [i32; 3],
+}
+
+impl ChannelMode {
+    const MODES: [ChannelMode; 4] = [
+        ChannelMode::Independent,
+        ChannelMode::LeftSide,
+        ChannelMode::RightSide,
+        ChannelMode::MidSide,
+    ];
+
+    pub fn from_bits(bits: u8) -> Self {
+        Self::MODES[(bits & 3) as usize]
+    }
+
+    pub fn as_bits(self) -> u8 {
+        self as u8
+    }
+
+    /// # Description
+    /// returns `true` if coded channel `channel_idx` is the side channel
+    pub fn is_side(self, channel_idx: usize) -> bool {
+        matches!(
+            (self, channel_idx),
+            (ChannelMode::LeftSide, 1) | (ChannelMode::RightSide, 0) | (ChannelMode::MidSide, 1)
+        )
+    }
+
+    /// # Description
+    /// maps a `(left, right)` pair to the pair of coded channels
+    pub fn decorrelate(self, left: i32, right: i32) -> (i32, i32) {
+        let side = left - right;
+        match self {
+            ChannelMode::Independent => (left, right),
+            ChannelMode::LeftSide => (left, side),
+            ChannelMode::RightSide => (side, right),
+            ChannelMode::MidSide => ((left + right) >> 1, side),
+        }
+    }
+
+    /// # Description
+    /// maps a pair of coded channels back to `(left, right)`
+    /// ## Comments
+    /// - done in 64 bits so corrupt streams can't overflow, results are saturated to `i32`
+    pub fn correlate(self, a: i32, b: i32) -> (i32, i32) {
+        let (a, b) = (a as i64, b as i64);
+        let (left, right) = match self {
+            ChannelMode::Independent => (a, b),
+            ChannelMode::LeftSide => (a, a - b),
+            ChannelMode::RightSide => (a + b, b),
+            ChannelMode::MidSide => {
+                let side = b;
+                let sum = (a << 1) | (side & 1);
+                ((sum + side) >> 1, (sum - side) >> 1)
+            }
+        };
+        let saturate = |x: i64| x.clamp(i32::MIN as i64, i32::MAX as i64) as i32;
+        (saturate(left), saturate(right))
+    }
+
+    /// # Description
+    /// describes coded channel `channel_idx` given the histories of the original channels
+    /// ## Parameters
+    /// - `histories` the last three samples of every channel, oldest first
+    /// - `bits_per_sample` bits needed to represent a sample of an original channel
+    pub fn coded_channel(
+        self,
+        channel_idx: usize,
+        histories: &[[i32; 3]],
+        bits_per_sample: u32,
+    ) -> CodedChannel {
+        let history = if self == ChannelMode::Independent {
+            histories[channel_idx]
+        } else {
+            let mut history = [0; 3];
+            for (k, h) in history.iter_mut().enumerate() {
+                let (a, b) = self.decorrelate(histories[0][k], histories[1][k]);
+                *h = if channel_idx == 0 { a } else { b };
+            }
+            history
+        };
+
+        CodedChannel {
+            mode: self,
+            sample_bits: bits_per_sample + self.is_side(channel_idx) as u32,
+            history,
+        }
+    }
+
+    /// # Description
+    /// picks the mode that looks the cheapest to encode
+    /// ## Comments
+    /// - cost is estimated with the absolute residuals of a 2nd order fixed predictor
+    pub fn choose(
+        left: &[i32],
+        right: &[i32],
+        left_history: [i32; 3],
+        right_history: [i32; 3],
+    ) -> Self {
+        // estimated costs of left, right, mid and side
+        let mut costs = [0u64; 4];
+
+        let mut prev = [
+            (left_history[1], right_history[1]),
+            (left_history[2], right_history[2]),
+        ];
+        for (&l, &r) in left.iter().zip(right.iter()) {
+            let channels = |(l, r): (i32, i32)| [l, r, (l + r) >> 1, l - r];
+            let x0 = channels((l, r));
+            let x1 = channels(prev[1]);
+            let x2 = channels(prev[0]);
+            for k in 0..4 {
+                costs[k] += (x0[k] as i64 - 2 * x1[k] as i64 + x2[k] as i64).unsigned_abs();
+            }
+            prev = [prev[1], (l, r)];
+        }
+
+        let [left_cost, right_cost, mid_cost, side_cost] = costs;
+        let mode_costs = [
+            (ChannelMode::Independent, left_cost + right_cost),
+            (ChannelMode::LeftSide, left_cost + side_cost),
+            (ChannelMode::RightSide, side_cost + right_cost),
+            (ChannelMode::MidSide, mid_cost + side_cost),
+        ];
+
+        mode_costs
+            .iter()
+            .min_by_key(|&&(_, cost)| cost)
+            .map(|&(mode, _)| mode)
+            .unwrap_or_default()
+    }
+}
+
+mod tests {
+    #[allow(unused_imports)]
+    use super::ChannelMode;
+
+    #[test]
+    fn modes_are_reversible() {
+        let pairs = [
+            (0, 0),
+            (1, 0),
+            (0, 1),
+            (-1, 0),
+            (32767, -32768),
+            (-32768, 32767),
+            (-32768, -32768),
+            (12345, -54),
+        ];
+        for bits in 0..4 {
+            let mode = ChannelMode::from_bits(bits);
+            assert_eq!(mode.as_bits(), bits);
+            for &(left, right) in pairs.iter() {
+                let (a, b) = mode.decorrelate(left, right);
+                assert_eq!(mode.correlate(a, b), (left, right), "{:?}", mode);
+            }
+        }
+    }
+
+    #[test]
+    fn near_mono_prefers_side() {
+        let left = (0..512)
+            .map(|k| ((k as f32 * 0.03).sin() * 20_000.0) as i32)
+            .collect::<Vec<_>>();
+        let right = left.iter().map(|&s| s + 3).collect::<Vec<_>>();
+        let mode = ChannelMode::choose(&left, &right, [0; 3], [0; 3]);
+        assert_ne!(mode, ChannelMode::Independent);
+    }
+}
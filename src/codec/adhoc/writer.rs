@@ -0,0 +1,412 @@
+use super::*;
+
+/// default number of bytes of compressed audio an `AdhocWriter` collects before writing a `StreamPacket`
+pub const DEFAULT_PACKET_SIZE: usize = 64 << 10;
+
+/// # Description
+/// A batch of frame-blocks, files written by `AdhocWriter` are a sequence of these
+/// ## Comments
+/// - layout after the `FileHeader`: `(compression_level: u32, info: StreamInfo)` followed by
+///   bincode `Option<StreamPacket>`s, `None` marks the end of the stream
+/// - bit cursors in `frame_header_list` are positions within the whole stream
+/// - the last chunk of a packet is usually only partially filled, the next packet starts with
+///   the completed version of that chunk
+#[derive(Serialize, Deserialize)]
+pub struct StreamPacket<Headers = FrameHeaders> {
+    /// headers of every frame in the packet
+    pub frame_header_list: Headers,
+    /// index of the first 128-bit chunk of the stream this packet carries
+    pub first_chunk: u64,
+    /// chunks `first_chunk..first_chunk + chunk_list.len()` of the stream
+    pub chunk_list: Vec<u128>,
+}
+
+impl StreamPacket {
+    /// # Description
+    /// reads the next packet of a file written by format `version`, older layouts are converted
+    /// ## Returns
+    /// `None` once the end of the stream has been reached
+    pub fn read_from<Resource>(
+        res: Resource,
+        limit: u64,
+        version: u16,
+    ) -> Result<Option<Self>, Error>
+    where
+        Resource: Read,
+    {
+        match version {
+            0..=1 => Self::read_converted::<_, FrameHeadersV1>(res, limit),
+            2 => Self::read_converted::<_, FrameHeadersV2>(res, limit),
+            _ => Ok(bincode_options(limit).deserialize_from::<_, Option<Self>>(res)?),
+        }
+    }
+
+    /// # Description
+    /// reads a packet whose headers are laid out like `Headers` and converts them
+    fn read_converted<Resource, Headers>(res: Resource, limit: u64) -> Result<Option<Self>, Error>
+    where
+        Resource: Read,
+        Headers: serde::de::DeserializeOwned + Into<FrameHeaders>,
+    {
+        let packet =
+            bincode_options(limit).deserialize_from::<_, Option<StreamPacket<Headers>>>(res)?;
+        Ok(packet.map(|packet| StreamPacket {
+            frame_header_list: packet.frame_header_list.into(),
+            first_chunk: packet.first_chunk,
+            chunk_list: packet.chunk_list,
+        }))
+    }
+}
+
+/// # Description
+/// Encodes straight into a `Write`, frames are written out as they are produced
+/// ## Comments
+/// - memory use doesn't depend on how much audio gets encoded, at most a packet worth of compressed
+///   audio (see `with_packet_size(..)`) is held in memory
+/// - `finalize()` **has** to be called once you are done, it writes out the end of the stream
+/// - the output can be read with `AdhocCodec::load(..)` or `AdhocReader`
+pub struct AdhocWriter<W> {
+    /// does the actual encoding, its `AudioStream` only holds the chunks that haven't been completed yet
+    codec: AdhocCodec,
+    res: W,
+    /// index of the first chunk `codec` still has, everything before it has been written out
+    window_start: u64,
+    /// `true` once the `FileHeader` and stream info have been written
+    is_started: bool,
+    /// a packet gets written once this many chunks are completed
+    packet_chunks: usize,
+}
+
+impl<W> AdhocWriter<W>
+where
+    W: Write,
+{
+    pub fn new(res: W) -> Self {
+        Self {
+            codec: AdhocCodec::new(),
+            res,
+            window_start: 0,
+            is_started: false,
+            packet_chunks: DEFAULT_PACKET_SIZE / 16,
+        }
+    }
+
+    /// # Description
+    /// see `AdhocCodec::with_compression_level(..)`
+    pub fn with_compression_level(mut self, level: u32) -> Self {
+        self.codec = self.codec.with_compression_level(level);
+        self
+    }
+
+    /// # Description
+    /// see `AdhocCodec::with_info(..)`, this **MUST** be called before encoding
+    pub fn with_info(mut self, info: StreamInfo) -> Self {
+        self.codec.set_info(info);
+        self
+    }
+
+    /// # Description
+    /// roughly how many bytes of compressed audio get collected before they are written out
+    /// ## Comments
+    /// - smaller packets mean lower latency but a bit more overhead per packet
+    pub fn with_packet_size(mut self, bytes: usize) -> Self {
+        self.packet_chunks = (bytes / 16).max(1);
+        self
+    }
+
+    /// # Description
+    /// see `AdhocCodec::with_segment_size(..)`
+    pub fn with_segment_size(mut self, samples: usize) -> Self {
+        self.codec = self.codec.with_segment_size(samples);
+        self
+    }
+
+    /// # Description
+    /// see `AdhocCodec::with_target_bitrate(..)`
+    pub fn with_target_bitrate(mut self, kbps: u32) -> Self {
+        self.codec = self.codec.with_target_bitrate(kbps);
+        self
+    }
+
+    /// # Description
+    /// see `AdhocCodec::with_target_snr(..)`
+    pub fn with_target_snr(mut self, db: f32) -> Self {
+        self.codec = self.codec.with_target_snr(db);
+        self
+    }
+
+    /// # Description
+    /// see `AdhocCodec::with_quantization_callback(..)`
+    pub fn with_quantization_callback<Callback>(mut self, callback: Callback) -> Self
+    where
+        Callback: Fn(&FrameContext) -> u32 + Send + Sync + 'static,
+    {
+        self.codec = self.codec.with_quantization_callback(callback);
+        self
+    }
+
+    /// # Description
+    /// see `AdhocCodec::encode_report()`
+    pub fn encode_report(&self) -> EncodeReport {
+        self.codec.encode_report()
+    }
+
+    /// # Description
+    /// see `AdhocCodec::with_frame_size(..)`
+    pub fn with_frame_size(mut self, samples: usize) -> Self {
+        self.codec = self.codec.with_frame_size(samples);
+        self
+    }
+
+    /// # Description
+    /// see `AdhocCodec::with_partition_depth(..)`
+    pub fn with_partition_depth(mut self, depth: u32) -> Self {
+        self.codec = self.codec.with_partition_depth(depth);
+        self
+    }
+
+    /// # Description
+    /// the stream info, or the default one if `with_info(..)` hasn't been called yet
+    pub fn info(&self) -> StreamInfo {
+        self.codec.stream.info().unwrap_or_default()
+    }
+
+    /// # Description
+    /// same as `AdhocCodec::encode_i16(..)`
+    pub fn encode_i16(&mut self, samples: &[i16]) -> Result<usize, Error> {
+        let samples_encoded = self.codec.encode_i16(samples)?;
+        self.write_completed_chunks()?;
+        Ok(samples_encoded)
+    }
+
+    /// # Description
+    /// same as `AdhocCodec::encode_i32(..)`
+    pub fn encode_i32(&mut self, samples: &[i32]) -> Result<usize, Error> {
+        let samples_encoded = self.codec.encode_i32(samples)?;
+        self.write_completed_chunks()?;
+        Ok(samples_encoded)
+    }
+
+    /// # Description
+    /// same as `AdhocCodec::encode_parallel(..)`
+    #[cfg(feature = "parallel")]
+    pub fn encode_parallel(&mut self, samples: &[f32]) -> Result<usize, Error> {
+        let samples_encoded = self.codec.encode_parallel(samples)?;
+        self.write_completed_chunks()?;
+        Ok(samples_encoded)
+    }
+
+    /// # Description
+    /// writes out everything encoded so far and flushes the underlying writer
+    /// ## Comments
+    /// - buffered samples are encoded as a short frame, see `AdhocCodec::flush()`
+    pub fn flush(&mut self) -> Result<(), Error> {
+        self.codec.flush();
+        self.write_packet()?;
+        self.res.flush()?;
+        Ok(())
+    }
+
+    /// # Description
+    /// writes out the rest of the stream along with the end marker
+    /// ## Returns
+    /// the underlying writer
+    pub fn finalize(mut self) -> Result<W, Error> {
+        self.codec.flush();
+        self.write_packet()?;
+        bincode::serialize_into(&mut self.res, &None::<StreamPacket>)?;
+        self.res.flush()?;
+        Ok(self.res)
+    }
+
+    /// # Description
+    /// writes a packet once enough chunks have been completed
+    fn write_completed_chunks(&mut self) -> Result<(), Error> {
+        let completed_chunks = self.codec.stream.len() / 128;
+        if completed_chunks >= self.packet_chunks {
+            self.write_packet()?;
+        }
+        Ok(())
+    }
+
+    /// # Description
+    /// writes the `FileHeader` and stream info, if that hasn't happened yet
+    fn start(&mut self) -> Result<(), Error> {
+        if self.is_started {
+            return Ok(());
+        }
+
+        let info = self.codec.try_info()?;
+        // features get picked frame by frame so any of them can show up later on
+        let mut flags = FileHeader::FLAG_STREAMED
+            | FileHeader::FLAG_LPC
+            | FileHeader::FLAG_STEREO_DECORRELATION;
+        if info.bits_per_sample() != 16 {
+            flags |= FileHeader::FLAG_WIDE_SAMPLES;
+        }
+        if self.codec.quantizes_frames() {
+            flags |= FileHeader::FLAG_FRAME_QUANTIZATION;
+        }
+
+        FileHeader::new(flags).write_to(&mut self.res)?;
+        bincode::serialize_into(&mut self.res, &(self.codec.compression_level, info))?;
+        self.is_started = true;
+        Ok(())
+    }
+
+    /// # Description
+    /// writes every frame encoded since the last packet, completed chunks are dropped afterwards
+    fn write_packet(&mut self) -> Result<(), Error> {
+        #[derive(Serialize)]
+        struct StreamPacketRef<'a> {
+            frame_header_list: &'a FrameHeaders,
+            first_chunk: u64,
+            chunk_list: &'a [u128],
+        }
+
+        self.start()?;
+
+        let frame_header_list = &mut self.codec.frame_header_list;
+        let stream = &mut self.codec.stream;
+        if frame_header_list.is_empty() {
+            return Ok(());
+        }
+
+        frame_header_list.offset_bit_cursors(self.window_start * 128);
+        let packet = StreamPacketRef {
+            frame_header_list,
+            first_chunk: self.window_start,
+            chunk_list: stream.chunks(),
+        };
+        bincode::serialize_into(&mut self.res, &Some(packet))?;
+
+        frame_header_list.clear();
+        let completed_chunks = stream.len() / 128;
+        stream.discard_chunks(completed_chunks);
+        self.window_start += completed_chunks as u64;
+        Ok(())
+    }
+}
+
+impl<W> Streamable for AdhocWriter<W>
+where
+    W: Write,
+{
+    fn info(&self) -> StreamInfo {
+        self.info()
+    }
+
+    fn filesize_upperbound(&self) -> u64 {
+        self.window_start * 128 + self.codec.filesize_upperbound()
+    }
+
+    fn encode(&mut self, samples: &[f32]) -> Result<usize, Error> {
+        let samples_encoded = self.codec.encode(samples)?;
+        self.write_completed_chunks()?;
+        Ok(samples_encoded)
+    }
+
+    fn decode(&mut self, _samples: &mut [f32]) -> Result<Option<usize>, Error> {
+        Err(Error::Unsupported("AdhocWriter can only encode"))
+    }
+
+    fn seek(&mut self, _dt: SeekFrom) -> Result<(), Error> {
+        Err(Error::Unsupported(
+            "AdhocWriter writes the stream front to back",
+        ))
+    }
+
+    fn seek_to_sample(&mut self, _sample: u64) -> Result<(), Error> {
+        Err(Error::Unsupported(
+            "AdhocWriter writes the stream front to back",
+        ))
+    }
+
+    /// everything gets appended, so this is always the end of the stream
+    fn position_in_samples(&self) -> u64 {
+        self.codec.duration_in_samples()
+    }
+
+    fn duration_in_samples(&self) -> u64 {
+        self.codec.duration_in_samples()
+    }
+}
+
+mod tests {
+    #[allow(unused_imports)]
+    use super::*;
+
+    #[test]
+    fn queries_before_info_is_set() {
+        let mut writer = AdhocWriter::new(Vec::new());
+        assert_eq!(writer.info().channels(), 0);
+        assert_eq!(Streamable::info(&writer).sample_rate, 0);
+        assert_eq!(writer.duration_in_samples(), 0);
+        assert_eq!(writer.position_in_samples(), 0);
+        writer.filesize_upperbound();
+        writer.encode_report();
+        assert!(matches!(writer.encode(&[0.0; 4]), Err(Error::InfoNotSet)));
+    }
+
+    #[test]
+    fn streamed_files_match_in_memory_encoding() {
+        let info = StreamInfo::new(44100, 2);
+        let samples = (0..60_000)
+            .map(|k| ((k as f32 * 0.013).sin() * 12_000.0) as i16 ^ (k % 5) as i16)
+            .collect::<Vec<_>>();
+
+        let mut codec = AdhocCodec::new().with_info(info);
+        let mut writer = AdhocWriter::new(Vec::new())
+            .with_info(info)
+            .with_packet_size(1024);
+        let mut max_buffered = 0;
+        for chunk in samples.chunks(2048) {
+            codec.encode_i16(chunk).unwrap();
+            writer.encode_i16(chunk).unwrap();
+            max_buffered = max_buffered.max(writer.codec.stream.blocks_allocated());
+        }
+        let bytes = writer.finalize().unwrap();
+        codec.flush();
+
+        // the writer never holds much more than a packet
+        assert!(max_buffered * 16 < 8 * 1024, "{}", max_buffered);
+
+        let loaded = AdhocCodec::load(Cursor::new(&bytes)).unwrap();
+        assert_eq!(loaded.stream.chunks(), codec.stream.chunks());
+        assert_eq!(
+            loaded.frame_header_list.len(),
+            codec.frame_header_list.len()
+        );
+
+        let mut loaded = loaded;
+        let mut decoded = vec![0; samples.len() + 16];
+        let samples_read = loaded.decode_i16(&mut decoded).unwrap().unwrap();
+        assert_eq!(&decoded[0..samples_read], &samples[..]);
+
+        let mut reader = AdhocReader::new(Cursor::new(&bytes)).unwrap();
+        let mut streamed = Vec::new();
+        let mut buffer = [0; 1000];
+        while let Some(samples_read) = reader.decode_i16(&mut buffer).unwrap() {
+            streamed.extend_from_slice(&buffer[0..samples_read]);
+        }
+        assert_eq!(streamed, samples);
+    }
+
+    #[test]
+    fn unfinished_streams_are_errors() {
+        let info = StreamInfo::new(8000, 1);
+        let mut writer = AdhocWriter::new(Vec::new()).with_info(info);
+        writer.encode(&[0.25; 4096]).unwrap();
+        writer.flush().unwrap();
+
+        // no end marker
+        let bytes = writer.res.clone();
+        assert!(matches!(
+            AdhocCodec::load(Cursor::new(&bytes)),
+            Err(Error::Io(_))
+        ));
+
+        let mut writer = AdhocWriter::new(Vec::new());
+        assert!(matches!(writer.flush(), Err(Error::InfoNotSet)));
+    }
+}
@@ -1,256 +1,350 @@
-use std::{
-    fmt::{Debug, Display},
-    io::{Read, Seek, SeekFrom, Write},
-    mem::{self},
-    slice, str,
+use std::io::{self, Read, Seek, SeekFrom, Write};
+
+use super::{
+    dequantize_sample, max_sample, quantize_sample, rescale_sample, resolve_seek, Error,
+    StreamInfo, Streamable,
 };
 
-use super::{normalize_sample, truncate_sample, StreamInfo, Streamable};
-
-#[derive(Debug)]
-#[repr(C, align(1))]
-struct RawWavHeader {
-    riff: [u8; 4],
-    file_size: u32,
-    wave: [u8; 4],
-    fmt: [u8; 4],
-    cksize: u32,
-    audio_format: i16,
-    num_channels: i16,
-    frequncy: i32,
-    bytes_per_sec: i32,
-    block_align: i16,
-    bits_per_sample: i16,
-    data_header: [u8; 4],
-    file_size_data: u32,
-}
-impl Default for RawWavHeader {
-    fn default() -> Self {
-        Self {
-            riff: [b'R', b'I', b'F', b'F'],
-            file_size: 0,
-            wave: [b'W', b'A', b'V', b'E'],
-            fmt: [b'f', b'm', b't', b' '],
-            cksize: 0,
-            audio_format: 0,
-            num_channels: 0,
-            frequncy: 0,
-            bytes_per_sec: 0,
-            block_align: 0,
-            bits_per_sample: 0,
-            data_header: [b'd', b'a', b't', b'a'],
-            file_size_data: 0,
-        }
-    }
-}
-impl Display for RawWavHeader {
-    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
-        write!(f, "riff:{}\n", str::from_utf8(&self.riff).ok().unwrap())?;
-        write!(f, "file_size:{}\n", self.file_size)?;
-        write!(f, "wave:{}\n", str::from_utf8(&self.wave).ok().unwrap())?;
-        write!(f, "fmt:{}\n", str::from_utf8(&self.fmt).ok().unwrap())?;
-        write!(f, "data_len:{}\n", self.cksize)?;
-        write!(f, "sample_type:{}\n", self.audio_format)?;
-        write!(f, "frequency:{}\n", self.frequncy)?;
-        write!(f, "bytes_per_sec:{}\n", self.bytes_per_sec)?;
-        write!(f, "block_align:{}\n", self.block_align)?;
-        write!(f, "bits_per_sample:{}\n", self.bits_per_sample)?;
-        write!(
-            f,
-            "data_header:{}\n",
-            str::from_utf8(&self.data_header).ok().unwrap()
-        )?;
-        write!(f, "file_size(data):{}\n", self.file_size_data)?;
+mod riff;
+pub use riff::*;
 
-        Ok(())
-    }
-}
+mod format;
+pub use format::*;
 
-impl RawWavHeader {
-    pub fn as_bytes(&self) -> &[u8] {
-        unsafe {
-            let bytes: *const u8 = mem::transmute(self);
-            slice::from_raw_parts(bytes, mem::size_of::<RawWavHeader>())
-        }
-    }
-    pub fn as_bytes_mut(&mut self) -> &mut [u8] {
-        unsafe {
-            let bytes: *mut u8 = mem::transmute(self);
-            slice::from_raw_parts_mut(bytes, mem::size_of::<RawWavHeader>())
-        }
-    }
-}
+mod reader;
+pub use reader::*;
+
+mod writer;
+pub use writer::*;
 
 /// # Description
 /// A utility for reading/writing WAVE streams
+/// ## Comments
+/// - samples are kept in the format they are saved in (see `with_sample_format(..)`), loaded files keep
+///   the format they came in
 pub struct WavCodec {
+    /// `bits_per_sample` is the bit depth of `encode_i32(..)`/`decode_i32(..)`
     info: StreamInfo,
-    /// PCM data in bytes (internally it is i16 )
+    /// how samples are laid out in `pcm`
+    sample_format: SampleFormat,
+    /// contents of the data chunk
     pcm: Vec<u8>,
-    /// cursor is index by sample (i16) not byte position
-    short_cursor: u64,
+    /// cursor is index by sample not byte position
+    sample_cursor: u64,
+    /// chunks other than `fmt ` and `data`, written back out by `save_to(..)`
+    chunk_list: Vec<RiffChunk>,
 }
 
 impl WavCodec {
+    /// # Description
+    /// creates an empty stream
+    /// ## Comments
+    /// - samples get saved as the integer format that fits `info.bits_per_sample()`, use
+    ///   `with_sample_format(..)` to pick another one
     pub fn new(info: StreamInfo) -> Self {
         Self {
             info,
+            sample_format: SampleFormat::for_bits(info.bits_per_sample()),
             pcm: Vec::new(),
-            short_cursor: 0,
+            sample_cursor: 0,
+            chunk_list: Vec::new(),
+        }
+    }
+
+    /// # Description
+    /// sets the format samples are stored (and saved) in
+    /// ## Comments
+    /// - samples that are already there get converted, widening an integer format is lossless
+    /// - `info().bits_per_sample()` is left alone, it's what `encode_i32(..)` and `decode_i32(..)` work with
+    pub fn with_sample_format(mut self, sample_format: SampleFormat) -> Self {
+        if sample_format != self.sample_format {
+            let old_size = self.sample_format.bytes_per_sample();
+            let new_size = sample_format.bytes_per_sample();
+            let mut pcm = vec![0; self.num_samples() * new_size];
+            for (old, new) in self
+                .pcm
+                .chunks_exact(old_size)
+                .zip(pcm.chunks_exact_mut(new_size))
+            {
+                self.sample_format.transcode(old, sample_format, new);
+            }
+            self.pcm = pcm;
+            self.sample_format = sample_format;
         }
+        self
+    }
+
+    pub fn sample_format(&self) -> SampleFormat {
+        self.sample_format
     }
 
     fn info(&self) -> StreamInfo {
         self.info
     }
 
+    /// # Description
+    /// chunks of the file that aren't `fmt ` or `data` (`LIST`, `bext`, ...), in the order they were found
+    pub fn chunks(&self) -> &[RiffChunk] {
+        &self.chunk_list
+    }
+
+    /// # Description
+    /// lets you add, change or drop chunks before the file gets saved
+    /// ## Comments
+    /// - `save_to(..)` writes them between the `fmt ` and `data` chunks
+    pub fn chunks_mut(&mut self) -> &mut Vec<RiffChunk> {
+        &mut self.chunk_list
+    }
+
+    /// # Description
+    /// the `fact` chunk, float files are supposed to have one
+    fn fact_chunk(&self) -> Option<RiffChunk> {
+        // RF64 files keep the real count in the ds64 chunk
+        let frames = self.duration_in_samples().min(DS64_SIZE as u64) as u32;
+        self.sample_format
+            .is_float()
+            .then(|| RiffChunk::new(*b"fact", frames.to_le_bytes().to_vec()))
+    }
+
+    /// # Description
+    /// size in bytes of everything `save_to(..)` writes that isn't PCM data
+    fn header_len(&self, is_rf64: bool) -> u64 {
+        let chunks_len = self
+            .chunk_list
+            .iter()
+            .chain(self.fact_chunk().iter())
+            .map(RiffChunk::len_in_bytes)
+            .sum::<u64>();
+        // streams `save_to(..)` refuses to write get the largest `fmt ` chunk there is
+        let format_len = WavFormat::new(self.info, self.sample_format)
+            .map_or(WavFormat::LEN + FormatExtension::LEN, |format| {
+                format.len_in_bytes()
+            });
+        let ds64_len = if is_rf64 {
+            ChunkHeader::LEN + Ds64::default().with_chunks(&self.chunk_list).len_in_bytes()
+        } else {
+            0
+        };
+        RIFF_HEADER_LEN
+            + ds64_len
+            + ChunkHeader::LEN
+            + format_len as u64
+            + chunks_len
+            + ChunkHeader::LEN
+            + (self.pcm.len() as u64 & 1)
+    }
+
+    /// # Description
+    /// `true` when the file is too big for 32-bit sizes and gets saved as RF64
+    fn is_rf64(&self) -> bool {
+        self.header_len(false) - ChunkHeader::LEN + self.pcm.len() as u64 > u32::MAX as u64
+    }
+
     /// # Description
     /// Writes wav file to `Resource`
     /// ## Comments
     /// - `Resource` is usually `Vec<u8>` or  `fs::File`
-    /// look at the tests for examples on how to use it
-    pub fn save_to<Resource>(&self, mut dst: Resource) -> Result<(), &'static str>
+    ///   look at the tests for examples on how to use it
+    /// - files over 4GiB are saved as RF64 (sizes go in a `ds64` chunk), smaller ones are plain RIFF
+    pub fn save_to<Resource>(&self, dst: Resource) -> Result<(), Error>
     where
         Resource: Write,
     {
-        let mut header = RawWavHeader::default();
-        header.file_size = mem::size_of::<RawWavHeader>() as u32 - 8 + self.pcm.len() as u32;
-        header.frequncy = self.info.sample_rate as i32;
-        header.audio_format = 1;
-        header.num_channels = self.info.channels as i16;
-        header.cksize = 16;
-        header.bytes_per_sec = (header.frequncy * header.num_channels as i32 * 16) / 8;
-        header.block_align = (header.num_channels * 16) / 8;
-        header.bits_per_sample = 16;
-        header.file_size_data = self.pcm.len() as u32;
-        // println!("header:\n{}\n",header);
-        dst.write(header.as_bytes())
-            .map_err(|_| "failed to write header")?;
-        dst.write_all(&self.pcm)
-            .map_err(|_| "failed to write pcm data")
+        self.save_as(dst, self.is_rf64())
     }
 
-    /// parses and loads wav file
-    pub fn load<Resource>(mut wav_res: Resource) -> Result<Self, &'static str>
+    fn save_as<Resource>(&self, mut dst: Resource, is_rf64: bool) -> Result<(), Error>
     where
-        Resource: Read + Seek,
+        Resource: Write,
     {
-        let mut header_binary = [0u8; 256];
-
-        wav_res
-            .read_exact(&mut header_binary)
-            .map_err(|_| "failed to read header bytes")?;
-
-        let mut header_slice = &header_binary[..];
-        let mut header = RawWavHeader::default();
-
-        // println!("buffer size ={}", wav_binary.len());
-
-        let (data_start, _) = header_binary
-            .windows(4)
-            .enumerate()
-            .find(|(_, window)| {
-                let number = [b'd', b'a', b't', b'a'];
-                window == &number
-            })
-            .ok_or("data region not found")?;
-
-        header_slice
-            .clone()
-            .read(header.as_bytes_mut())
-            .map_err(|_| "header failed to read")?;
-
-        header
-            .data_header
-            .iter_mut()
-            .zip(header_slice[data_start..data_start + 4].iter())
-            .for_each(|(data_header_byte, &b)| {
-                *data_header_byte = b;
-            });
-
-        header_slice = &header_slice[data_start + 4..];
-        header.file_size_data = u32::from_le_bytes([
-            header_slice[0],
-            header_slice[1],
-            header_slice[2],
-            header_slice[3],
-        ]);
-
-        if header.bits_per_sample != 16 && header.bits_per_sample != 8 {
-            return Err("invalid bits per sample, either 16 or 8 bits per sample is supported ");
+        let format = WavFormat::new(self.info, self.sample_format)?;
+        if !is_rf64 && self.chunk_list.iter().any(RiffChunk::is_oversized) {
+            return Err(Error::UnsupportedFormat(
+                "chunks over 4GiB only fit in RF64 files".to_string(),
+            ));
         }
-        if header.audio_format != 1 {
-            return Err("formats other than PCM liner isn't supported");
+        let riff_size = self.header_len(is_rf64) - ChunkHeader::LEN + self.pcm.len() as u64;
+        if is_rf64 {
+            dst.write_all(b"RF64")?;
+            dst.write_all(&DS64_SIZE.to_le_bytes())?;
+            dst.write_all(b"WAVE")?;
+            let ds64 = Ds64 {
+                riff_size,
+                data_size: self.pcm.len() as u64,
+                sample_count: self.duration_in_samples(),
+                table: Vec::new(),
+            }
+            .with_chunks(&self.chunk_list);
+            RiffChunk::new(*b"ds64", ds64.to_bytes()).write_to(&mut dst)?;
+        } else {
+            dst.write_all(b"RIFF")?;
+            dst.write_all(&(riff_size as u32).to_le_bytes())?;
+            dst.write_all(b"WAVE")?;
         }
 
-        // println!("parsed header: {}",header);
-        // println!("pcm data = {} byles", wav_binary.len());
-
-        wav_res
-            .seek(SeekFrom::Start(data_start as u64 + 8))
-            .map_err(|_| "failed to seek to pcm data")?;
-
-        let mut pcm = Vec::<u8>::new();
-        wav_res
-            .read_to_end(&mut pcm)
-            .map_err(|_| "failed to read to end")?;
-
-        if header.bits_per_sample == 8 {
-            //convert 8bit stream to 16bit
-            let len = pcm.len();
-            pcm.resize(pcm.len() * 2, 0);
-            for k in (0..len).rev() {
-                let sample = pcm[k];
-                let scaled_sample = ((sample as f32 / 255.0) * 2.0 - 1.0).clamp(-1.0, 1.0)
-                    * ((i16::MAX - 1) as f32);
-                let sample = scaled_sample as i16;
-                pcm[2 * k + 0] = ((sample >> 0) & 0xff) as u8;
-                pcm[2 * k + 1] = ((sample >> 8) & 0xff) as u8;
-            }
+        ChunkHeader::write_to(*b"fmt ", format.len_in_bytes() as u32, &mut dst)?;
+        dst.write_all(&format.to_bytes())?;
+
+        for chunk in self.fact_chunk().iter().chain(self.chunk_list.iter()) {
+            chunk.write_to(&mut dst)?;
         }
 
-        // println!("header:\n{}\n", header);
+        let data_size = if is_rf64 {
+            DS64_SIZE
+        } else {
+            self.pcm.len() as u32
+        };
+        ChunkHeader::write_to(*b"data", data_size, &mut dst)?;
+        dst.write_all(&self.pcm)?;
+        if self.pcm.len() % 2 == 1 {
+            dst.write_all(&[0])?;
+        }
+        Ok(())
+    }
 
+    /// # Description
+    /// parses and loads wav file
+    /// ## Comments
+    /// - chunks are walked one by one, `fmt ` and `data` can be anywhere in the file
+    /// - every other chunk is kept (see `chunks()`), except `fact` which `save_to(..)` writes itself
+    /// - RF64/BW64 files (over 4GiB) are loaded too
+    /// - the whole `data` chunk is read into memory, `WavReader` decodes without doing that
+    pub fn load<Resource>(wav_res: Resource) -> Result<Self, Error>
+    where
+        Resource: Read + Seek,
+    {
+        let mut reader = WavReader::new(wav_res)?;
         Ok(Self {
-            info: StreamInfo {
-                sample_rate: header.frequncy as u32,
-                channels: header.num_channels as u32,
-            },
-            pcm,
-            short_cursor: 0,
+            info: reader.info(),
+            sample_format: reader.sample_format(),
+            pcm: reader.read_remaining()?,
+            sample_cursor: 0,
+            chunk_list: reader.chunks().to_vec(),
         })
     }
 
     fn num_samples(&self) -> usize {
-        self.pcm.len() / 2
+        self.pcm.len() / self.sample_format.bytes_per_sample()
     }
 
-    fn get_pcm(pcm: &Vec<u8>) -> &[i16] {
-        unsafe { slice::from_raw_parts(pcm.as_ptr() as *const i16, pcm.len() / 2) }
+    /// writes a sample at current cursor positions (will offset the cursor)
+    fn write_sample<Encode>(&mut self, encode: Encode)
+    where
+        Encode: FnOnce(&mut [u8]),
+    {
+        let size = self.sample_format.bytes_per_sample();
+        let byte_index = self.sample_cursor as usize * size;
+        if byte_index >= self.pcm.len() {
+            self.pcm.resize(byte_index + size, 0);
+        }
+        encode(&mut self.pcm[byte_index..byte_index + size]);
+        self.sample_cursor += 1;
     }
 
-    #[allow(dead_code)]
-    fn get_pcm_mut(pcm: &mut Vec<u8>) -> &mut [i16] {
-        unsafe { slice::from_raw_parts_mut(pcm.as_ptr() as *mut i16, pcm.len() / 2) }
+    /// # Description
+    /// number of channels in a block
+    /// ## Comments
+    /// - fails with `Error::UnsupportedFormat` if the stream has no channels, like `WavFormat::new(..)`
+    fn num_channels(&self) -> Result<usize, Error> {
+        match self.info.channels() {
+            0 => Err(Error::UnsupportedFormat(
+                "wav files have at least one channel".to_string(),
+            )),
+            num_channels => Ok(num_channels),
+        }
     }
 
-    /// writes a sample at current cursor positions (will offset the cursor)
-    fn write_sample(&mut self, truncated_sample: i16) {
-        let upper_byte = ((truncated_sample >> 8) & 0xff) as u8;
-        let lower_byte = ((truncated_sample >> 0) & 0xff) as u8;
-        if (self.short_cursor as usize) < self.num_samples() {
-            let byte_index = (self.short_cursor as usize) << 1;
-            self.pcm[byte_index + 0] = lower_byte;
-            self.pcm[byte_index + 1] = upper_byte;
-        } else {
-            self.pcm.push(lower_byte);
-            self.pcm.push(upper_byte);
+    /// # Description
+    /// writes every whole block of interleaved `samples`, `encode` stores a sample in the sample format
+    fn encode_with<T, Encode>(&mut self, samples: &[T], encode: Encode) -> Result<usize, Error>
+    where
+        T: Copy,
+        Encode: Fn(SampleFormat, T, &mut [u8]),
+    {
+        let num_channels = self.num_channels()?;
+        let valid_len = (samples.len() / num_channels) * num_channels;
+        let sample_format = self.sample_format;
+        for &samp in &samples[0..valid_len] {
+            self.write_sample(|out| encode(sample_format, samp, out));
         }
-        self.offset_cursor(1);
+        Ok(valid_len)
+    }
+
+    /// # Description
+    /// writes interleaved 16-bit `samples` as is, they never get converted to floating point
+    /// ## Comments
+    /// - samples get shifted to the sample format's bit depth, so this is exact for 16 bits and wider
+    /// ## Returns
+    /// number of samples encoded
+    pub fn encode_i16(&mut self, samples: &[i16]) -> Result<usize, Error> {
+        self.encode_with(samples, |format, sample, out| {
+            format.encode_int(sample as i32, 16, out)
+        })
+    }
+
+    /// # Description
+    /// reads interleaved 16-bit samples into `out`
+    /// ## Comments
+    /// - samples stored as exactly 16 bits come out as they are
+    /// ## Returns
+    /// number of samples decoded, `None` once the end of the stream has been reached
+    pub fn decode_i16(&mut self, out: &mut [i16]) -> Result<Option<usize>, Error> {
+        self.decode_with(out, |format, bytes| format.decode_int(bytes, 16) as i16)
+    }
+
+    /// # Description
+    /// writes interleaved integer `samples` that are at the stream's bit depth
+    /// ## Comments
+    /// - samples are right-justified, a 24-bit stream expects samples in `-8388608..=8388607`
+    /// - exact as long as the sample format is an integer format at least as wide as the stream
+    /// ## Returns
+    /// number of samples encoded
+    pub fn encode_i32(&mut self, samples: &[i32]) -> Result<usize, Error> {
+        let bits_per_sample = self.info.bits_per_sample();
+        self.encode_with(samples, |format, sample, out| {
+            format.encode_int(sample, bits_per_sample, out)
+        })
+    }
+
+    /// # Description
+    /// reads interleaved integer samples at the stream's bit depth into `out`
+    /// ## Comments
+    /// - see `encode_i32(..)`
+    /// ## Returns
+    /// number of samples decoded, `None` once the end of the stream has been reached
+    pub fn decode_i32(&mut self, out: &mut [i32]) -> Result<Option<usize>, Error> {
+        let bits_per_sample = self.info.bits_per_sample();
+        self.decode_with(out, |format, bytes| {
+            format.decode_int(bytes, bits_per_sample)
+        })
     }
 
-    fn offset_cursor(&mut self, offset: i64) {
-        self.short_cursor = (self.short_cursor as i64 + offset).max(0) as u64;
+    /// # Description
+    /// reads samples into `out`, `convert` maps stored samples to the output format
+    fn decode_with<T, Convert>(
+        &mut self,
+        out: &mut [T],
+        convert: Convert,
+    ) -> Result<Option<usize>, Error>
+    where
+        Convert: Fn(SampleFormat, &[u8]) -> T,
+    {
+        let num_channels = self.num_channels()?;
+        let size = self.sample_format.bytes_per_sample();
+        let start = (self.sample_cursor as usize).min(self.num_samples());
+
+        //makes sure we can't write partial PCM 'blocks'
+        let valid_length = (out.len() / num_channels) * num_channels;
+
+        let stored = self.pcm[start * size..].chunks_exact(size);
+        let mut out_cursor = 0;
+        for (out, bytes) in out[0..valid_length].iter_mut().zip(stored) {
+            *out = convert(self.sample_format, bytes);
+            out_cursor += 1;
+        }
+        self.sample_cursor = (start + out_cursor) as u64;
+
+        Ok((out_cursor > 0).then_some(out_cursor))
     }
 }
 impl Streamable for WavCodec {
@@ -259,73 +353,47 @@ impl Streamable for WavCodec {
     }
 
     fn filesize_upperbound(&self) -> u64 {
-        let wav_header_in_bits = mem::size_of::<RawWavHeader>() as u64 * 8;
+        let wav_header_in_bits = self.header_len(self.is_rf64()) * 8;
         let wav_pcm_in_bits = self.pcm.len() as u64 * 8;
         wav_header_in_bits + wav_pcm_in_bits
     }
 
-    fn encode(&mut self, samples: &[f32]) -> Option<usize> {
-        let num_channels = self.info.channels();
-        let valid_len = (samples.len() / num_channels) * num_channels;
-        for &samp in &samples[0..valid_len] {
-            let sample_i16 = truncate_sample(samp);
-            self.write_sample(sample_i16);
-        }
-        Some(valid_len)
+    fn encode(&mut self, samples: &[f32]) -> Result<usize, Error> {
+        self.encode_with(samples, |format, sample, out| {
+            format.encode_f32(sample, out)
+        })
+    }
+    fn decode(&mut self, out: &mut [f32]) -> Result<Option<usize>, Error> {
+        self.decode_with(out, |format, bytes| format.decode_f32(bytes))
     }
-    fn decode(&mut self, out: &mut [f32]) -> Option<usize> {
-        let mut out_cursor = 0;
-
-        let num_channels = self.info().channels();
-        let stream_length = self.num_samples() as u64;
-        let cursor = &mut self.short_cursor;
-        let samples_list = Self::get_pcm(&self.pcm);
-
-        //makes sure we can't write partial PCM 'blocks'
-        let valid_length = (out.len() / num_channels) * num_channels;
-
-        while *cursor < stream_length && out_cursor < valid_length {
-            let sample_i16 = samples_list[*cursor as usize];
-            let normalized_sample = normalize_sample(sample_i16);
-            out[out_cursor] = normalized_sample;
-            *cursor += 1;
-            out_cursor += 1;
-        }
 
-        (out_cursor > 0).then(|| out_cursor)
+    fn seek(&mut self, dt: SeekFrom) -> Result<(), Error> {
+        let position = self.position_in_samples();
+        let len = self.duration_in_samples();
+        self.seek_to_sample(resolve_seek(dt, position, len, self.info.sample_rate))
     }
 
-    fn seek(&mut self, dt: SeekFrom) {
-        let sample_rate = self.info.sample_rate as f32;
-        let num_channels = self.info.channels() as i64;
-        let num_samples = self.num_samples() as i64;
+    fn seek_to_sample(&mut self, sample: u64) -> Result<(), Error> {
+        let num_channels = self.info.channels().max(1) as u64;
+        self.sample_cursor = sample.min(self.duration_in_samples()) * num_channels;
+        Ok(())
+    }
 
-        let sample_rate_in_milliseconds = sample_rate / 1000.0;
+    fn position_in_samples(&self) -> u64 {
+        self.sample_cursor / self.info.channels().max(1) as u64
+    }
 
-        match dt {
-            SeekFrom::Current(dt) => {
-                let sample_offset = (sample_rate_in_milliseconds * (dt as f32)) as i64;
-                let block_index = (self.short_cursor as i64 / num_channels) * num_channels;
-                self.short_cursor =
-                    (block_index + sample_offset * num_channels).clamp(0, num_samples - 1) as u64;
-            }
-            SeekFrom::Start(dt) => {
-                let sample_offset = (sample_rate_in_milliseconds * (dt as f32)) as i64;
-                self.short_cursor = (sample_offset * num_channels).clamp(0, num_samples - 1) as u64;
-            }
-            SeekFrom::End(dt) => {
-                let sample_offset = (sample_rate_in_milliseconds * (dt as f32)) as i64;
-                let block_index = num_samples - num_samples;
-                self.short_cursor =
-                    (block_index + sample_offset * num_channels).clamp(0, num_samples - 1) as u64;
-            }
-        }
+    fn duration_in_samples(&self) -> u64 {
+        self.num_samples() as u64 / self.info.channels().max(1) as u64
     }
 }
 
 mod test {
     #[allow(unused_imports)]
-    use super::{StreamInfo, Streamable, WavCodec};
+    use super::{
+        ChunkHeader, Error, RiffChunk, RiffChunks, SampleFormat, StreamInfo, Streamable, WavCodec,
+        WavFormat, WavReader, WavWriter,
+    };
     #[allow(unused_imports)]
     use std::{
         fs,
@@ -371,13 +439,13 @@ mod test {
         // wav_codec.seek(SeekFrom::Start(60_000));
 
         //start writing from 1minute mark
-        while let Some(samples_read) = wav_codec.decode(&mut buffer) {
-            new_wav.encode(&buffer[0..samples_read]);
+        while let Some(samples_read) = wav_codec.decode(&mut buffer).unwrap() {
+            new_wav.encode(&buffer[0..samples_read]).unwrap();
         }
 
         // wav_codec.seek(SeekFrom::Start(0));
         // new_wav.seek(SeekFrom::Start(0));
-        // while let Some(samples_read) = wav_codec.decode(&mut buffer) {
+        // while let Some(samples_read) = wav_codec.decode(&mut buffer).unwrap() {
         //     new_wav.encode(&buffer[0..samples_read]);
         // }
 
@@ -385,4 +453,378 @@ mod test {
             fs::File::create("./resources/taunt_copy.wav").expect("failed to create file");
         new_wav.save_to(new_file).expect("failed to write");
     }
+
+    #[test]
+    fn chunks_before_data_are_walked_and_kept() {
+        let info = StreamInfo::new(8000, 2);
+        let samples = [100i16, -100, 2000, -2000, 32767, -32768];
+
+        // a big LIST chunk ahead of `fmt `, a padded odd sized chunk and a `fmt ` with a cbSize
+        let mut bytes = Vec::new();
+        bytes.extend_from_slice(b"RIFF\0\0\0\0WAVE");
+        let list = RiffChunk::new(*b"LIST", vec![7; 1001]);
+        list.write_to(&mut bytes).unwrap();
+        ChunkHeader::write_to(*b"fmt ", 18, &mut bytes).unwrap();
+        bytes.extend_from_slice(&WavFormat::new(info, SampleFormat::I16).unwrap().to_bytes());
+        bytes.extend_from_slice(&[0, 0]);
+        let junk = RiffChunk::new(*b"JUNK", vec![1, 2, 3]);
+        junk.write_to(&mut bytes).unwrap();
+        ChunkHeader::write_to(*b"data", 12, &mut bytes).unwrap();
+        bytes.extend(samples.iter().flat_map(|s| s.to_le_bytes()));
+
+        let mut wav = WavCodec::load(Cursor::new(&bytes)).unwrap();
+        assert_eq!(wav.info().sample_rate, 8000);
+        assert_eq!(wav.info().channels(), 2);
+        assert_eq!(wav.chunks(), &[list.clone(), junk.clone()]);
+        let mut decoded = [0; 8];
+        assert_eq!(wav.decode_i16(&mut decoded).unwrap(), Some(6));
+        assert_eq!(&decoded[0..6], &samples[..]);
+
+        // chunks survive a round trip and the result is tiny, well under 256 bytes without the LIST
+        wav.chunks_mut().retain(|chunk| chunk.id != *b"LIST");
+        let mut saved = Vec::new();
+        wav.save_to(&mut saved).unwrap();
+        assert_eq!(saved.len(), 12 + 8 + 16 + 12 + 8 + 12);
+        let riff_size = u32::from_le_bytes([saved[4], saved[5], saved[6], saved[7]]);
+        assert_eq!(riff_size as usize, saved.len() - 8);
+        let mut reloaded = WavCodec::load(Cursor::new(&saved)).unwrap();
+        assert_eq!(reloaded.chunks(), &[junk]);
+        assert_eq!(reloaded.decode_i16(&mut decoded).unwrap(), Some(6));
+        assert_eq!(&decoded[0..6], &samples[..]);
+
+        // a file that never gets to its `data` chunk
+        let truncated = &bytes[0..12 + 8 + 1001 + 1 + 8 + 18];
+        assert!(matches!(
+            WavCodec::load(Cursor::new(truncated)),
+            Err(Error::CorruptHeader(_))
+        ));
+    }
+
+    #[test]
+    fn oversized_chunks_get_a_ds64_entry() {
+        /// keeps the first bytes written and only counts the rest
+        struct HeaderOnly {
+            header: Vec<u8>,
+            len: u64,
+        }
+        impl Write for HeaderOnly {
+            fn write(&mut self, buf: &[u8]) -> std::io::Result<usize> {
+                let kept = buf.len().min(256 - self.header.len());
+                self.header.extend_from_slice(&buf[0..kept]);
+                self.len += buf.len() as u64;
+                Ok(buf.len())
+            }
+            fn flush(&mut self) -> std::io::Result<()> {
+                Ok(())
+            }
+        }
+
+        // zeroed allocations are mapped lazily, the body is never touched
+        let big_len = u32::MAX as usize + 2;
+        let big = || RiffChunk::new(*b"big ", vec![0; big_len]);
+        let info = StreamInfo::new(8000, 2);
+        let mut wav = WavCodec::new(info);
+        wav.encode_i16(&[1, 2, 3, 4]).unwrap();
+        wav.chunks_mut().push(big());
+
+        let mut saved = HeaderOnly {
+            header: Vec::new(),
+            len: 0,
+        };
+        wav.save_to(&mut saved).unwrap();
+        assert_eq!(&saved.header[0..4], b"RF64");
+        let mut chunks = RiffChunks::new(Cursor::new(&saved.header)).unwrap();
+        let ds64 = chunks.ds64().unwrap().clone();
+        assert_eq!(ds64.table, [(*b"big ", big_len as u64)]);
+        assert_eq!(ds64.riff_size, saved.len - 8);
+        let header_list = chunks.by_ref().collect::<Result<Vec<_>, _>>().unwrap();
+        let big_header = header_list.iter().find(|h| h.id == *b"big ").unwrap();
+        assert_eq!(big_header.size, big_len as u64);
+
+        // without RF64 there is nowhere to put the size
+        let writer = WavWriter::new(Cursor::new(Vec::new()))
+            .with_info(info)
+            .with_chunk(big());
+        assert!(matches!(
+            writer.finalize(),
+            Err(Error::UnsupportedFormat(_))
+        ));
+    }
+
+    #[test]
+    fn sample_formats_round_trip() {
+        let info = StreamInfo::new(48000, 2);
+        let samples = (0..2000)
+            .map(|k| (k as f32 * 0.031).sin() * 0.9)
+            .chain([1.0, -1.0])
+            .collect::<Vec<_>>();
+
+        let formats = [
+            (SampleFormat::U8, 8, 1, 8),
+            (SampleFormat::I16, 16, 1, 16),
+            (SampleFormat::I24, 24, 0xFFFE, 24),
+            (SampleFormat::I32, 32, 0xFFFE, 32),
+            (SampleFormat::F32, 32, 3, 24),
+            (SampleFormat::F64, 64, 3, 32),
+        ];
+        for (sample_format, bits_per_sample, format_tag, stream_bits) in formats {
+            let mut wav = WavCodec::new(info).with_sample_format(sample_format);
+            wav.encode(&samples).unwrap();
+            let mut bytes = Vec::new();
+            wav.save_to(&mut bytes).unwrap();
+
+            // the fmt chunk comes first
+            let fmt_len = u32::from_le_bytes([bytes[16], bytes[17], bytes[18], bytes[19]]);
+            let format = WavFormat::parse(&bytes[20..20 + fmt_len as usize]).unwrap();
+            assert_eq!(format.format_tag, format_tag);
+            assert_eq!(format.sample_format().unwrap(), sample_format);
+            assert_eq!(format.bits_per_sample, bits_per_sample);
+            assert_eq!(format.block_align as u32, 2 * bits_per_sample as u32 / 8);
+
+            let mut loaded = WavCodec::load(Cursor::new(&bytes)).unwrap();
+            assert_eq!(loaded.sample_format(), sample_format);
+            assert_eq!(loaded.info().bits_per_sample(), stream_bits);
+            assert_eq!(loaded.duration_in_samples(), 1001);
+            assert_eq!(loaded.chunks(), &[]);
+
+            let mut decoded = vec![0.0; samples.len()];
+            assert_eq!(loaded.decode(&mut decoded).unwrap(), Some(samples.len()));
+            let tolerance = 1.0 / ((1 << (stream_bits.min(24) - 1)) - 1) as f32;
+            for (&d, &s) in decoded.iter().zip(samples.iter()) {
+                assert!(
+                    (d - s).abs() <= tolerance,
+                    "{:?}: {} {}",
+                    sample_format,
+                    d,
+                    s
+                );
+            }
+            if sample_format == SampleFormat::F32 {
+                assert_eq!(decoded, samples);
+            }
+        }
+
+        // integers wider than 16 bits never go through floating point
+        let info = info.with_bits_per_sample(24);
+        let wide = [8_388_607, -8_388_608, 1, -1, 0, 4_000_001];
+        let mut wav = WavCodec::new(info);
+        assert_eq!(wav.sample_format(), SampleFormat::I24);
+        wav.encode_i32(&wide).unwrap();
+        let mut bytes = Vec::new();
+        wav.save_to(&mut bytes).unwrap();
+        assert_eq!(bytes.len(), 44 + 24 + 3 * wide.len());
+
+        let mut loaded = WavCodec::load(Cursor::new(&bytes)).unwrap();
+        let mut decoded = [0; 6];
+        loaded.decode_i32(&mut decoded).unwrap();
+        assert_eq!(decoded, wide);
+
+        // widening and narrowing back is lossless
+        let mut loaded = loaded
+            .with_sample_format(SampleFormat::I32)
+            .with_sample_format(SampleFormat::I24);
+        loaded.seek_to_sample(0).unwrap();
+        loaded.decode_i32(&mut decoded).unwrap();
+        assert_eq!(decoded, wide);
+        let mut narrowed = [0; 6];
+        loaded.seek_to_sample(0).unwrap();
+        loaded.decode_i16(&mut narrowed).unwrap();
+        assert_eq!(narrowed, [32767, -32768, 0, -1, 0, 15625]);
+
+        // 8-bit samples are unsigned
+        let mut wav = WavCodec::new(StreamInfo::new(8000, 1).with_bits_per_sample(8));
+        wav.encode_i16(&[0, i16::MAX, i16::MIN]).unwrap();
+        let mut bytes = Vec::new();
+        wav.save_to(&mut bytes).unwrap();
+        assert_eq!(&bytes[44..47], &[128, 255, 0]);
+
+        let mut float = WavFormat::new(info, SampleFormat::F32).unwrap();
+        float.bits_per_sample = 16;
+        assert!(matches!(
+            float.sample_format(),
+            Err(Error::UnsupportedFormat(_))
+        ));
+    }
+
+    #[test]
+    fn extensible_formats() {
+        // 20-bit samples in 24-bit containers, 5.1 layout
+        let channel_mask = 0x3F;
+        let info = StreamInfo::new(48000, 6)
+            .with_bits_per_sample(20)
+            .with_channel_mask(channel_mask);
+        let samples = (0..600)
+            .map(|k| (k * 1741 % 1_048_575) - 524_288)
+            .collect::<Vec<i32>>();
+        let mut wav = WavCodec::new(info);
+        wav.encode_i32(&samples).unwrap();
+        let mut bytes = Vec::new();
+        wav.save_to(&mut bytes).unwrap();
+
+        let format = WavFormat::parse(&bytes[20..60]).unwrap();
+        assert_eq!(format.format_tag, WavFormat::EXTENSIBLE);
+        assert_eq!(format.bits_per_sample, 24);
+        assert_eq!(format.block_align, 18);
+        let extension = format.extension.unwrap();
+        assert_eq!(extension.valid_bits_per_sample, 20);
+        assert_eq!(extension.channel_mask, channel_mask);
+        assert_eq!(extension.sub_format_tag(), Some(WavFormat::PCM));
+
+        let mut loaded = WavCodec::load(Cursor::new(&bytes)).unwrap();
+        assert_eq!(loaded.sample_format(), SampleFormat::I24);
+        assert_eq!(loaded.info().bits_per_sample(), 20);
+        assert_eq!(loaded.info().channel_mask(), channel_mask);
+        let mut decoded = vec![0; samples.len()];
+        loaded.decode_i32(&mut decoded).unwrap();
+        assert_eq!(decoded, samples);
+
+        // stereo with the default layout stays a plain file, more channels need the extension
+        let stereo = StreamInfo::new(44100, 2).with_channel_mask(0x3);
+        assert_eq!(
+            WavFormat::new(stereo, SampleFormat::I16).unwrap().extension,
+            None
+        );
+        assert_eq!(
+            WavFormat::new(stereo, SampleFormat::F32).unwrap().extension,
+            None
+        );
+        let quad = WavFormat::new(StreamInfo::new(44100, 4), SampleFormat::F32).unwrap();
+        assert_eq!(
+            quad.extension.unwrap().sub_format_tag(),
+            Some(WavFormat::IEEE_FLOAT)
+        );
+        assert_eq!(quad.extension.unwrap().channel_mask, 0);
+        assert_eq!(WavFormat::parse(&quad.to_bytes()).unwrap(), quad);
+        assert_eq!(quad.sample_format().unwrap(), SampleFormat::F32);
+
+        // channel counts, blocks and byte rates that don't fit their fields are refused, not truncated
+        let oversized = [
+            (8000, 70_000, SampleFormat::I16),
+            (8000, 16_384, SampleFormat::F32),
+            (96_000_000, 8, SampleFormat::F64),
+        ];
+        for (sample_rate, channels, sample_format) in oversized {
+            let info = StreamInfo::new(sample_rate, channels);
+            assert!(matches!(
+                WavFormat::new(info, sample_format),
+                Err(Error::UnsupportedFormat(_))
+            ));
+            let wav = WavCodec::new(info).with_sample_format(sample_format);
+            assert!(matches!(
+                wav.save_to(Vec::new()),
+                Err(Error::UnsupportedFormat(_))
+            ));
+            let mut writer = WavWriter::new(Cursor::new(Vec::new()))
+                .with_info(info)
+                .with_sample_format(sample_format);
+            assert!(matches!(
+                writer.encode(&[]),
+                Err(Error::UnsupportedFormat(_))
+            ));
+            assert!(writer.finalize().is_err());
+        }
+
+        // unknown sub formats and chunks cut short are rejected
+        let mut unknown = quad;
+        unknown.extension.as_mut().unwrap().sub_format[15] ^= 1;
+        assert!(matches!(
+            unknown.sample_format(),
+            Err(Error::UnsupportedFormat(_))
+        ));
+        assert!(matches!(
+            WavFormat::parse(&quad.to_bytes()[0..18]),
+            Err(Error::CorruptHeader(_))
+        ));
+    }
+
+    #[test]
+    fn streaming_reader_and_writer() {
+        let info = StreamInfo::new(22050, 2);
+        let samples = (0..4000)
+            .map(|k| (k as f32 * 0.017).sin() * 0.8)
+            .collect::<Vec<_>>();
+        let list = RiffChunk::new(*b"LIST", vec![3; 7]);
+
+        for sample_format in [SampleFormat::U8, SampleFormat::I24, SampleFormat::F32] {
+            let mut wav = WavCodec::new(info).with_sample_format(sample_format);
+            wav.chunks_mut().push(list.clone());
+            wav.encode(&samples).unwrap();
+            let mut expected = Vec::new();
+            wav.save_to(&mut expected).unwrap();
+
+            // streaming in small pieces writes the same file
+            let mut writer = WavWriter::new(Cursor::new(Vec::new()))
+                .with_info(info)
+                .with_sample_format(sample_format)
+                .with_chunk(list.clone());
+            for piece in samples.chunks(334) {
+                writer.encode(piece).unwrap();
+            }
+            assert_eq!(writer.duration_in_samples(), 2000);
+            assert!(writer.filesize_upperbound() >= expected.len() as u64 * 8);
+            let bytes = writer.finalize().unwrap().into_inner();
+            assert_eq!(bytes, expected);
+
+            // the reader decodes what WavCodec does, seeking included
+            let mut reader = WavReader::new(Cursor::new(&bytes)).unwrap();
+            let mut wav = WavCodec::load(Cursor::new(&bytes)).unwrap();
+            assert_eq!(reader.sample_format(), sample_format);
+            assert_eq!(reader.chunks(), std::slice::from_ref(&list));
+            assert_eq!(reader.duration_in_samples(), 2000);
+            let mut streamed = vec![0.0; 250];
+            let mut loaded = vec![0.0; 250];
+            for position in [0, 1500, 10] {
+                reader.seek_to_sample(position).unwrap();
+                wav.seek_to_sample(position).unwrap();
+                while let Some(n) = reader.decode(&mut streamed).unwrap() {
+                    assert_eq!(wav.decode(&mut loaded).unwrap(), Some(n));
+                    assert_eq!(streamed[0..n], loaded[0..n]);
+                }
+                assert_eq!(reader.position_in_samples(), 2000);
+            }
+        }
+
+        // integers go through untouched and a truncated file keeps its whole blocks
+        let info = info.with_bits_per_sample(24);
+        let wide = [8_388_607, -8_388_608, 1, -1, 0, 4_000_001];
+        let mut writer = WavWriter::new(Cursor::new(Vec::new())).with_info(info);
+        writer.encode_i32(&wide).unwrap();
+        let bytes = writer.finalize().unwrap().into_inner();
+        let mut reader = WavReader::new(Cursor::new(&bytes[0..bytes.len() - 4])).unwrap();
+        assert_eq!(reader.duration_in_samples(), 2);
+        let mut decoded = [0; 6];
+        assert_eq!(reader.decode_i32(&mut decoded).unwrap(), Some(4));
+        assert_eq!(decoded[0..4], wide[0..4]);
+        assert!(matches!(
+            reader.encode(&[0.0; 2]),
+            Err(Error::Unsupported(_))
+        ));
+
+        // nothing can be written before the info is set, or without any channels
+        let mut writer = WavWriter::new(Cursor::new(Vec::new()));
+        assert!(matches!(writer.encode(&[0.0; 2]), Err(Error::InfoNotSet)));
+        let mut writer =
+            WavWriter::new(Cursor::new(Vec::new())).with_info(StreamInfo::new(8000, 0));
+        assert!(matches!(
+            writer.encode(&[0.0; 2]),
+            Err(Error::UnsupportedFormat(_))
+        ));
+        let mut codec = WavCodec::new(StreamInfo::new(8000, 0));
+        assert!(matches!(
+            codec.encode(&[0.0; 2]),
+            Err(Error::UnsupportedFormat(_))
+        ));
+        assert!(matches!(
+            codec.encode_i16(&[0; 2]),
+            Err(Error::UnsupportedFormat(_))
+        ));
+        assert!(matches!(
+            codec.decode(&mut [0.0; 2]),
+            Err(Error::UnsupportedFormat(_))
+        ));
+        assert!(matches!(
+            codec.decode_i32(&mut [0; 2]),
+            Err(Error::UnsupportedFormat(_))
+        ));
+    }
 }
@@ -0,0 +1,471 @@
+use super::*;
+
+/// # Description
+/// How samples are laid out in the `data` chunk of a wav file
+/// ## Comments
+/// - integers are little endian and signed, except for `U8` where `128` is silence
+/// - floats are IEEE754 (`format_tag` 3) and aren't clamped, they can go past `[-1,1]`
+#[derive(Copy, Clone, PartialEq, Eq, Debug)]
+pub enum SampleFormat {
+    U8,
+    I16,
+    /// packed 3 byte integers
+    I24,
+    I32,
+    F32,
+    F64,
+}
+
+impl SampleFormat {
+    /// # Description
+    /// the smallest integer format that holds `bits_per_sample` bit samples
+    pub fn for_bits(bits_per_sample: u32) -> Self {
+        match bits_per_sample {
+            0..=8 => Self::U8,
+            9..=16 => Self::I16,
+            17..=24 => Self::I24,
+            _ => Self::I32,
+        }
+    }
+
+    /// # Description
+    /// the format described by the fields of a `fmt ` chunk
+    /// ## Comments
+    /// - integer samples that don't fill their container (20 bits in 24 for example) are MSB-aligned,
+    ///   so they are read as if they did
+    pub fn from_format(format_tag: u16, bits_per_sample: u16) -> Result<Self, Error> {
+        let container_bits = bits_per_sample.div_ceil(8) * 8;
+        match (format_tag, container_bits) {
+            (WavFormat::PCM, 8) => Ok(Self::U8),
+            (WavFormat::PCM, 16) => Ok(Self::I16),
+            (WavFormat::PCM, 24) => Ok(Self::I24),
+            (WavFormat::PCM, 32) => Ok(Self::I32),
+            (WavFormat::IEEE_FLOAT, 32) => Ok(Self::F32),
+            (WavFormat::IEEE_FLOAT, 64) => Ok(Self::F64),
+            (WavFormat::PCM | WavFormat::IEEE_FLOAT, _) => Err(Error::UnsupportedFormat(format!(
+                "{} bits per sample in wav format tag {}",
+                bits_per_sample, format_tag
+            ))),
+            _ => Err(Error::UnsupportedFormat(format!(
+                "wav format tag {}, only PCM and IEEE float are supported",
+                format_tag
+            ))),
+        }
+    }
+
+    pub fn format_tag(self) -> u16 {
+        if self.is_float() {
+            WavFormat::IEEE_FLOAT
+        } else {
+            WavFormat::PCM
+        }
+    }
+
+    pub fn is_float(self) -> bool {
+        matches!(self, Self::F32 | Self::F64)
+    }
+
+    /// # Description
+    /// size of a sample in bytes
+    pub fn bytes_per_sample(self) -> usize {
+        match self {
+            Self::U8 => 1,
+            Self::I16 => 2,
+            Self::I24 => 3,
+            Self::I32 | Self::F32 => 4,
+            Self::F64 => 8,
+        }
+    }
+
+    /// # Description
+    /// bit depth of the integer samples a stream in this format has, see `StreamInfo::bits_per_sample()`
+    /// ## Comments
+    /// - `F32` maps onto 24 bits (the precision of its mantissa), `F64` onto 32
+    pub fn stream_bits(self) -> u32 {
+        match self {
+            Self::U8 => 8,
+            Self::I16 => 16,
+            Self::I24 | Self::F32 => 24,
+            Self::I32 | Self::F64 => 32,
+        }
+    }
+
+    /// # Description
+    /// reads an integer format's sample as it's stored, `None` for floats
+    fn read_raw(self, bytes: &[u8]) -> Option<i32> {
+        match self {
+            Self::U8 => Some(bytes[0] as i32 - 128),
+            Self::I16 => Some(i16::from_le_bytes([bytes[0], bytes[1]]) as i32),
+            Self::I24 => Some(i32::from_le_bytes([0, bytes[0], bytes[1], bytes[2]]) >> 8),
+            Self::I32 => Some(i32::from_le_bytes([bytes[0], bytes[1], bytes[2], bytes[3]])),
+            Self::F32 | Self::F64 => None,
+        }
+    }
+
+    fn read_float(self, bytes: &[u8]) -> f64 {
+        match self {
+            Self::F32 => f32::from_le_bytes([bytes[0], bytes[1], bytes[2], bytes[3]]) as f64,
+            Self::F64 => f64::from_le_bytes(bytes[0..8].try_into().unwrap_or_default()),
+            _ => self.read_raw(bytes).map_or(0.0, |sample| {
+                sample as f64 / max_sample(self.stream_bits()) as f64
+            }),
+        }
+    }
+
+    /// # Description
+    /// writes an integer format's sample, `sample` has to fit the format
+    fn write_raw(self, sample: i32, out: &mut [u8]) {
+        match self {
+            Self::U8 => out[0] = (sample + 128) as u8,
+            Self::I16 => out.copy_from_slice(&(sample as i16).to_le_bytes()),
+            Self::I24 => out.copy_from_slice(&sample.to_le_bytes()[0..3]),
+            Self::I32 => out.copy_from_slice(&sample.to_le_bytes()),
+            Self::F32 | Self::F64 => {
+                let max = max_sample(self.stream_bits()) as f64;
+                self.write_float(sample as f64 / max, out)
+            }
+        }
+    }
+
+    fn write_float(self, sample: f64, out: &mut [u8]) {
+        match self {
+            Self::F32 => out.copy_from_slice(&(sample as f32).to_le_bytes()),
+            Self::F64 => out.copy_from_slice(&sample.to_le_bytes()),
+            _ => {
+                let max = max_sample(self.stream_bits()) as f64;
+                self.write_raw((sample.clamp(-1.0, 1.0) * max) as i32, out)
+            }
+        }
+    }
+
+    /// # Description
+    /// reads a sample as a float in `[-1,1]` (give or take, floats aren't clamped)
+    pub(super) fn decode_f32(self, bytes: &[u8]) -> f32 {
+        match self.read_raw(bytes) {
+            Some(sample) => dequantize_sample(sample, self.stream_bits()),
+            None => self.read_float(bytes) as f32,
+        }
+    }
+
+    /// # Description
+    /// reads a sample as a `bits_per_sample` bit integer
+    pub(super) fn decode_int(self, bytes: &[u8], bits_per_sample: u32) -> i32 {
+        match self.read_raw(bytes) {
+            Some(sample) => rescale_sample(sample, self.stream_bits(), bits_per_sample),
+            None => {
+                let max = max_sample(bits_per_sample) as f64;
+                (self.read_float(bytes).clamp(-1.0, 1.0) * max) as i32
+            }
+        }
+    }
+
+    pub(super) fn encode_f32(self, sample: f32, out: &mut [u8]) {
+        if self.is_float() {
+            self.write_float(sample as f64, out);
+        } else {
+            self.write_raw(quantize_sample(sample, self.stream_bits()), out);
+        }
+    }
+
+    /// # Description
+    /// writes a `bits_per_sample` bit integer sample
+    pub(super) fn encode_int(self, sample: i32, bits_per_sample: u32, out: &mut [u8]) {
+        if self.is_float() {
+            self.write_float(sample as f64 / max_sample(bits_per_sample) as f64, out);
+        } else {
+            self.write_raw(
+                rescale_sample(sample, bits_per_sample, self.stream_bits()),
+                out,
+            );
+        }
+    }
+
+    /// # Description
+    /// rewrites a sample stored as `self` in `format`
+    /// ## Comments
+    /// - integers are shifted, so going to a wider integer format and back is lossless
+    pub(super) fn transcode(self, bytes: &[u8], format: SampleFormat, out: &mut [u8]) {
+        match self.read_raw(bytes) {
+            Some(sample) if !format.is_float() => {
+                format.encode_int(sample, self.stream_bits(), out);
+            }
+            _ => format.write_float(self.read_float(bytes), out),
+        }
+    }
+}
+
+/// # Description
+/// The part of a `fmt ` chunk that only `WAVE_FORMAT_EXTENSIBLE` files have
+/// ## Comments
+/// - layout (little endian), right after the fields every `fmt ` chunk has: `cb_size: u16` (at least 22),
+///   `valid_bits_per_sample: u16`, `channel_mask: u32`, `sub_format: [u8; 16]`
+#[derive(Copy, Clone, PartialEq, Eq, Debug)]
+pub struct FormatExtension {
+    /// bits of every sample that are actually used, samples are MSB-aligned in their container
+    pub valid_bits_per_sample: u16,
+    /// see `StreamInfo::with_channel_mask(..)`
+    pub channel_mask: u32,
+    /// GUID of the actual format, see `sub_format_tag()`
+    pub sub_format: [u8; 16],
+}
+
+impl FormatExtension {
+    /// size in bytes of the extension, `cb_size` included
+    pub const LEN: usize = 24;
+
+    /// bytes 2.. of every `KSDATAFORMAT_SUBTYPE_*` GUID that stands in for an old style format tag,
+    /// bytes 0..2 are the format tag
+    const GUID_TAIL: [u8; 14] = [
+        0x00, 0x00, 0x00, 0x00, 0x10, 0x00, 0x80, 0x00, 0x00, 0xAA, 0x00, 0x38, 0x9B, 0x71,
+    ];
+
+    /// # Description
+    /// the sub format GUID of `format_tag`
+    pub fn sub_format_of(format_tag: u16) -> [u8; 16] {
+        let mut sub_format = [0; 16];
+        sub_format[0..2].copy_from_slice(&format_tag.to_le_bytes());
+        sub_format[2..16].copy_from_slice(&Self::GUID_TAIL);
+        sub_format
+    }
+
+    /// # Description
+    /// the format tag `sub_format` stands in for
+    /// ## Returns
+    /// `None` if `sub_format` isn't a `KSDATAFORMAT_SUBTYPE_*` GUID
+    pub fn sub_format_tag(&self) -> Option<u16> {
+        (self.sub_format[2..16] == Self::GUID_TAIL)
+            .then(|| u16::from_le_bytes([self.sub_format[0], self.sub_format[1]]))
+    }
+}
+
+/// # Description
+/// The contents of a `fmt ` chunk
+/// ## Comments
+/// - layout (little endian): `format_tag: u16`, `channels: u16`, `sample_rate: u32`, `bytes_per_sec: u32`,
+///   `block_align: u16`, `bits_per_sample: u16`
+/// - `WAVE_FORMAT_EXTENSIBLE` chunks go on with a `FormatExtension`, other formats can have a `cbSize`
+///   and extra bytes which are ignored
+#[derive(Copy, Clone, PartialEq, Eq, Debug)]
+pub struct WavFormat {
+    pub format_tag: u16,
+    pub channels: u16,
+    pub sample_rate: u32,
+    pub bytes_per_sec: u32,
+    /// size in bytes of one sample of every channel
+    pub block_align: u16,
+    /// size in bits of a sample's container, see `FormatExtension` for the bits that are used
+    pub bits_per_sample: u16,
+    /// only there if `format_tag` is `EXTENSIBLE`
+    pub extension: Option<FormatExtension>,
+}
+
+impl WavFormat {
+    /// `format_tag` of integer PCM
+    pub const PCM: u16 = 1;
+
+    /// `format_tag` of IEEE754 floats
+    pub const IEEE_FLOAT: u16 = 3;
+
+    /// `format_tag` of `WAVE_FORMAT_EXTENSIBLE`, the actual format is in the `FormatExtension`
+    pub const EXTENSIBLE: u16 = 0xFFFE;
+
+    /// size in bytes of the fields every `fmt ` chunk has
+    pub const LEN: usize = 16;
+
+    /// # Description
+    /// the format of a stream stored as `sample_format`
+    /// ## Comments
+    /// - the format is `EXTENSIBLE` if a plain one can't describe the stream: more than two channels,
+    ///   integers wider than 16 bits, integers that don't fill their container or a channel mask that isn't
+    ///   the default one
+    /// ## Returns
+    /// `Error::UnsupportedFormat` if the stream has no channels, a block doesn't fit the 16-bit fields or
+    /// the byte rate doesn't fit its 32-bit one
+    pub fn new(info: StreamInfo, sample_format: SampleFormat) -> Result<Self, Error> {
+        let channels = u16::try_from(info.channels)
+            .ok()
+            .filter(|&channels| channels > 0)
+            .ok_or_else(|| {
+                Error::UnsupportedFormat(format!(
+                    "wav files have 1 to {} channels, not {}",
+                    u16::MAX,
+                    info.channels
+                ))
+            })?;
+        let bytes_per_sample = sample_format.bytes_per_sample() as u16;
+        let block_align = channels.checked_mul(bytes_per_sample).ok_or_else(|| {
+            Error::UnsupportedFormat(format!(
+                "{} channels of {:?} samples are too many for a wav file",
+                channels, sample_format
+            ))
+        })?;
+        let container_bits = bytes_per_sample * 8;
+        // a stream only leaves bits unused if its format was picked to fit it, see `WavCodec::new(..)`
+        let valid_bits = if SampleFormat::for_bits(info.bits_per_sample) == sample_format {
+            info.bits_per_sample as u16
+        } else {
+            container_bits
+        };
+
+        let default_mask = match info.channels {
+            1 => 0x4,
+            2 => 0x3,
+            _ => 0,
+        };
+        let is_extensible = info.channels > 2
+            || (!sample_format.is_float() && container_bits > 16)
+            || valid_bits != container_bits
+            || (info.channel_mask != 0 && info.channel_mask != default_mask);
+        let extension = is_extensible.then(|| FormatExtension {
+            valid_bits_per_sample: valid_bits,
+            channel_mask: info.channel_mask,
+            sub_format: FormatExtension::sub_format_of(sample_format.format_tag()),
+        });
+
+        let bytes_per_sec = info
+            .sample_rate
+            .checked_mul(block_align as u32)
+            .ok_or_else(|| {
+                Error::UnsupportedFormat(format!(
+                    "{}Hz is too high a sample rate for {} channels of {:?} samples",
+                    info.sample_rate, channels, sample_format
+                ))
+            })?;
+
+        Ok(Self {
+            format_tag: if is_extensible {
+                Self::EXTENSIBLE
+            } else {
+                sample_format.format_tag()
+            },
+            channels,
+            sample_rate: info.sample_rate,
+            bytes_per_sec,
+            block_align,
+            bits_per_sample: container_bits,
+            extension,
+        })
+    }
+
+    /// # Description
+    /// how the samples of the `data` chunk are laid out
+    /// ## Returns
+    /// `Error::UnsupportedFormat` for anything other than 8/16/24/32-bit integers and 32/64-bit floats
+    pub fn sample_format(&self) -> Result<SampleFormat, Error> {
+        let format_tag = match &self.extension {
+            Some(extension) => extension.sub_format_tag().ok_or_else(|| {
+                Error::UnsupportedFormat(format!(
+                    "wav sub format {:02x?}, only PCM and IEEE float are supported",
+                    extension.sub_format
+                ))
+            })?,
+            None => self.format_tag,
+        };
+        SampleFormat::from_format(format_tag, self.bits_per_sample)
+    }
+
+    /// # Description
+    /// size in bytes of the chunk's body
+    pub fn len_in_bytes(&self) -> usize {
+        Self::LEN + self.extension.map_or(0, |_| FormatExtension::LEN)
+    }
+
+    /// # Description
+    /// parses the body of a `fmt ` chunk
+    pub fn parse(body: &[u8]) -> Result<Self, Error> {
+        if body.len() < Self::LEN {
+            return Err(Error::CorruptHeader(format!(
+                "fmt chunk is {} bytes, at least {} expected",
+                body.len(),
+                Self::LEN
+            )));
+        }
+        let u16_at = |idx: usize| u16::from_le_bytes([body[idx], body[idx + 1]]);
+        let u32_at = |idx: usize| {
+            u32::from_le_bytes([body[idx], body[idx + 1], body[idx + 2], body[idx + 3]])
+        };
+
+        let format_tag = u16_at(0);
+        let extension = if format_tag == Self::EXTENSIBLE {
+            let cb_size = if body.len() >= Self::LEN + 2 {
+                u16_at(16)
+            } else {
+                0
+            };
+            if body.len() < Self::LEN + FormatExtension::LEN
+                || (cb_size as usize) < FormatExtension::LEN - 2
+            {
+                return Err(Error::CorruptHeader(
+                    "WAVE_FORMAT_EXTENSIBLE fmt chunk is missing its extension".to_string(),
+                ));
+            }
+            let mut sub_format = [0; 16];
+            sub_format.copy_from_slice(&body[24..40]);
+            Some(FormatExtension {
+                valid_bits_per_sample: u16_at(18),
+                channel_mask: u32_at(20),
+                sub_format,
+            })
+        } else {
+            None
+        };
+
+        let format = Self {
+            format_tag,
+            channels: u16_at(2),
+            sample_rate: u32_at(4),
+            bytes_per_sec: u32_at(8),
+            block_align: u16_at(12),
+            bits_per_sample: u16_at(14),
+            extension,
+        };
+        if format.channels == 0 {
+            return Err(Error::CorruptHeader(
+                "fmt chunk has no channels".to_string(),
+            ));
+        }
+        Ok(format)
+    }
+
+    pub fn to_bytes(&self) -> Vec<u8> {
+        let mut bytes = Vec::with_capacity(self.len_in_bytes());
+        bytes.extend_from_slice(&self.format_tag.to_le_bytes());
+        bytes.extend_from_slice(&self.channels.to_le_bytes());
+        bytes.extend_from_slice(&self.sample_rate.to_le_bytes());
+        bytes.extend_from_slice(&self.bytes_per_sec.to_le_bytes());
+        bytes.extend_from_slice(&self.block_align.to_le_bytes());
+        bytes.extend_from_slice(&self.bits_per_sample.to_le_bytes());
+        if let Some(extension) = &self.extension {
+            let cb_size = FormatExtension::LEN as u16 - 2;
+            bytes.extend_from_slice(&cb_size.to_le_bytes());
+            bytes.extend_from_slice(&extension.valid_bits_per_sample.to_le_bytes());
+            bytes.extend_from_slice(&extension.channel_mask.to_le_bytes());
+            bytes.extend_from_slice(&extension.sub_format);
+        }
+        bytes
+    }
+
+    /// # Description
+    /// the stream described by the chunk
+    /// ## Comments
+    /// - integer streams are as deep as their valid bits, float streams see `SampleFormat::stream_bits()`
+    /// - the channel mask is `0` unless the format is `EXTENSIBLE`
+    pub fn info(&self) -> Result<StreamInfo, Error> {
+        let sample_format = self.sample_format()?;
+        let container_bits = sample_format.stream_bits();
+        let valid_bits = match &self.extension {
+            Some(extension) => extension.valid_bits_per_sample as u32,
+            None => self.bits_per_sample as u32,
+        };
+        let bits_per_sample =
+            if sample_format.is_float() || valid_bits == 0 || valid_bits > container_bits {
+                container_bits
+            } else {
+                valid_bits
+            };
+        let channel_mask = self.extension.map_or(0, |extension| extension.channel_mask);
+        Ok(StreamInfo::new(self.sample_rate, self.channels as u32)
+            .with_bits_per_sample(bits_per_sample)
+            .with_channel_mask(channel_mask))
+    }
+}
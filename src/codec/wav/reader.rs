@@ -0,0 +1,200 @@
+use super::*;
+
+/// # Description
+/// Decodes a wav file straight out of a `Read + Seek`, samples are read as they are asked for
+/// ## Comments
+/// - only the chunk headers (and the bodies of chunks other than `data`) are read upfront, memory use
+///   doesn't depend on the length of the recording
+/// - understands everything `WavCodec::load(..)` does, which is built on top of this
+/// - `encode(..)` returns `Error::Unsupported`, use `WavWriter` to write files
+pub struct WavReader<R> {
+    res: R,
+    info: StreamInfo,
+    sample_format: SampleFormat,
+    /// where the samples of the `data` chunk start in `res`
+    data_offset: u64,
+    /// number of samples in the `data` chunk, a truncated file only counts whole blocks
+    num_samples: u64,
+    /// index of the next sample `decode(..)` reads, `res` is always positioned right at it
+    sample_cursor: u64,
+    /// chunks other than `fmt ` and `data`
+    chunk_list: Vec<RiffChunk>,
+    /// raw bytes of the samples being decoded
+    buffer: Vec<u8>,
+}
+
+impl<R> WavReader<R>
+where
+    R: Read + Seek,
+{
+    /// # Description
+    /// walks the chunks of the file and gets ready to decode the `data` chunk
+    /// ## Comments
+    /// - `fmt ` and `data` can be anywhere in the file, every other chunk is kept (see `chunks()`)
+    ///   except `fact`
+    pub fn new(res: R) -> Result<Self, Error> {
+        let mut chunks = RiffChunks::new(res)?;
+        let mut format = None;
+        let mut data = None;
+        let mut chunk_list = Vec::new();
+
+        while let Some(header) = chunks.next().transpose()? {
+            match &header.id {
+                b"fmt " if format.is_none() => {
+                    format = Some(WavFormat::parse(&chunks.read_body(&header, u64::MAX)?)?);
+                }
+                b"data" if data.is_none() => data = Some(header),
+                b"fact" => (),
+                _ => {
+                    let body = chunks.read_body(&header, u64::MAX)?;
+                    chunk_list.push(RiffChunk::new(header.id, body));
+                }
+            }
+        }
+
+        let format =
+            format.ok_or_else(|| Error::CorruptHeader("fmt chunk not found".to_string()))?;
+        let data = data.ok_or_else(|| Error::CorruptHeader("data chunk not found".to_string()))?;
+        let sample_format = format.sample_format()?;
+        let info = format.info()?;
+
+        // a truncated file can end in the middle of a block
+        let mut res = chunks.into_inner();
+        let res_len = res.seek(SeekFrom::End(0))?;
+        let data_len = data.size.min(res_len.saturating_sub(data.offset));
+        let block_align = info.channels() as u64 * sample_format.bytes_per_sample() as u64;
+        let num_samples = (data_len / block_align) * info.channels() as u64;
+        res.seek(SeekFrom::Start(data.offset))?;
+
+        Ok(Self {
+            res,
+            info,
+            sample_format,
+            data_offset: data.offset,
+            num_samples,
+            sample_cursor: 0,
+            chunk_list,
+            buffer: Vec::new(),
+        })
+    }
+
+    pub fn info(&self) -> StreamInfo {
+        self.info
+    }
+
+    pub fn sample_format(&self) -> SampleFormat {
+        self.sample_format
+    }
+
+    /// # Description
+    /// chunks of the file that aren't `fmt ` or `data` (`LIST`, `bext`, ...), in the order they were found
+    pub fn chunks(&self) -> &[RiffChunk] {
+        &self.chunk_list
+    }
+
+    pub fn into_inner(self) -> R {
+        self.res
+    }
+
+    /// # Description
+    /// same as `WavCodec::decode_i16(..)`
+    pub fn decode_i16(&mut self, out: &mut [i16]) -> Result<Option<usize>, Error> {
+        self.decode_with(out, |format, bytes| format.decode_int(bytes, 16) as i16)
+    }
+
+    /// # Description
+    /// same as `WavCodec::decode_i32(..)`
+    pub fn decode_i32(&mut self, out: &mut [i32]) -> Result<Option<usize>, Error> {
+        let bits_per_sample = self.info.bits_per_sample();
+        self.decode_with(out, |format, bytes| {
+            format.decode_int(bytes, bits_per_sample)
+        })
+    }
+
+    /// # Description
+    /// reads whatever is left of the `data` chunk as is, this is how `WavCodec::load(..)` gets its samples
+    pub(super) fn read_remaining(&mut self) -> Result<Vec<u8>, Error> {
+        let size = self.sample_format.bytes_per_sample() as u64;
+        let remaining = (self.num_samples - self.sample_cursor) * size;
+        let mut pcm = Vec::new();
+        (&mut self.res).take(remaining).read_to_end(&mut pcm)?;
+        if (pcm.len() as u64) < remaining {
+            return Err(Error::Io(io::ErrorKind::UnexpectedEof.into()));
+        }
+        self.sample_cursor = self.num_samples;
+        Ok(pcm)
+    }
+
+    /// # Description
+    /// reads samples into `out`, `convert` maps stored samples to the output format
+    fn decode_with<T, Convert>(
+        &mut self,
+        out: &mut [T],
+        convert: Convert,
+    ) -> Result<Option<usize>, Error>
+    where
+        Convert: Fn(SampleFormat, &[u8]) -> T,
+    {
+        let num_channels = self.info.channels();
+        let size = self.sample_format.bytes_per_sample();
+
+        //makes sure we can't write partial PCM 'blocks'
+        let valid_length = (out.len() / num_channels) * num_channels;
+        let samples_left = self.num_samples - self.sample_cursor;
+        let samples_read = (valid_length as u64).min(samples_left) as usize;
+
+        self.buffer.resize(samples_read * size, 0);
+        self.res.read_exact(&mut self.buffer)?;
+        for (out, bytes) in out.iter_mut().zip(self.buffer.chunks_exact(size)) {
+            *out = convert(self.sample_format, bytes);
+        }
+        self.sample_cursor += samples_read as u64;
+
+        Ok((samples_read > 0).then_some(samples_read))
+    }
+}
+
+impl<R> Streamable for WavReader<R>
+where
+    R: Read + Seek,
+{
+    fn info(&self) -> StreamInfo {
+        self.info()
+    }
+
+    fn filesize_upperbound(&self) -> u64 {
+        (self.data_offset + self.num_samples * self.sample_format.bytes_per_sample() as u64) * 8
+    }
+
+    fn encode(&mut self, _samples: &[f32]) -> Result<usize, Error> {
+        Err(Error::Unsupported("WavReader can only decode"))
+    }
+
+    fn decode(&mut self, out: &mut [f32]) -> Result<Option<usize>, Error> {
+        self.decode_with(out, |format, bytes| format.decode_f32(bytes))
+    }
+
+    fn seek(&mut self, dt: SeekFrom) -> Result<(), Error> {
+        let position = self.position_in_samples();
+        let len = self.duration_in_samples();
+        self.seek_to_sample(resolve_seek(dt, position, len, self.info.sample_rate))
+    }
+
+    fn seek_to_sample(&mut self, sample: u64) -> Result<(), Error> {
+        let num_channels = self.info.channels().max(1) as u64;
+        self.sample_cursor = sample.min(self.duration_in_samples()) * num_channels;
+        let size = self.sample_format.bytes_per_sample() as u64;
+        self.res.seek(SeekFrom::Start(
+            self.data_offset + self.sample_cursor * size,
+        ))?;
+        Ok(())
+    }
+
+    fn position_in_samples(&self) -> u64 {
+        self.sample_cursor / self.info.channels().max(1) as u64
+    }
+
+    fn duration_in_samples(&self) -> u64 {
+        self.num_samples / self.info.channels().max(1) as u64
+    }
+}
@@ -0,0 +1,386 @@
+use super::*;
+
+/// size in bytes of the header every RIFF/WAVE file starts with, `"RIFF"`, a u32 size and `"WAVE"`
+pub const RIFF_HEADER_LEN: u64 = 12;
+
+/// chunk size (and RIFF size) of RF64/BW64 files that says "look it up in the `ds64` chunk"
+pub const DS64_SIZE: u32 = u32::MAX;
+
+/// # Description
+/// Body of the `ds64` chunk RF64/BW64 files (RIFF files over 4GiB) start with
+/// ## Comments
+/// - layout (little endian): `riff_size: u64`, `data_size: u64`, `sample_count: u64`,
+///   `table_length: u32` and then `table_length` entries of `id: [u8; 4]`, `size: u64`
+/// - the 64-bit sizes replace 32-bit ones that are set to `DS64_SIZE`
+#[derive(Clone, PartialEq, Eq, Debug, Default)]
+pub struct Ds64 {
+    pub riff_size: u64,
+    pub data_size: u64,
+    /// number of sample frames, replaces the `fact` chunk's
+    pub sample_count: u64,
+    /// sizes of chunks other than `data` that don't fit in 32 bits
+    pub table: Vec<([u8; 4], u64)>,
+}
+
+impl Ds64 {
+    /// size in bytes of a `ds64` body without any table entries
+    pub const LEN: u64 = 28;
+
+    pub fn len_in_bytes(&self) -> u64 {
+        Self::LEN + self.table.len() as u64 * 12
+    }
+
+    /// # Description
+    /// parses the body of a `ds64` chunk
+    pub fn parse(bytes: &[u8]) -> Result<Self, Error> {
+        if (bytes.len() as u64) < Self::LEN {
+            return Err(Error::CorruptHeader("ds64 chunk is too short".to_string()));
+        }
+        let u64_at = |k: usize| u64::from_le_bytes(bytes[k..k + 8].try_into().unwrap());
+        let table_length = u32::from_le_bytes(bytes[24..28].try_into().unwrap()) as usize;
+        let table = bytes[Self::LEN as usize..]
+            .chunks_exact(12)
+            .take(table_length)
+            .map(|entry| {
+                let id = [entry[0], entry[1], entry[2], entry[3]];
+                (id, u64::from_le_bytes(entry[4..12].try_into().unwrap()))
+            })
+            .collect();
+        Ok(Self {
+            riff_size: u64_at(0),
+            data_size: u64_at(8),
+            sample_count: u64_at(16),
+            table,
+        })
+    }
+
+    pub fn to_bytes(&self) -> Vec<u8> {
+        let mut bytes = Vec::with_capacity(self.len_in_bytes() as usize);
+        bytes.extend_from_slice(&self.riff_size.to_le_bytes());
+        bytes.extend_from_slice(&self.data_size.to_le_bytes());
+        bytes.extend_from_slice(&self.sample_count.to_le_bytes());
+        bytes.extend_from_slice(&(self.table.len() as u32).to_le_bytes());
+        for (id, size) in &self.table {
+            bytes.extend_from_slice(id);
+            bytes.extend_from_slice(&size.to_le_bytes());
+        }
+        bytes
+    }
+
+    /// # Description
+    /// adds a table entry for every chunk of `chunk_list` that's too big for a 32-bit size
+    pub fn with_chunks<'a, Chunks>(mut self, chunk_list: Chunks) -> Self
+    where
+        Chunks: IntoIterator<Item = &'a RiffChunk>,
+    {
+        self.table.extend(
+            chunk_list
+                .into_iter()
+                .filter(|chunk| chunk.is_oversized())
+                .map(|chunk| (chunk.id, chunk.data.len() as u64)),
+        );
+        self
+    }
+
+    /// # Description
+    /// the real size of a chunk whose header says `DS64_SIZE`
+    fn chunk_size(&self, id: &[u8; 4]) -> Option<u64> {
+        if id == b"data" {
+            return Some(self.data_size);
+        }
+        self.table
+            .iter()
+            .find(|(entry_id, _)| entry_id == id)
+            .map(|&(_, size)| size)
+    }
+}
+
+/// # Description
+/// The header in front of every chunk of a RIFF file
+/// ## Comments
+/// - layout (little endian): `id: [u8; 4]`, `size: u32`, the body follows and is padded to an even length
+#[derive(Copy, Clone, PartialEq, Eq, Debug)]
+pub struct ChunkHeader {
+    pub id: [u8; 4],
+    /// size of the body in bytes, the pad byte isn't included
+    pub size: u64,
+    /// where the body starts in the resource
+    pub offset: u64,
+}
+
+impl ChunkHeader {
+    /// size in bytes of a chunk header
+    pub const LEN: u64 = 8;
+
+    /// # Description
+    /// where the chunk following this one starts, odd sized bodies are followed by a pad byte
+    pub fn end(&self) -> u64 {
+        self.offset + self.size + (self.size & 1)
+    }
+
+    /// # Description
+    /// writes a chunk header for a body of `size` bytes
+    pub fn write_to<Resource>(id: [u8; 4], size: u32, mut res: Resource) -> Result<(), Error>
+    where
+        Resource: Write,
+    {
+        res.write_all(&id)?;
+        res.write_all(&size.to_le_bytes())?;
+        Ok(())
+    }
+}
+
+/// # Description
+/// A chunk `WavCodec` doesn't interpret (`LIST`, `bext`, `cue `, ...)
+/// ## Comments
+/// - kept around so metadata survives loading and saving a file, see `WavCodec::chunks()`
+#[derive(Clone, PartialEq, Eq, Debug)]
+pub struct RiffChunk {
+    pub id: [u8; 4],
+    pub data: Vec<u8>,
+}
+
+impl RiffChunk {
+    pub fn new(id: [u8; 4], data: Vec<u8>) -> Self {
+        Self { id, data }
+    }
+
+    /// # Description
+    /// size of the chunk in bytes once written, header and pad byte included
+    pub fn len_in_bytes(&self) -> u64 {
+        let size = self.data.len() as u64;
+        ChunkHeader::LEN + size + (size & 1)
+    }
+
+    /// # Description
+    /// `true` if the body is too big for a 32-bit size, the chunk then only fits in RF64 files
+    pub fn is_oversized(&self) -> bool {
+        self.data.len() as u64 > u32::MAX as u64
+    }
+
+    /// # Description
+    /// writes the chunk, header and pad byte included
+    /// ## Comments
+    /// - oversized chunks get `DS64_SIZE` in their header, their size goes in the `ds64` table
+    ///   (see `Ds64::with_chunks(..)`)
+    pub fn write_to<Resource>(&self, mut res: Resource) -> Result<(), Error>
+    where
+        Resource: Write,
+    {
+        let size = u32::try_from(self.data.len()).unwrap_or(DS64_SIZE);
+        ChunkHeader::write_to(self.id, size, &mut res)?;
+        res.write_all(&self.data)?;
+        if self.data.len() % 2 == 1 {
+            res.write_all(&[0])?;
+        }
+        Ok(())
+    }
+}
+
+/// # Description
+/// Walks the chunks of a RIFF/WAVE resource one header at a time
+/// ## Comments
+/// - bodies are skipped by seeking, `read_body(..)` reads the one you're interested in
+/// - the size in the RIFF header is ignored (plenty of writers get it wrong), the walk ends where the resource does
+/// - the last chunk of a truncated file reports the size it claims to have, `read_body(..)` returns what's left
+/// - RF64/BW64 files are walked the same way, their `ds64` chunk is parsed upfront (see `ds64()`) and isn't
+///   returned by the walk, chunks get their 64-bit sizes from it
+pub struct RiffChunks<Resource> {
+    res: Resource,
+    /// where the next chunk header starts
+    cursor: u64,
+    is_done: bool,
+    ds64: Option<Ds64>,
+}
+
+impl<Resource> RiffChunks<Resource>
+where
+    Resource: Read + Seek,
+{
+    /// # Description
+    /// checks the `RIFF` (or `RF64`/`BW64`) header at the current position of `res`
+    /// ## Returns
+    /// `Error::BadMagic` if `res` isn't a RIFF/WAVE resource
+    pub fn new(mut res: Resource) -> Result<Self, Error> {
+        let start = res.stream_position()?;
+        let mut header = [0u8; RIFF_HEADER_LEN as usize];
+        res.read_exact(&mut header)?;
+        let is_rf64 = match &header[0..4] {
+            b"RIFF" => false,
+            b"RF64" | b"BW64" => true,
+            _ => return Err(Error::BadMagic),
+        };
+        if header[8..12] != *b"WAVE" {
+            return Err(Error::BadMagic);
+        }
+
+        let mut chunks = Self {
+            res,
+            cursor: start + RIFF_HEADER_LEN,
+            is_done: false,
+            ds64: None,
+        };
+        if is_rf64 {
+            // the ds64 chunk has to come first
+            let ds64 = match chunks.next_header()? {
+                Some(header) if header.id == *b"ds64" => header,
+                _ => return Err(Error::CorruptHeader("ds64 chunk not found".to_string())),
+            };
+            let body = chunks.read_body(&ds64, u16::MAX as u64)?;
+            chunks.ds64 = Some(Ds64::parse(&body)?);
+        }
+        Ok(chunks)
+    }
+
+    /// # Description
+    /// the `ds64` chunk of an RF64/BW64 file, `None` for plain RIFF files
+    pub fn ds64(&self) -> Option<&Ds64> {
+        self.ds64.as_ref()
+    }
+
+    /// # Description
+    /// reads the body of `header`, at most `limit` bytes of it
+    /// ## Comments
+    /// - returns less than `header.size` bytes if the resource ends early
+    pub fn read_body(&mut self, header: &ChunkHeader, limit: u64) -> Result<Vec<u8>, Error> {
+        self.res.seek(SeekFrom::Start(header.offset))?;
+        let mut body = Vec::new();
+        (&mut self.res)
+            .take(header.size.min(limit))
+            .read_to_end(&mut body)?;
+        Ok(body)
+    }
+
+    pub fn into_inner(self) -> Resource {
+        self.res
+    }
+
+    fn next_header(&mut self) -> Result<Option<ChunkHeader>, Error> {
+        self.res.seek(SeekFrom::Start(self.cursor))?;
+
+        // running out of bytes (or a few stray ones at the end) ends the walk
+        let mut bytes = [0u8; ChunkHeader::LEN as usize];
+        let mut bytes_read = 0;
+        while bytes_read < bytes.len() {
+            match self.res.read(&mut bytes[bytes_read..])? {
+                0 => return Ok(None),
+                n => bytes_read += n,
+            }
+        }
+
+        let id = [bytes[0], bytes[1], bytes[2], bytes[3]];
+        let size = u32::from_le_bytes([bytes[4], bytes[5], bytes[6], bytes[7]]);
+        let size = match &self.ds64 {
+            Some(ds64) if size == DS64_SIZE => ds64.chunk_size(&id).unwrap_or(size as u64),
+            _ => size as u64,
+        };
+        let header = ChunkHeader {
+            id,
+            size,
+            offset: self.cursor + ChunkHeader::LEN,
+        };
+        self.cursor = header.end();
+        Ok(Some(header))
+    }
+}
+
+impl<Resource> Iterator for RiffChunks<Resource>
+where
+    Resource: Read + Seek,
+{
+    type Item = Result<ChunkHeader, Error>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        if self.is_done {
+            return None;
+        }
+        let header = self.next_header().transpose();
+        self.is_done = !matches!(header, Some(Ok(_)));
+        header
+    }
+}
+
+mod test {
+    #[allow(unused_imports)]
+    use super::*;
+    #[allow(unused_imports)]
+    use std::io::Cursor;
+
+    #[test]
+    fn chunks_are_walked_with_padding() {
+        let mut bytes = Vec::new();
+        bytes.extend_from_slice(b"RIFF\0\0\0\0WAVE");
+        RiffChunk::new(*b"odd ", vec![1, 2, 3])
+            .write_to(&mut bytes)
+            .unwrap();
+        RiffChunk::new(*b"even", vec![4, 5])
+            .write_to(&mut bytes)
+            .unwrap();
+        // claims more than is there
+        ChunkHeader::write_to(*b"data", 100, &mut bytes).unwrap();
+        bytes.extend_from_slice(&[6, 7, 8]);
+
+        let mut chunks = RiffChunks::new(Cursor::new(&bytes)).unwrap();
+        let header_list = chunks.by_ref().collect::<Result<Vec<_>, _>>().unwrap();
+        let id_list = header_list
+            .iter()
+            .map(|header| &header.id)
+            .collect::<Vec<_>>();
+        assert_eq!(id_list, [b"odd ", b"even", b"data"]);
+        assert_eq!(header_list[0].size, 3);
+        assert_eq!(header_list[1].offset, 12 + 8 + 4 + 8);
+
+        assert_eq!(
+            chunks.read_body(&header_list[0], u64::MAX).unwrap(),
+            [1, 2, 3]
+        );
+        assert_eq!(chunks.read_body(&header_list[1], 1).unwrap(), [4]);
+        assert_eq!(
+            chunks.read_body(&header_list[2], u64::MAX).unwrap(),
+            [6, 7, 8]
+        );
+
+        assert!(matches!(
+            RiffChunks::new(Cursor::new(b"RIFX\0\0\0\0WAVE")),
+            Err(Error::BadMagic)
+        ));
+    }
+
+    #[test]
+    fn ds64_sizes_are_used() {
+        let ds64 = Ds64 {
+            riff_size: 5_000_000_000,
+            data_size: 4_500_000_000,
+            sample_count: 1_125_000_000,
+            table: vec![(*b"big ", 4_400_000_000)],
+        };
+        assert_eq!(Ds64::parse(&ds64.to_bytes()).unwrap(), ds64);
+
+        let mut bytes = Vec::new();
+        bytes.extend_from_slice(b"BW64\xFF\xFF\xFF\xFFWAVE");
+        RiffChunk::new(*b"ds64", ds64.to_bytes())
+            .write_to(&mut bytes)
+            .unwrap();
+        RiffChunk::new(*b"LIST", vec![1, 2])
+            .write_to(&mut bytes)
+            .unwrap();
+        ChunkHeader::write_to(*b"data", DS64_SIZE, &mut bytes).unwrap();
+
+        let mut chunks = RiffChunks::new(Cursor::new(&bytes)).unwrap();
+        assert_eq!(chunks.ds64(), Some(&ds64));
+        let header_list = chunks.by_ref().collect::<Result<Vec<_>, _>>().unwrap();
+        assert_eq!(header_list[0].id, *b"LIST");
+        assert_eq!(header_list[0].size, 2);
+        assert_eq!(header_list[1].size, 4_500_000_000);
+
+        // RF64 files have to start with a ds64 chunk
+        let mut bytes = b"RF64\xFF\xFF\xFF\xFFWAVE".to_vec();
+        RiffChunk::new(*b"LIST", vec![1, 2])
+            .write_to(&mut bytes)
+            .unwrap();
+        assert!(matches!(
+            RiffChunks::new(Cursor::new(&bytes)),
+            Err(Error::CorruptHeader(_))
+        ));
+    }
+}
@@ -0,0 +1,353 @@
+use super::*;
+
+/// # Description
+/// Writes a wav file straight into a `Write + Seek`, samples are written out as they are encoded
+/// ## Comments
+/// - nothing but the samples of a single `encode(..)` call is held in memory
+/// - the header goes out with the first samples, sizes are left blank until `finalize()` seeks back
+///   and fills them in, so `finalize()` **has** to be called once you are done
+/// - the output is what `WavCodec::save_to(..)` would have written for the same samples
+/// - files are limited to 4GiB unless `with_rf64_support()` is used
+pub struct WavWriter<W> {
+    res: W,
+    info: Option<StreamInfo>,
+    /// picked from the stream info if it isn't set, see `WavCodec::new(..)`
+    sample_format: Option<SampleFormat>,
+    /// chunks written between the `fmt ` and `data` chunks
+    chunk_list: Vec<RiffChunk>,
+    /// where the file starts in `res`
+    riff_offset: u64,
+    /// where the body of the `fact` chunk is, float files only
+    fact_offset: Option<u64>,
+    /// where the samples of the `data` chunk start
+    data_offset: u64,
+    /// number of samples written so far
+    num_samples: u64,
+    /// `true` once the header has been written
+    is_started: bool,
+    /// raw bytes of the samples being encoded
+    buffer: Vec<u8>,
+    /// `true` if room for a `ds64` chunk gets set aside
+    has_rf64_support: bool,
+}
+
+impl<W> WavWriter<W>
+where
+    W: Write + Seek,
+{
+    /// # Description
+    /// creates a writer, the file starts wherever `res` currently is
+    pub fn new(res: W) -> Self {
+        Self {
+            res,
+            info: None,
+            sample_format: None,
+            chunk_list: Vec::new(),
+            riff_offset: 0,
+            fact_offset: None,
+            data_offset: 0,
+            num_samples: 0,
+            is_started: false,
+            buffer: Vec::new(),
+            has_rf64_support: false,
+        }
+    }
+
+    /// # Description
+    /// sets the stream info, this **MUST** be called before encoding
+    pub fn with_info(mut self, info: StreamInfo) -> Self {
+        self.info = Some(info);
+        self
+    }
+
+    /// # Description
+    /// see `WavCodec::with_sample_format(..)`
+    pub fn with_sample_format(mut self, sample_format: SampleFormat) -> Self {
+        self.sample_format = Some(sample_format);
+        self
+    }
+
+    /// # Description
+    /// adds a chunk (`LIST`, `bext`, ...) to the header, see `WavCodec::chunks_mut()`
+    /// ## Comments
+    /// - chunks have to be added before the first samples are encoded
+    pub fn with_chunk(mut self, chunk: RiffChunk) -> Self {
+        self.chunk_list.push(chunk);
+        self
+    }
+
+    /// # Description
+    /// lets the file grow past 4GiB
+    /// ## Comments
+    /// - a `JUNK` chunk the size of a `ds64` chunk is written ahead of `fmt `, if the file ends up over 4GiB
+    ///   `finalize()` turns it into a `ds64` chunk and the file into an RF64 file
+    /// - smaller files stay plain RIFF files (with an extra `JUNK` chunk)
+    pub fn with_rf64_support(mut self) -> Self {
+        self.has_rf64_support = true;
+        self
+    }
+
+    pub fn info(&self) -> StreamInfo {
+        self.info.unwrap_or_default()
+    }
+
+    /// # Description
+    /// the format samples get written in
+    pub fn sample_format(&self) -> SampleFormat {
+        self.sample_format
+            .unwrap_or_else(|| SampleFormat::for_bits(self.info().bits_per_sample()))
+    }
+
+    /// # Description
+    /// same as `WavCodec::encode_i16(..)`
+    pub fn encode_i16(&mut self, samples: &[i16]) -> Result<usize, Error> {
+        self.encode_with(samples, |format, sample, out| {
+            format.encode_int(sample as i32, 16, out)
+        })
+    }
+
+    /// # Description
+    /// same as `WavCodec::encode_i32(..)`
+    pub fn encode_i32(&mut self, samples: &[i32]) -> Result<usize, Error> {
+        let bits_per_sample = self.info().bits_per_sample();
+        self.encode_with(samples, |format, sample, out| {
+            format.encode_int(sample, bits_per_sample, out)
+        })
+    }
+
+    /// # Description
+    /// fills in the sizes the header left blank and flushes the underlying writer
+    /// ## Returns
+    /// the underlying writer, positioned at the end of the file
+    pub fn finalize(mut self) -> Result<W, Error> {
+        self.start()?;
+        let is_rf64 = self.riff_size() > u32::MAX as u64;
+        self.finalize_as(is_rf64)
+    }
+
+    fn finalize_as(mut self, is_rf64: bool) -> Result<W, Error> {
+        self.start()?;
+
+        let data_len = self.data_len();
+        if data_len % 2 == 1 {
+            self.res.write_all(&[0])?;
+        }
+        let end = self.res.stream_position()?;
+
+        self.res.seek(SeekFrom::Start(self.riff_offset))?;
+        if is_rf64 {
+            // the JUNK chunk set aside by `start()` becomes the ds64 chunk
+            let ds64 = Ds64 {
+                riff_size: self.riff_size(),
+                data_size: data_len,
+                sample_count: self.duration_in_samples(),
+                table: Vec::new(),
+            }
+            .with_chunks(&self.chunk_list);
+            self.res.write_all(b"RF64")?;
+            self.res.write_all(&DS64_SIZE.to_le_bytes())?;
+            self.res.write_all(b"WAVE")?;
+            RiffChunk::new(*b"ds64", ds64.to_bytes()).write_to(&mut self.res)?;
+        } else {
+            self.res.write_all(b"RIFF")?;
+            self.res
+                .write_all(&(self.riff_size() as u32).to_le_bytes())?;
+        }
+        if let Some(fact_offset) = self.fact_offset {
+            let frames = self.duration_in_samples().min(DS64_SIZE as u64) as u32;
+            self.res.seek(SeekFrom::Start(fact_offset))?;
+            self.res.write_all(&frames.to_le_bytes())?;
+        }
+        let data_size = if is_rf64 { DS64_SIZE } else { data_len as u32 };
+        self.res.seek(SeekFrom::Start(self.data_offset - 4))?;
+        self.res.write_all(&data_size.to_le_bytes())?;
+
+        self.res.seek(SeekFrom::Start(end))?;
+        self.res.flush()?;
+        Ok(self.res)
+    }
+
+    /// # Description
+    /// size in the RIFF header once the file is finalized
+    fn riff_size(&self) -> u64 {
+        let data_len = self.data_len();
+        self.data_offset - self.riff_offset - ChunkHeader::LEN + data_len + (data_len & 1)
+    }
+
+    /// # Description
+    /// size in bytes of the samples written so far
+    fn data_len(&self) -> u64 {
+        self.num_samples * self.sample_format().bytes_per_sample() as u64
+    }
+
+    /// # Description
+    /// writes the header, if that hasn't happened yet
+    fn start(&mut self) -> Result<(), Error> {
+        if self.is_started {
+            return Ok(());
+        }
+
+        let info = self.info.ok_or(Error::InfoNotSet)?;
+        let sample_format = self.sample_format();
+        // streams a wav file can't describe (no channels, blocks that are too large, ..) are refused upfront
+        let format = WavFormat::new(info, sample_format)?;
+        if !self.has_rf64_support && self.chunk_list.iter().any(RiffChunk::is_oversized) {
+            return Err(Error::UnsupportedFormat(
+                "chunks over 4GiB need RF64, see `with_rf64_support()`".to_string(),
+            ));
+        }
+        self.sample_format = Some(sample_format);
+        self.riff_offset = self.res.stream_position()?;
+
+        // sizes get filled in by `finalize()`
+        self.res.write_all(b"RIFF")?;
+        self.res.write_all(&0u32.to_le_bytes())?;
+        self.res.write_all(b"WAVE")?;
+        if self.has_rf64_support {
+            // room for the ds64 table too, chunks over 4GiB need an entry
+            let ds64_len = Ds64::default().with_chunks(&self.chunk_list).len_in_bytes();
+            RiffChunk::new(*b"JUNK", vec![0; ds64_len as usize]).write_to(&mut self.res)?;
+        }
+
+        ChunkHeader::write_to(*b"fmt ", format.len_in_bytes() as u32, &mut self.res)?;
+        self.res.write_all(&format.to_bytes())?;
+
+        if sample_format.is_float() {
+            ChunkHeader::write_to(*b"fact", 4, &mut self.res)?;
+            self.fact_offset = Some(self.res.stream_position()?);
+            self.res.write_all(&0u32.to_le_bytes())?;
+        }
+        for chunk in &self.chunk_list {
+            chunk.write_to(&mut self.res)?;
+        }
+
+        ChunkHeader::write_to(*b"data", 0, &mut self.res)?;
+        self.data_offset = self.res.stream_position()?;
+        self.is_started = true;
+        Ok(())
+    }
+
+    /// # Description
+    /// writes every whole block of interleaved `samples`, `encode` stores a sample in the sample format
+    fn encode_with<T, Encode>(&mut self, samples: &[T], encode: Encode) -> Result<usize, Error>
+    where
+        T: Copy,
+        Encode: Fn(SampleFormat, T, &mut [u8]),
+    {
+        self.start()?;
+
+        let num_channels = self.info().channels();
+        let valid_len = (samples.len() / num_channels) * num_channels;
+        let sample_format = self.sample_format();
+        let size = sample_format.bytes_per_sample();
+
+        let data_len = self.data_len() + (valid_len * size) as u64;
+        let riff_size = self.data_offset - self.riff_offset - ChunkHeader::LEN + data_len + 1;
+        if !self.has_rf64_support && riff_size > u32::MAX as u64 {
+            return Err(Error::UnsupportedFormat(
+                "wav files can't be larger than 4GiB without RF64, see `with_rf64_support()`"
+                    .to_string(),
+            ));
+        }
+
+        self.buffer.resize(valid_len * size, 0);
+        for (&sample, out) in samples.iter().zip(self.buffer.chunks_exact_mut(size)) {
+            encode(sample_format, sample, out);
+        }
+        self.res.write_all(&self.buffer)?;
+        self.num_samples += valid_len as u64;
+        Ok(valid_len)
+    }
+}
+
+impl<W> Streamable for WavWriter<W>
+where
+    W: Write + Seek,
+{
+    fn info(&self) -> StreamInfo {
+        self.info()
+    }
+
+    fn filesize_upperbound(&self) -> u64 {
+        (self.data_offset - self.riff_offset + self.data_len() + 1) * 8
+    }
+
+    fn encode(&mut self, samples: &[f32]) -> Result<usize, Error> {
+        self.encode_with(samples, |format, sample, out| {
+            format.encode_f32(sample, out)
+        })
+    }
+
+    fn decode(&mut self, _out: &mut [f32]) -> Result<Option<usize>, Error> {
+        Err(Error::Unsupported("WavWriter can only encode"))
+    }
+
+    fn seek(&mut self, _dt: SeekFrom) -> Result<(), Error> {
+        Err(Error::Unsupported(
+            "WavWriter writes the file front to back",
+        ))
+    }
+
+    fn seek_to_sample(&mut self, _sample: u64) -> Result<(), Error> {
+        Err(Error::Unsupported(
+            "WavWriter writes the file front to back",
+        ))
+    }
+
+    /// everything gets appended, so this is always the end of the stream
+    fn position_in_samples(&self) -> u64 {
+        self.duration_in_samples()
+    }
+
+    fn duration_in_samples(&self) -> u64 {
+        self.num_samples / self.info().channels().max(1) as u64
+    }
+}
+
+mod test {
+    #[allow(unused_imports)]
+    use super::*;
+    #[allow(unused_imports)]
+    use std::io::Cursor;
+
+    #[test]
+    fn junk_chunk_becomes_ds64() {
+        let info = StreamInfo::new(8000, 2);
+        let samples = (0..600)
+            .map(|k| (k as f32 * 0.05).cos() * 0.5)
+            .collect::<Vec<_>>();
+        let mut wav = WavCodec::new(info).with_sample_format(SampleFormat::F32);
+        wav.encode(&samples).unwrap();
+
+        let encode = || {
+            let mut writer = WavWriter::new(Cursor::new(Vec::new()))
+                .with_info(info)
+                .with_sample_format(SampleFormat::F32)
+                .with_rf64_support();
+            writer.encode(&samples).unwrap();
+            writer
+        };
+
+        // as if the file had grown past 4GiB
+        let rf64 = encode().finalize_as(true).unwrap().into_inner();
+        let mut expected = Vec::new();
+        wav.save_as(&mut expected, true).unwrap();
+        assert_eq!(rf64, expected);
+        assert_eq!(&rf64[0..4], b"RF64");
+
+        let mut loaded = WavCodec::load(Cursor::new(&rf64)).unwrap();
+        assert_eq!(loaded.chunks(), &[]);
+        let mut decoded = vec![0.0; samples.len()];
+        assert_eq!(loaded.decode(&mut decoded).unwrap(), Some(samples.len()));
+        assert_eq!(decoded, samples);
+
+        // small files stay RIFF, the space set aside is just a JUNK chunk
+        let riff = encode().finalize().unwrap().into_inner();
+        assert_eq!(riff.len(), rf64.len());
+        assert_eq!(&riff[0..4], b"RIFF");
+        let mut loaded = WavReader::new(Cursor::new(&riff)).unwrap();
+        assert_eq!(loaded.chunks()[0].id, *b"JUNK");
+        assert_eq!(loaded.decode(&mut decoded).unwrap(), Some(samples.len()));
+        assert_eq!(decoded, samples);
+    }
+}
@@ -15,10 +15,7 @@ mod stack;
 
 pub use bitstream::*;
 pub use bitvec::*;
-pub use linked_list::*;
-pub use lru_cache::*;
 pub use nibble_list::*;
-pub use queue::*;
 pub use stack::*;
 
 type InternalPtr = u32;
@@ -115,7 +112,7 @@ where
     where
         D: Deserializer<'de>,
     {
-        const FIELDS: &'static [&'static str] = &["data", "children"];
+        const FIELDS: &[&str] = &["data", "children"];
 
         enum Field {
             Data,
@@ -221,6 +218,6 @@ where
             }
         }
 
-        deserializer.deserialize_struct("Node", FIELDS, NodeVisitor::<T, N>(PhantomData::default()))
+        deserializer.deserialize_struct("Node", FIELDS, NodeVisitor::<T, N>(PhantomData))
     }
 }
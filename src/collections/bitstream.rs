@@ -138,34 +138,12 @@ impl BitStream {
         self.offset_bit_cursor(bits_to_be_written as i128);
     }
 
+    /// # Description
+    /// reads up to 128 bits without moving the cursor
+    /// ## Comments
+    /// - bits past the end of the stream read as zero, the stream never grows while reading
     pub fn peek_bits(&mut self, num_bits: usize) -> u128 {
-        let mut bits = 0;
-        let bits_to_be_read = num_bits;
-
-        let mask = 1u128
-            .checked_shl(bits_to_be_read as u32)
-            .map(|result| result - 1)
-            .unwrap_or(!0);
-
-        let cur_chunk_idx = self.chunk_index();
-        let nxt_chunk_idx = cur_chunk_idx + 1;
-        let chunk_bit_idx = self.chunk_bit_index();
-
-        self.allocate_if_needed(cur_chunk_idx);
-        self.allocate_if_needed(nxt_chunk_idx);
-
-        // return 0 if oob
-        if cur_chunk_idx >= self.binary.len() {
-            return 0;
-        }
-
-        bits |= self.binary[cur_chunk_idx] >> chunk_bit_idx;
-        let remaining_bits = 128 - chunk_bit_idx;
-        if remaining_bits < bits_to_be_read {
-            bits |= self.binary[nxt_chunk_idx] << remaining_bits
-        }
-
-        bits & mask
+        self.reader().peek_bits(num_bits)
     }
 
     /// # Description
@@ -187,22 +165,13 @@ impl BitStream {
     /// # Parameters
     /// - `val` - should either be `0` or `1`  
     pub fn read_bit(&mut self) -> u128 {
-        let chunk_idx = self.chunk_index();
-        let bit_idx = self.chunk_bit_index();
-        if chunk_idx >= self.binary.len() {
-            return 0;
-        }
-        self.offset_bit_cursor(1);
-        let extracted_bit = self.binary[chunk_idx] >> bit_idx;
-        extracted_bit & 1
+        self.read_with(|reader| reader.read_bit())
     }
 
     /// # Description
     /// read `bit_count` bits into the stream where (`bit_count` <= 128)
     pub fn read_bits(&mut self, bit_count: usize) -> u128 {
-        let peeked_val = self.peek_bits(bit_count);
-        self.offset_bit_cursor(bit_count as i128);
-        peeked_val
+        self.read_with(|reader| reader.read_bits(bit_count))
     }
 
     pub fn peek<T>(&mut self) -> u128
@@ -232,6 +201,40 @@ impl BitStream {
         peeked_val
     }
 
+    /// # Description
+    /// writes every bit of `other` (up to its cursor) at the cursor
+    pub fn append(&mut self, other: &BitStream) {
+        let mut reader = BitReader::new(&other.binary, 0);
+        let mut bits_remaining = other.len();
+        while bits_remaining > 0 {
+            let num_bits = bits_remaining.min(CHUNK_SIZE_IN_BITS);
+            self.write_bits(reader.read_bits(num_bits), num_bits);
+            bits_remaining -= num_bits;
+        }
+    }
+
+    /// # Description
+    /// the raw 128-bit chunks of the stream
+    pub fn chunks(&self) -> &[u128] {
+        &self.binary
+    }
+
+    /// # Description
+    /// appends a raw 128-bit chunk to the end of the stream
+    /// ## Comments
+    /// - chunks are laid out exactly like they are serialized, see `AdhocReader`
+    pub fn push_chunk(&mut self, chunk: u128) {
+        self.binary.push(chunk);
+    }
+
+    /// # Description
+    /// forgets the first `count` chunks of the stream, the cursor moves back along with the data
+    pub fn discard_chunks(&mut self, count: usize) {
+        let count = count.min(self.binary.len());
+        self.binary.drain(0..count);
+        self.bit_cursor = self.bit_cursor.saturating_sub(count as u128 * 128);
+    }
+
     fn allocate_if_needed(&mut self, chunk_idx: usize) {
         if chunk_idx >= self.binary.len() {
             self.binary.push(0);
@@ -274,20 +277,25 @@ impl BitStream {
         }
     }
     pub fn write_compressed_divisor(&mut self, divisor: i16, entropy: i16) {
-        self.write_compressed((divisor - 1).count_ones() as i16, entropy)
+        self.write_compressed((divisor - 1).count_ones() as i16, entropy as i64)
     }
     pub fn read_compressed_divisor(&mut self, divisor: i16) -> i16 {
-        self.read_compressed((divisor - 1).count_ones() as i16)
+        self.read_compressed((divisor - 1).count_ones() as i16) as i16
     }
 
-    pub fn write_compressed(&mut self, exponent: i16, entropy: i16) {
-        let sign_bit = (entropy >> 15) & 1;
-        let mut quotient = entropy.abs() >> exponent;
-        let remainder = entropy.abs() & ((1 << exponent) - 1);
+    /// # Description
+    /// writes `entropy` as a sign bit followed by a rice code with divisor `2^exponent`
+    /// ## Comments
+    /// - `exponent` has to be less than `64`
+    pub fn write_compressed(&mut self, exponent: i16, entropy: i64) {
+        let sign_bit = (entropy < 0) as u8;
+        let magnitude = entropy.unsigned_abs();
+        let mut quotient = magnitude >> exponent;
+        let remainder = magnitude & ((1 << exponent) - 1);
         let remainder_size_in_bits = exponent as usize;
 
         //write sign bit
-        self.write_bit(sign_bit as u8);
+        self.write_bit(sign_bit);
 
         //write unary quotient
         // while quotient > 0 && quotient % 8 != 0 {
@@ -314,23 +322,127 @@ impl BitStream {
         //zero bit denotes end of unary value
         self.write_bit(0);
         //write remainder
-        self.write_bits(remainder as u32, remainder_size_in_bits)
+        self.write_bits(remainder, remainder_size_in_bits)
     }
 
-    pub fn read_compressed(&mut self, exponent: i16) -> i16 {
-        //write sign bit
-        let sign_bit = self.read_bit() as i16;
+    pub fn read_compressed(&mut self, exponent: i16) -> i64 {
+        self.read_with(|reader| reader.read_compressed(exponent))
+    }
+
+    /// # Description
+    /// a read-only view of the stream starting at the cursor
+    /// ## Comments
+    /// - any number of readers can share the stream, see `AdhocCodec::decode_range(..)`
+    pub fn reader(&self) -> BitReader<'_> {
+        BitReader::new(&self.binary, self.bit_cursor)
+    }
+
+    /// # Description
+    /// reads through a `BitReader` and moves the cursor past whatever was read
+    fn read_with<T>(&mut self, read: impl FnOnce(&mut BitReader) -> T) -> T {
+        let mut reader = self.reader();
+        let val = read(&mut reader);
+        let bits_read = reader.bit_cursor() - self.bit_cursor;
+        self.offset_bit_cursor(bits_read as i128);
+        val
+    }
+
+    fn chunk_index(&self) -> usize {
+        (self.bit_cursor / 128) as usize
+    }
+    fn chunk_bit_index(&self) -> usize {
+        (self.bit_cursor % 128) as usize
+    }
+    pub fn offset_bit_cursor(&mut self, offset: i128) {
+        self.bit_cursor = (self.bit_cursor as i128 + offset) as u128;
+        self.capacity = self.bit_cursor.max(self.capacity);
+    }
+
+    pub fn set_bit_cursor(&mut self, idx: u128) {
+        self.bit_cursor = idx;
+    }
+
+    pub fn bit_cursor(&self) -> u128 {
+        self.bit_cursor
+    }
+}
+
+/// # Description
+/// Reads a `BitStream` without borrowing it mutably, the cursor lives in the reader
+/// ## Comments
+/// - just like `BitStream` bits past the end read as zero
+#[derive(Copy, Clone)]
+pub struct BitReader<'a> {
+    binary: &'a [u128],
+    bit_cursor: u128,
+}
+
+impl<'a> BitReader<'a> {
+    pub fn new(binary: &'a [u128], bit_cursor: u128) -> Self {
+        Self { binary, bit_cursor }
+    }
+
+    /// # Description
+    /// reads up to 128 bits without moving the cursor
+    /// ## Comments
+    /// - bits past the end of the stream read as zero, the stream never grows while reading
+    pub fn peek_bits(&self, num_bits: usize) -> u128 {
+        let mut bits = 0;
+        let bits_to_be_read = num_bits;
+
+        let mask = 1u128
+            .checked_shl(bits_to_be_read as u32)
+            .map(|result| result - 1)
+            .unwrap_or(!0);
+
+        let cur_chunk_idx = self.chunk_index();
+        let nxt_chunk_idx = cur_chunk_idx + 1;
+        let chunk_bit_idx = self.chunk_bit_index();
+
+        // return 0 if oob
+        if cur_chunk_idx >= self.binary.len() {
+            return 0;
+        }
+
+        bits |= self.binary[cur_chunk_idx] >> chunk_bit_idx;
+        let remaining_bits = 128 - chunk_bit_idx;
+        if remaining_bits < bits_to_be_read && nxt_chunk_idx < self.binary.len() {
+            bits |= self.binary[nxt_chunk_idx] << remaining_bits
+        }
+
+        bits & mask
+    }
+
+    /// # Description
+    /// Reads a single bit
+    pub fn read_bit(&mut self) -> u128 {
+        let bit = self.peek_bits(1);
+        self.offset_bit_cursor(1);
+        bit
+    }
+
+    /// # Description
+    /// read `bit_count` bits where (`bit_count` <= 128)
+    pub fn read_bits(&mut self, bit_count: usize) -> u128 {
+        let peeked_val = self.peek_bits(bit_count);
+        self.offset_bit_cursor(bit_count as i128);
+        peeked_val
+    }
+
+    pub fn read_compressed(&mut self, exponent: i16) -> i64 {
+        //read sign bit
+        let sign_bit = self.read_bit() as i128;
         let remainder_size_in_bits = exponent as u32;
-        let divisor = 1 << exponent;
+        let divisor = 1i128 << exponent;
 
         //read zero(expected)
-        let mut quotient = 0;
+        let mut quotient = 0i128;
 
         #[allow(unused_assignments)]
         let mut bit_chunk = 0;
 
         while {
-            bit_chunk = self.peek::<u128>();
+            bit_chunk = self.peek_bits(128);
             bit_chunk.count_zeros() == 0
         } {
             self.offset_bit_cursor(128);
@@ -345,12 +457,15 @@ impl BitStream {
             bit_chunk >>= 1;
         }
         self.offset_bit_cursor(zero_bit_pos + 1);
-        quotient += zero_bit_pos.max(0) as i16;
+        quotient += zero_bit_pos;
 
         // read
-        let remainder = self.read_bits(remainder_size_in_bits as usize) as i16;
-        let unsigned_val = divisor * quotient + remainder;
-        unsigned_val * (-sign_bit) + unsigned_val * (1 - sign_bit)
+        let remainder = self.read_bits(remainder_size_in_bits as usize) as i128;
+        let unsigned_val = divisor
+            .saturating_mul(quotient)
+            .saturating_add(remainder)
+            .min(i64::MAX as i128);
+        (unsigned_val * (-sign_bit) + unsigned_val * (1 - sign_bit)) as i64
     }
 
     fn chunk_index(&self) -> usize {
@@ -361,7 +476,6 @@ impl BitStream {
     }
     pub fn offset_bit_cursor(&mut self, offset: i128) {
         self.bit_cursor = (self.bit_cursor as i128 + offset) as u128;
-        self.capacity = self.bit_cursor.max(self.capacity);
     }
 
     pub fn set_bit_cursor(&mut self, idx: u128) {
@@ -371,6 +485,12 @@ impl BitStream {
     pub fn bit_cursor(&self) -> u128 {
         self.bit_cursor
     }
+
+    /// # Description
+    /// bits the reader can see, anything past this reads as zero
+    pub fn capacity_upperbound(&self) -> usize {
+        self.binary.len() * 128
+    }
 }
 
 mod tests {
@@ -379,11 +499,30 @@ mod tests {
     #[allow(unused_imports)]
     use super::{CAPPED_MAX, CAPPED_MIN};
 
+    #[test]
+    fn append_keeps_every_bit() {
+        let mut segment = BitStream::new();
+        for k in 0..300 {
+            segment.write_compressed(3, k * 7 - 1000);
+        }
+
+        let mut bit_stream = BitStream::new();
+        bit_stream.write_bits(0b101u8, 3);
+        bit_stream.append(&segment);
+        assert_eq!(bit_stream.len(), segment.len() + 3);
+
+        bit_stream.seek_start();
+        assert_eq!(bit_stream.read_bits(3), 0b101);
+        for k in 0..300 {
+            assert_eq!(bit_stream.read_compressed(3), k * 7 - 1000);
+        }
+    }
+
     #[test]
     fn compressed_capped() {
         let mut bit_stream = BitStream::new();
         for k in CAPPED_MIN + 1..CAPPED_MAX - 1 {
-            bit_stream.write_compressed_capped::<2>(k as i16);
+            bit_stream.write_compressed_capped::<2>(k);
             bit_stream.seek_start();
             let result = bit_stream.read_compressed_capped(2);
             bit_stream.seek_start();
@@ -416,6 +555,43 @@ mod tests {
         }
     }
 
+    #[test]
+    fn compressed_test_wide() {
+        let mut bit_stream = BitStream::new();
+        let values = [
+            0i64,
+            -1,
+            (1 << 23) - 1,
+            -(1 << 23),
+            i32::MAX as i64,
+            i32::MIN as i64,
+            (1 << 32) - 1,
+            -(1 << 32),
+        ];
+        for exponent in [1, 15, 23, 31] {
+            for &k in values
+                .iter()
+                .filter(|k| (k.unsigned_abs() >> exponent) < 4096)
+            {
+                bit_stream.write_compressed(exponent, k);
+            }
+        }
+        bit_stream.seek_start();
+        for exponent in [1, 15, 23, 31] {
+            for &k in values
+                .iter()
+                .filter(|k| (k.unsigned_abs() >> exponent) < 4096)
+            {
+                assert_eq!(
+                    k,
+                    bit_stream.read_compressed(exponent),
+                    "exponent == {}",
+                    exponent
+                );
+            }
+        }
+    }
+
     #[test]
     fn sanity() {
         let mut bit_stream = BitStream::new();
@@ -443,7 +619,7 @@ mod tests {
     }
 
     #[test]
-    #[cfg(feature = "desktop")]
+    #[cfg(feature = "cli")]
     fn shotgun_aligned() {
         let mut bit_stream = BitStream::new();
 
@@ -478,7 +654,7 @@ mod tests {
     }
 
     #[test]
-    #[cfg(feature = "desktop")]
+    #[cfg(feature = "cli")]
     fn shotgun_unaligned() {
         let mut bit_stream = BitStream::new();
 
@@ -21,6 +21,12 @@ impl BitVec {
         self.bit_cursor = 0;
     }
 
+    /// # Description
+    /// shortens the vector to `len` bits, does nothing if `len` is larger than the current length
+    pub fn truncate(&mut self, len: usize) {
+        self.bit_cursor = self.bit_cursor.min(len as u128);
+    }
+
     /// # Description
     /// return number of bits written to vector
     pub fn len(&self) -> usize {
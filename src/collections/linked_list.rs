@@ -62,7 +62,7 @@ impl<T> LinkedList<T> {
 
     pub fn remove_at(&mut self, node: Ptr) -> Option<T> {
         if self.len() == 0 {
-            return None;
+            None
         } else if self.len() == 1 {
             let r = self.rear;
             let f = self.front;
@@ -155,18 +155,18 @@ impl<T> LinkedList<T> {
         self.memory.get_mut(node.as_usize())
     }
 
-    pub fn iter<'a>(&'a self) -> impl Iterator<Item = &'a T> {
+    pub fn iter(&self) -> impl Iterator<Item = &T> {
         LinkedListIterator::new(self).map(move |node| self[node].data.as_ref().unwrap())
     }
     
     pub fn iter_mut(&mut self) -> impl Iterator<Item = &mut T> {
-        let self_ref: &mut Self = unsafe { std::mem::transmute(self as *mut Self) };
+        let self_ref: &mut Self = unsafe { &mut *(self as *mut Self) };
         LinkedListIterator::new(self).map(move |node| {
             let node = unsafe {
                 &mut *self_ref
                     .memory
                     .as_mut_ptr()
-                    .offset(node.as_usize() as isize)
+                    .add(node.as_usize())
             };
             node.data.as_mut().unwrap()
         })
@@ -34,7 +34,7 @@ where
     pub fn lookup(&self,key:&K)->Option<&V>{
         let table = & self.table;
         let list = & self.list;
-        table.get(&key).and_then(move |&node| {
+        table.get(key).and_then(move |&node| {
             list[node].data.as_ref().map(|(_, v)| v)
         })
     }
@@ -43,7 +43,7 @@ where
     pub fn get(&mut self, key: &K) -> Option<&V> {
         let table = &mut self.table;
         let list = &mut self.list;
-        table.get_mut(&key).and_then(move |node_ref| {
+        table.get_mut(key).and_then(move |node_ref| {
             Self::raise_key(node_ref, list, None);
             list[list.front()].data.as_ref().map(|(_, v)| v)
         })
@@ -52,7 +52,7 @@ where
         let table = &mut self.table;
         let list = &mut self.list;
         table
-            .get_mut(&key)
+            .get_mut(key)
             .and_then(move |&mut node| list.remove_at(node))
             .map(|(k, v)| {
                 table.remove(&k);
@@ -63,7 +63,7 @@ where
     pub fn get_mut(&mut self, key: &K) -> Option<&mut V> {
         let table = &mut self.table;
         let list = &mut self.list;
-        table.get_mut(&key).and_then(move |node_ref| {
+        table.get_mut(key).and_then(move |node_ref| {
             Self::raise_key(node_ref, list, None);
             let new_front = list.front();
             list[new_front].data.as_mut().map(|(_, v)| v)
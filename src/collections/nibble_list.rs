@@ -35,7 +35,9 @@ impl NibbleList {
         if block_idx >= self.data.len() {
             self.data.push(0);
         }
-        self.data[block_idx] |= val << 4 * (nibble_idx);
+        //clear stale nibble (list may have been truncated)
+        self.data[block_idx] &= !(0x0f << (4 * nibble_idx));
+        self.data[block_idx] |= (val & 0x0f) << (4 * nibble_idx);
         self.nibble_count += 1;
     }
 
@@ -45,7 +47,7 @@ impl NibbleList {
         if block_idx >= self.data.len() {
             return 0;
         }
-        (self.data[block_idx] >> 4 * (nibble_idx)) & 0x0f
+        (self.data[block_idx] >> (4 * nibble_idx)) & 0x0f
     }
 
     pub fn set(&mut self, idx: usize, val: u8) {
@@ -55,13 +57,19 @@ impl NibbleList {
             return;
         }
         self.data[block_idx] &= (0x0f * nibble_idx) | (0xf0 * (1 - nibble_idx));
-        self.data[block_idx] |= val << 4 * (nibble_idx);
+        self.data[block_idx] |= val << (4 * nibble_idx);
     }
 
     pub fn clear(&mut self) {
         self.nibble_count = 0;
     }
 
+    /// # Description
+    /// shortens the list to `len` nibbles, does nothing if `len` is larger than the current length
+    pub fn truncate(&mut self, len: usize) {
+        self.nibble_count = self.nibble_count.min(len as u32);
+    }
+
     pub fn iter<'a>(&'a self) -> impl Iterator<Item = u8> + 'a {
         (0..self.len()).map(move |idx| self.get(idx))
     }
@@ -76,7 +84,7 @@ mod tests {
     fn sanity() {
         let mut list = NibbleList::new();
         let input = [1u8, 2, 3, 4, 5, 6, 7];
-        input.iter().for_each(|&b| list.push(b as u8));
+        input.iter().for_each(|&b| list.push(b));
         assert_eq!(&input, list.iter().collect::<Vec<_>>().as_slice());
         assert_eq!(input.len(), list.len());
 
@@ -97,5 +105,13 @@ mod tests {
             vec![10, 2, 3, 4, 5, 1, 10],
             list.iter().collect::<Vec<_>>().as_slice()
         );
+
+        list.truncate(3);
+        list.push(15);
+        list.push(15);
+        assert_eq!(
+            vec![10, 2, 3, 15, 15],
+            list.iter().collect::<Vec<_>>().as_slice()
+        );
     }
 }
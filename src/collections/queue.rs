@@ -46,7 +46,7 @@ where
     }
 
     pub fn push_front(&mut self, item: T) {
-        if self.is_full() == false {
+        if !self.is_full() {
             self.front = (self.front + self.capacity - 1) & (self.capacity - 1);
             let front = self.front;
             self[front] = item;
@@ -55,7 +55,7 @@ where
     }
 
     pub fn pop_front(&mut self) -> Option<&T> {
-        if self.is_empty() == false {
+        if !self.is_empty() {
             let old_front = self.front;
             self.front = (self.front + 1) & (self.capacity - 1);
             self.len -= 1;
@@ -67,7 +67,7 @@ where
     }
 
     pub fn push_rear(&mut self, item: T) {
-        if self.is_full() == false {
+        if !self.is_full() {
             let rear = self.rear;
             self[rear] = item;
             self.rear = (self.rear + 1) & (self.capacity - 1);
@@ -76,7 +76,7 @@ where
     }
 
     pub fn pop_rear(&mut self) -> Option<&T> {
-        if self.is_empty() == false {
+        if !self.is_empty() {
             let new_rear = (self.rear + self.capacity - 1) & (self.capacity - 1);
             self.rear = new_rear;
             self.len -= 1;
@@ -86,7 +86,7 @@ where
             None
         }
     }
-    pub fn iter<'a>(&'a self) -> impl Iterator<Item = &'a T> + '_
+    pub fn iter<'a>(&'a self) -> impl Iterator<Item = &'a T> + 'a
     where
         T: 'a,
     {
@@ -138,36 +138,36 @@ mod tests {
 
         assert_eq!(
             vec![0, 1, 2, 3],
-            queue.iter().map(|&a| a).collect::<Vec<_>>()
+            queue.iter().copied().collect::<Vec<_>>()
         );
         assert_eq!(4, queue.len());
 
-        let item = queue.pop_front().map(|&a| a);
+        let item = queue.pop_front().copied();
         assert_eq!(Some(0), item);
-        assert_eq!(vec![1, 2, 3], queue.iter().map(|&a| a).collect::<Vec<_>>());
+        assert_eq!(vec![1, 2, 3], queue.iter().copied().collect::<Vec<_>>());
 
-        let item = queue.pop_front().map(|&a| a);
+        let item = queue.pop_front().copied();
         assert_eq!(Some(1), item);
-        assert_eq!(vec![2, 3], queue.iter().map(|&a| a).collect::<Vec<_>>());
+        assert_eq!(vec![2, 3], queue.iter().copied().collect::<Vec<_>>());
 
-        let item = queue.pop_front().map(|&a| a);
+        let item = queue.pop_front().copied();
         assert_eq!(Some(2), item);
-        assert_eq!(vec![3], queue.iter().map(|&a| a).collect::<Vec<_>>());
+        assert_eq!(vec![3], queue.iter().copied().collect::<Vec<_>>());
         assert_eq!(1, queue.len());
 
-        let item = queue.pop_rear().map(|&a| a);
+        let item = queue.pop_rear().copied();
         assert_eq!(Some(3), item);
         assert_eq!(
             Vec::<i32>::new(),
-            queue.iter().map(|&a| a).collect::<Vec<_>>()
+            queue.iter().copied().collect::<Vec<_>>()
         );
         assert_eq!(0, queue.len());
 
-        let item = queue.pop_rear().map(|&a| a);
+        let item = queue.pop_rear().copied();
         assert_eq!(None, item);
         assert_eq!(
             Vec::<i32>::new(),
-            queue.iter().map(|&a| a).collect::<Vec<_>>()
+            queue.iter().copied().collect::<Vec<_>>()
         );
         assert_eq!(0, queue.len());
     }
@@ -43,8 +43,7 @@ where
         let len = self.len() as u32;
         self.cursor = ((self.cursor + 4) - 1) & 3;
         self.len = (len as i32 - 1).max(0) as u32;
-        let item = self[self.cursor];
-        item
+        self[self.cursor]
     }
 
     /// fetches previously written element
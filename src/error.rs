@@ -0,0 +1,64 @@
+use std::{fmt, io};
+
+/// # Description
+/// Everything that can go wrong while reading, writing, encoding or decoding a stream
+#[derive(Debug)]
+#[non_exhaustive]
+pub enum Error {
+    /// the underlying reader/writer failed, this includes data ending early
+    Io(io::Error),
+    /// the data doesn't start with the bytes the format expects
+    BadMagic,
+    /// the stream is well formed but uses a version, feature or sample format this crate can't handle
+    UnsupportedFormat(String),
+    /// a file/stream header is malformed
+    CorruptHeader(String),
+    /// the encoded data of frame `frame` (index into the frame headers) is inconsistent
+    CorruptFrame { frame: usize },
+    /// `StreamInfo` has to be set before encoding/decoding
+    InfoNotSet,
+    /// the operation isn't supported by the codec
+    Unsupported(&'static str),
+    /// deserializing would have read/allocated more than the configured limit
+    LimitExceeded,
+}
+
+impl fmt::Display for Error {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Error::Io(err) => write!(f, "i/o error: {}", err),
+            Error::BadMagic => write!(f, "unrecognized magic bytes"),
+            Error::UnsupportedFormat(what) => write!(f, "unsupported format: {}", what),
+            Error::CorruptHeader(what) => write!(f, "corrupt header: {}", what),
+            Error::CorruptFrame { frame } => write!(f, "frame {} is corrupt", frame),
+            Error::InfoNotSet => write!(f, "stream info not set, call set_info/with_info first"),
+            Error::Unsupported(what) => write!(f, "unsupported operation: {}", what),
+            Error::LimitExceeded => write!(f, "size limit exceeded while deserializing"),
+        }
+    }
+}
+
+impl std::error::Error for Error {
+    fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
+        match self {
+            Error::Io(err) => Some(err),
+            _ => None,
+        }
+    }
+}
+
+impl From<io::Error> for Error {
+    fn from(err: io::Error) -> Self {
+        Error::Io(err)
+    }
+}
+
+impl From<bincode::Error> for Error {
+    fn from(err: bincode::Error) -> Self {
+        match *err {
+            bincode::ErrorKind::Io(err) => Error::Io(err),
+            bincode::ErrorKind::SizeLimit => Error::LimitExceeded,
+            other => Error::CorruptHeader(other.to_string()),
+        }
+    }
+}
@@ -6,40 +6,38 @@
 //! use adhoc_audio::{codec::Streamable, AdhocCodec, WavCodec};
 //! use std::fs::File;
 //!
-//! fn main() {
-//!     println!("compressing file example..");
+//! println!("compressing file example..");
 //! 
-//!     //set up a buffer for reading/writing samples
-//!     let mut samples = [0.0; 1024];
+//! //set up a buffer for reading/writing samples
+//! let mut samples = [0.0; 1024];
 //! 
-//!     //open wav file
-//!     let mut wav_reader = WavCodec::load(
-//!         File::open("./resources/taunt.wav").unwrap()
-//!     ).unwrap();
+//! //open wav file
+//! let mut wav_reader = WavCodec::load(
+//!     File::open("./resources/taunt.wav").unwrap()
+//! ).unwrap();
 //! 
-//!     let mut adhoc = AdhocCodec::new()
-//!         // compression-level 0 means 'lossless'
-//!         // while levels 1-10 does quantization + dithering (quality suffers)
-//!         .with_compression_level(7)
-//!         // AdhocCodec::with_info(.. ) MUST BE CALLED before calling 
-//!         // encode/decode when you are creating a new instance of AdhocCodec
-//!         .with_info(wav_reader.info());
+//! let mut adhoc = AdhocCodec::new()
+//!     // compression-level 0 means 'lossless'
+//!     // while levels 1-10 does quantization + dithering (quality suffers)
+//!     .with_compression_level(7)
+//!     // AdhocCodec::with_info(.. ) MUST BE CALLED before calling 
+//!     // encode/decode when you are creating a new instance of AdhocCodec
+//!     .with_info(wav_reader.info());
 //! 
-//!     //'decode' wav stream bit-by-bit
-//!     //Note:in this case we are just reading PCM info
-//!     while let Some(samples_read) = wav_reader.decode(&mut samples) {
-//!         //encode wav data bit-by-bit
-//!         //memory is allocated as needed
-//!         adhoc.encode(&samples[0..samples_read]);
-//!     }
+//! //'decode' wav stream bit-by-bit
+//! //Note:in this case we are just reading PCM info
+//! while let Some(samples_read) = wav_reader.decode(&mut samples).unwrap() {
+//!     //encode wav data bit-by-bit
+//!     //memory is allocated as needed
+//!     adhoc.encode(&samples[0..samples_read]).unwrap();
+//! }
 //! 
-//!     //write compressed audio back to disk
-//!     adhoc
-//!         .save_to(File::create("./resources/taunt.adhoc").unwrap())
-//!         .unwrap();
+//! //write compressed audio back to disk
+//! adhoc
+//!     .save_to(File::create("./resources/taunt.adhoc").unwrap())
+//!     .unwrap();
 //! 
-//!     println!("taunt.adhoc written to: ./resources");
-//! }
+//! println!("taunt.adhoc written to: ./resources");
 //! ```
 //! 
 //! 
@@ -65,10 +63,10 @@
 //!    let mut wav_writer = WavCodec::new(adhoc.info());
 //!
 //!    //decode adhoc stream a chunk of samples at a time
-//!    while let Some(samples_read) = adhoc.decode(&mut samples) {
+//!    while let Some(samples_read) = adhoc.decode(&mut samples).unwrap() {
 //!        //encode wav data bit-by-bit
 //!        //memory is allocated as needed
-//!        wav_writer.encode(&samples[0..samples_read]);
+//!        wav_writer.encode(&samples[0..samples_read]).unwrap();
 //!    }
 //!
 //!    //write compressed audio back to disk
@@ -86,7 +84,15 @@
 /// all the audio stuff is in here 
 pub mod codec;
 mod collections;
+mod error;
 mod math;
 
-pub use codec::{adhoc::AdhocCodec, wav::WavCodec, StreamInfo, Streamable};
+pub use codec::{
+    adhoc::{
+        AdhocAsset, AdhocCodec, AdhocDecoder, AdhocReader, AdhocWriter, EncodeReport, FrameContext,
+    },
+    wav::{SampleFormat, WavCodec, WavReader, WavWriter},
+    StreamInfo, Streamable,
+};
+pub use error::Error;
 pub use std::io::SeekFrom;
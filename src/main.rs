@@ -1,5 +1,7 @@
 #[cfg(feature = "cli")]
-use adhoc_audio::{AdhocCodec, Streamable, WavCodec};
+use adhoc_audio::{
+    codec::adhoc::DEFAULT_SEGMENT_SIZE, AdhocCodec, AdhocWriter, Error, Streamable, WavCodec,
+};
 
 #[cfg(feature = "cli")]
 use clap::{App, Arg};
@@ -10,6 +12,7 @@ use rayon::prelude::*;
 #[cfg(feature = "cli")]
 use std::{
     fs::File,
+    io::BufWriter,
     path::{Path, PathBuf},
 };
 
@@ -45,6 +48,12 @@ pub fn cli() {
                 .help("specifies output directory")
                 // .index(2),
         )
+        .arg(
+            Arg::with_name("parallel")
+                .short("p")
+                .long("parallel")
+                .help("splits every wav file into segments that get encoded on all cores, files are converted one at a time")
+        )
         .arg(
             Arg::with_name("compression_level")
                 .short("c")
@@ -65,7 +74,7 @@ pub fn cli() {
 
     let output_directory = m.value_of("output").unwrap_or("./");
 
-    if Path::new(output_directory).is_dir() == false {
+    if !Path::new(output_directory).is_dir() {
         eprintln!("directory specified is not valid!");
         return;
     }
@@ -80,42 +89,55 @@ pub fn cli() {
         .and_then(|val| val.parse::<u32>().ok())
         .unwrap_or(5);
 
+    let is_parallel = m.is_present("parallel");
 
     // println!("input files {:?}",input_files);
     // println!("output_directory: {:?}",output_directory);
 
-    input_files.par_iter().for_each(|file_path| {
+    let convert = |file_path: &&str| {
         let input: &Path = file_path.as_ref();
         let output_dir: &Path = output_directory.as_ref();
-        convert_file(input, output_dir, compression_level);
-    });
+        if let Err(err) = convert_file(input, output_dir, compression_level, is_parallel) {
+            eprintln!("failed to convert {}: {}", input.display(), err);
+        }
+    };
+    if is_parallel {
+        // each file already gets the whole pool and a buffer per thread, so one file at a time
+        input_files.iter().for_each(convert);
+    } else {
+        input_files.par_iter().for_each(convert);
+    }
 }
 
-pub fn convert_file(input: &Path, output_dir: &Path, compression_level: u32) -> Option<()> {
-    let input_ext = input.extension()?.to_str()?;
+pub fn convert_file(
+    input: &Path,
+    output_dir: &Path,
+    compression_level: u32,
+    is_parallel: bool,
+) -> Result<(), Error> {
+    let input_ext = match input.extension().and_then(|ext| ext.to_str()) {
+        Some(ext) => ext,
+        None => return Ok(()),
+    };
     if input_ext.contains("wav") {
-        convert_wav_to_adhoc(input, output_dir, compression_level)?;
+        convert_wav_to_adhoc(input, output_dir, compression_level, is_parallel)?;
     }
     if input_ext.contains("adhoc") {
         convert_adhoc_to_wav(input, output_dir)?;
     }
-    Some(())
+    Ok(())
 }
 
-fn convert_wav_to_adhoc(input: &Path, output_dir: &Path, compression_level: u32) -> Option<()> {
-    let file = File::open(input).ok()?;
-    let file_name = input.file_stem()?;
-
-    let mut parsed_wav = WavCodec::load(file).ok()?;
-
-    let mut compressed_wav = AdhocCodec::new()
-        .with_compression_level(compression_level)
-        .with_info(parsed_wav.info());
+fn convert_wav_to_adhoc(
+    input: &Path,
+    output_dir: &Path,
+    compression_level: u32,
+    is_parallel: bool,
+) -> Result<(), Error> {
+    let file = File::open(input)?;
+    let file_name = input.file_stem().unwrap_or_default();
 
-    let mut buffer = [0.0; 1024];
-    while let Some(samples_read) = parsed_wav.decode(&mut buffer[..]) {
-        compressed_wav.encode(&buffer[0..samples_read]);
-    }
+    let mut parsed_wav = WavCodec::load(file)?;
 
     let mut file_dest = PathBuf::from(output_dir);
     // println!("dest = {:?}",file_dest);
@@ -127,28 +149,41 @@ fn convert_wav_to_adhoc(input: &Path, output_dir: &Path, compression_level: u32)
     
     // println!("final dest = {:?}",file_dest);
 
-    compressed_wav.save_to(File::create(file_dest).ok()?);
+    // frames get written out as they are encoded so long recordings don't pile up in memory
+    let mut compressed_wav = AdhocWriter::new(BufWriter::new(File::create(file_dest)?))
+        .with_compression_level(compression_level)
+        .with_info(parsed_wav.info());
+
+    if is_parallel {
+        // enough audio to hand every thread a segment
+        let segment_len = DEFAULT_SEGMENT_SIZE * parsed_wav.info().channels();
+        let mut buffer = vec![0.0; segment_len * rayon::current_num_threads()];
+        while let Some(samples_read) = parsed_wav.decode(&mut buffer[..])? {
+            compressed_wav.encode_parallel(&buffer[0..samples_read])?;
+        }
+    } else {
+        let mut buffer = [0.0; 1024];
+        while let Some(samples_read) = parsed_wav.decode(&mut buffer[..])? {
+            compressed_wav.encode(&buffer[0..samples_read])?;
+        }
+    }
 
-    Some(())
+    compressed_wav.finalize()?;
+    Ok(())
 }
-fn convert_adhoc_to_wav(input: &Path, output_dir: &Path) -> Option<()> {
-    let file = File::open(input).ok()?;
-    let file_name = input.file_stem()?;
+fn convert_adhoc_to_wav(input: &Path, output_dir: &Path) -> Result<(), Error> {
+    let file = File::open(input)?;
+    let file_name = input.file_stem().unwrap_or_default();
 
-    let mut src_adhoc = AdhocCodec::load(file)?;
+    let src_adhoc = AdhocCodec::load(file)?;
     let mut dst_wav = WavCodec::new(src_adhoc.info());
 
-    let mut buffer = [0.0; 1024];
-
-    while let Some(samples_read) = src_adhoc.decode(&mut buffer[..]) {
-        dst_wav.encode(&buffer[0..samples_read]);
-    }
+    // the whole file ends up in memory anyway, so decode every frame-block at once
+    dst_wav.encode(&src_adhoc.decode_all_parallel()?)?;
 
     let mut file_dest = PathBuf::from(output_dir);
     file_dest.push(file_name);
     file_dest.set_extension("wav");
 
-    dst_wav.save_to(File::create(file_dest).ok()?).ok()?;
-
-    Some(())
+    dst_wav.save_to(File::create(file_dest)?)
 }
@@ -1,11 +1,11 @@
-pub mod signal;
+pub mod lpc;
+pub mod noise;
 pub mod parabola;
-pub mod noise; 
-
+pub mod signal;
 
-pub use signal::*; 
-pub use parabola::*; 
-pub use noise::*; 
+pub use lpc::*;
+pub use noise::*;
+pub use parabola::*;
 
 #[allow(dead_code)]
 pub fn compute_mse(a: &[f32], b: &[f32]) -> f32 {
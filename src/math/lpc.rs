@@ -0,0 +1,199 @@
+/// highest prediction order the encoder will consider
+pub const MAX_LPC_ORDER: usize = 32;
+
+/// number of bits (sign included) used to store a single quantized coefficient
+pub const LPC_COEF_PRECISION: u32 = 14;
+
+/// largest shift a quantized predictor is allowed to have (it has to fit in a nibble)
+pub const MAX_LPC_SHIFT: i32 = 15;
+
+/// # Description
+/// A linear predictor with quantized coefficients, the prediction for `x[n]` is: \
+/// `( coefs[0]*x[n-1] + coefs[1]*x[n-2] + ... + coefs[order-1]*x[n-order] ) >> shift`
+/// ## Comments
+/// - an `order` of `0` is a valid predictor, codecs use it to mean "use `FixedParabola` instead"
+/// - everything is done in integer arithmetic so encoder and decoder always agree
+#[derive(Copy, Clone, Default, PartialEq, Debug)]
+pub struct LinearPredictor {
+    pub order: u8,
+    pub shift: u8,
+    pub coefs: [i16; MAX_LPC_ORDER],
+}
+
+impl LinearPredictor {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn order(&self) -> usize {
+        self.order as usize
+    }
+
+    pub fn coefs(&self) -> &[i16] {
+        &self.coefs[0..self.order()]
+    }
+
+    /// # Description
+    /// quantizes floating point `coefs` down to `LPC_COEF_PRECISION` bits
+    /// ## Returns
+    /// `None` if the coefficients can't be represented (too large, all zero or too many of them)
+    pub fn quantize(coefs: &[f64]) -> Option<Self> {
+        if coefs.is_empty() || coefs.len() > MAX_LPC_ORDER {
+            return None;
+        }
+
+        let max_coef = coefs.iter().fold(0.0f64, |acc, c| acc.max(c.abs()));
+        if !max_coef.is_finite() || max_coef <= 0.0 {
+            return None;
+        }
+
+        //number of bits needed to the left of the binary point
+        let integer_bits = max_coef.log2().floor() as i32 + 1;
+        let shift = (LPC_COEF_PRECISION as i32 - 1 - integer_bits).min(MAX_LPC_SHIFT);
+        if shift < 0 {
+            return None;
+        }
+
+        let q_max = (1i32 << (LPC_COEF_PRECISION - 1)) - 1;
+        let q_min = -q_max - 1;
+        let scale = (1i64 << shift) as f64;
+
+        let mut predictor = Self {
+            order: coefs.len() as u8,
+            shift: shift as u8,
+            coefs: [0; MAX_LPC_ORDER],
+        };
+
+        //quantization error is carried over to the next coefficient
+        let mut error = 0.0;
+        for (q, &c) in predictor.coefs.iter_mut().zip(coefs.iter()) {
+            let scaled = c * scale + error;
+            let rounded = (scaled.round() as i32).clamp(q_min, q_max);
+            error = scaled - rounded as f64;
+            *q = rounded as i16;
+        }
+
+        Some(predictor)
+    }
+
+    /// # Description
+    /// predicts the sample that comes right after `history`
+    /// ## Comments
+    /// - `history` is expected to have at least `order` samples, the last element being the most recent one
+    pub fn predict(&self, history: &[i32]) -> i64 {
+        let len = history.len();
+        let sum = self.coefs().iter().enumerate().fold(0i64, |acc, (j, &c)| {
+            acc + c as i64 * history[len - 1 - j] as i64
+        });
+        sum >> self.shift
+    }
+}
+
+/// # Description
+/// applies a welch window to `samples` then writes the autocorrelation for lags `0..autoc.len()` into `autoc`
+pub fn compute_autocorrelation(samples: &[f64], autoc: &mut [f64]) {
+    let n = samples.len();
+    let half = (n as f64 - 1.0) * 0.5;
+    let windowed = samples
+        .iter()
+        .enumerate()
+        .map(|(i, &s)| {
+            let t = (i as f64 - half) / (half + 1.0);
+            s * (1.0 - t * t)
+        })
+        .collect::<Vec<_>>();
+
+    for (lag, a) in autoc.iter_mut().enumerate() {
+        *a = if lag < n {
+            windowed[lag..]
+                .iter()
+                .zip(windowed.iter())
+                .map(|(x, y)| x * y)
+                .sum()
+        } else {
+            0.0
+        };
+    }
+}
+
+/// # Description
+/// solves for the predictors of every order from `1` up to `autoc.len()-1` using levinson-durbin recursion
+/// ## Returns
+/// a list where element `k` is the tuple `(coefficients of order k+1, prediction error of order k+1)`
+/// ## Comments
+/// - the list is cut short when the recursion becomes unstable (silence, numerical issues, etc)
+pub fn levinson_durbin(autoc: &[f64]) -> Vec<(Vec<f64>, f64)> {
+    let max_order = autoc.len().saturating_sub(1).min(MAX_LPC_ORDER);
+    let mut solutions = Vec::with_capacity(max_order);
+    let mut coefs = [0.0f64; MAX_LPC_ORDER];
+    let mut error = autoc.first().copied().unwrap_or(0.0);
+
+    for m in 0..max_order {
+        if error <= 0.0 || !error.is_finite() {
+            break;
+        }
+
+        let mut k = autoc[m + 1];
+        for j in 0..m {
+            k -= coefs[j] * autoc[m - j];
+        }
+        k /= error;
+
+        if !k.is_finite() {
+            break;
+        }
+
+        let prev = coefs;
+        for j in 0..m {
+            coefs[j] = prev[j] - k * prev[m - 1 - j];
+        }
+        coefs[m] = k;
+        error *= 1.0 - k * k;
+
+        solutions.push((coefs[0..=m].to_vec(), error.max(0.0)));
+    }
+
+    solutions
+}
+
+mod tests {
+    #[allow(unused_imports)]
+    use super::*;
+
+    #[test]
+    fn sinusoid_is_predictable() {
+        let samples = (0..1024)
+            .map(|k| ((k as f64 * 0.05).sin() * 10_000.0).round())
+            .collect::<Vec<_>>();
+
+        let mut autoc = [0.0; 9];
+        compute_autocorrelation(&samples, &mut autoc);
+        let solutions = levinson_durbin(&autoc);
+        assert_eq!(solutions.len(), 8);
+
+        // a pure tone is (almost) perfectly described by a 2nd order predictor
+        let (coefs, _) = &solutions[1];
+        let predictor = LinearPredictor::quantize(coefs).expect("quantization failed");
+        let history = samples.iter().map(|&s| s as i32).collect::<Vec<_>>();
+
+        let max_error = (2..history.len())
+            .map(|n| (predictor.predict(&history[0..n]) - history[n] as i64).abs())
+            .max()
+            .unwrap_or_default();
+
+        assert!(max_error < 64, "prediction error too large: {}", max_error);
+    }
+
+    #[test]
+    fn quantize_rejects_degenerate_coefs() {
+        assert_eq!(LinearPredictor::quantize(&[]), None);
+        assert_eq!(LinearPredictor::quantize(&[0.0, 0.0]), None);
+        assert_eq!(LinearPredictor::quantize(&[f64::NAN]), None);
+
+        let predictor = LinearPredictor::quantize(&[1.5, -0.5]).unwrap();
+        assert_eq!(predictor.order(), 2);
+        let scale = (1 << predictor.shift) as f64;
+        assert!((predictor.coefs[0] as f64 / scale - 1.5).abs() < 0.001);
+        assert!((predictor.coefs[1] as f64 / scale + 0.5).abs() < 0.001);
+    }
+}
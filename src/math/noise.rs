@@ -19,7 +19,7 @@ impl PseudoRandom {
     pub fn triangle(&mut self) -> impl Iterator<Item = f32> + '_ {
         (0u64..).map(|_| {
             //uniform dist range -1..0
-            let a = ((self.rand() & 16383) as f32 / 16384.0) * -1.0;
+            let a = -((self.rand() & 16383) as f32 / 16384.0);
             //uniform dist range 0..1
             let b = ((self.rand() & 16383) as f32 / 16384.0) * 1.0;
             // adding results in triangle distribution
@@ -81,8 +81,8 @@ impl<const N: usize> fmt::Display for NoiseDistribution<f32, N> {
             frequencies[idx.clamp(0, frequencies.len() - 1)] += 1;
         });
 
-        for k in 0..frequencies.len() {
-            let stars = (frequencies[k] * 300) / total;
+        for (k, &frequency) in frequencies.iter().enumerate() {
+            let stars = (frequency * 300) / total;
             let idx = k as f32;
 
             let lbound = idx * DELTA * 2.0 - 1.0;
@@ -91,7 +91,7 @@ impl<const N: usize> fmt::Display for NoiseDistribution<f32, N> {
             for _ in 0..stars {
                 write!(f, "*")?;
             }
-            write!(f, "\n")?;
+            writeln!(f)?;
         }
 
         Ok(())
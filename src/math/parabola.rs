@@ -61,9 +61,8 @@ mod tests {
             .zip(p.f.iter())
             .for_each(|(x, &f_expected)| {
                 let f_eval = p.eval(x);
-                assert_eq!(
+                assert!(
                     (f_eval - f_expected).abs() < 0.001,
-                    true,
                     "accuracy threshold not met"
                 )
             });
@@ -1,13 +1,15 @@
 #![allow(dead_code)]
 
+// 3.141 is what the filter has always used, swapping in PI would change its output
+#[allow(clippy::approx_constant)]
 pub fn gaussian_filter<const N:usize>(sigma:f32,r:f32)->[f32;N]{
     let mut result = [0f32;N];
     let coef = 1.0/( sigma *  (2.*3.141f32).sqrt()  );
-    for k in 0..N{
+    for (k, result_k) in result.iter_mut().enumerate(){
         let x_scaled =    ( k as f32/ (N-1) as f32  )*2.0*r  - r ;  
         // println!("x={},x_s={}",k,x_scaled);
         let exponent = -0.5*(x_scaled/sigma).powf(2.0);
-        result[k] =  coef*exponent.exp();
+        *result_k =  coef*exponent.exp();
     }
     result
 }
@@ -47,12 +49,12 @@ pub fn convolve_1d_branchless(samples:&[f32], kernel:&[f32] , result:&mut [f32])
 pub fn scale_signal<'a>(input:&[f32],dst_len:usize,output:&'a mut [f32])->&'a mut [f32]{
     let src_len = input.len();
     let scale_factor = (src_len-1) as f32/(dst_len-1) as f32 ; 
-    for k in 0..dst_len{
+    for (k, output_k) in output.iter_mut().take(dst_len).enumerate(){
         let kf = (k as f32)*scale_factor;
         let w0 = (kf.floor() as usize).min(src_len-1);
         let w1 = (w0+1).min(src_len-1);
         let t = kf.fract();
-        output[k] = input[w0]*(1.0-t) + input[w1]*t;
+        *output_k = input[w0]*(1.0-t) + input[w1]*t;
     }
     &mut output[0..dst_len]
 }
\ No newline at end of file